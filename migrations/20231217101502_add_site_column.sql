@@ -0,0 +1,4 @@
+ALTER TABLE pages ADD COLUMN site TEXT NOT NULL DEFAULT '';
+UPDATE pages
+SET site = COALESCE(substring(url FROM '^[A-Za-z][A-Za-z0-9+.-]*://([^/:?#]+)'), '');
+CREATE INDEX pages_site_idx ON pages (site);
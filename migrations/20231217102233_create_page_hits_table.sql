@@ -0,0 +1,10 @@
+CREATE TABLE page_hits(
+hit_id bigserial NOT NULL,
+PRIMARY KEY (hit_id),
+page_id uuid NOT NULL REFERENCES pages (page_id),
+timestamp bigint NOT NULL,
+utm_source TEXT NULL,
+utm_medium TEXT NULL,
+utm_campaign TEXT NULL
+);
+CREATE INDEX page_hits_page_id_timestamp_idx ON page_hits (page_id, timestamp);
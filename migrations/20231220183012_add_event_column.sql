@@ -0,0 +1,3 @@
+-- Hits with an event name are events such as `read-to-end`.
+-- Plain page hits don't have an event.
+ALTER TABLE page_hits ADD COLUMN event TEXT NULL;
@@ -0,0 +1,6 @@
+CREATE TABLE owners(
+owner_id uuid NOT NULL,
+PRIMARY KEY (owner_id),
+-- SHA-256 of the API key, hex encoded.
+api_key_hash TEXT NOT NULL UNIQUE
+);
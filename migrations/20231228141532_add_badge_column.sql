@@ -0,0 +1,2 @@
+-- Badges are opt-in, so they are disabled by default.
+ALTER TABLE pages ADD COLUMN badge BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,5 @@
+-- Dashboards are private unless the owner makes them public
+-- or hands out the secret link.
+ALTER TABLE pages ADD COLUMN public BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE pages ADD COLUMN share_secret TEXT NULL;
+ALTER TABLE page_hits ADD COLUMN referrer TEXT NULL;
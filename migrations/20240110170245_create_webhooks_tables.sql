@@ -0,0 +1,29 @@
+CREATE TABLE webhooks(
+webhook_id uuid NOT NULL,
+PRIMARY KEY (webhook_id),
+page_id uuid NOT NULL REFERENCES pages (page_id) ON DELETE CASCADE,
+url TEXT NOT NULL,
+-- Key of the HMAC that signs the deliveries.
+secret TEXT NOT NULL,
+-- Notify when the hits reach 1k, 10k, 100k, ...
+milestones BOOLEAN NOT NULL,
+-- Highest milestone that has been notified about.
+last_milestone bigint NOT NULL DEFAULT 0,
+-- Notify when the page gets spike_hits hits within spike_minutes.
+spike_hits INTEGER NULL,
+spike_minutes INTEGER NULL,
+-- Unix time of the last spike that has been notified about.
+last_spike_at bigint NULL
+);
+CREATE INDEX webhooks_page_id_idx ON webhooks (page_id);
+
+-- Deliveries that are yet to succeed.
+CREATE TABLE webhook_deliveries(
+delivery_id bigserial NOT NULL,
+PRIMARY KEY (delivery_id),
+webhook_id uuid NOT NULL REFERENCES webhooks (webhook_id) ON DELETE CASCADE,
+payload TEXT NOT NULL,
+attempts INTEGER NOT NULL DEFAULT 0,
+next_attempt_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE INDEX webhook_deliveries_next_attempt_at_idx ON webhook_deliveries (next_attempt_at);
@@ -0,0 +1,3 @@
+-- Descriptive metadata that's given when a page is registered.
+ALTER TABLE pages ADD COLUMN title TEXT NULL;
+ALTER TABLE pages ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}';
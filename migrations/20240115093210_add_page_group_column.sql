@@ -0,0 +1,3 @@
+-- Section of a site that a page belongs to. `group` is a keyword.
+ALTER TABLE pages ADD COLUMN page_group TEXT NULL;
+CREATE INDEX pages_tags_idx ON pages USING GIN (tags);
@@ -0,0 +1,37 @@
+-- Dimensions of hits that aren't in the referrer.
+ALTER TABLE page_hits ADD COLUMN country TEXT NULL;
+ALTER TABLE page_hits ADD COLUMN device TEXT NULL;
+
+-- Hits per page, dimension and value, summed up per hour and
+-- per day. Buckets are the Unix time at which they start.
+CREATE TABLE hits_hourly(
+page_id uuid NOT NULL REFERENCES pages (page_id) ON DELETE CASCADE,
+dimension TEXT NOT NULL,
+value TEXT NOT NULL,
+hour bigint NOT NULL,
+hits bigint NOT NULL,
+PRIMARY KEY (page_id, dimension, hour, value)
+);
+CREATE TABLE hits_daily(
+page_id uuid NOT NULL REFERENCES pages (page_id) ON DELETE CASCADE,
+dimension TEXT NOT NULL,
+value TEXT NOT NULL,
+day bigint NOT NULL,
+hits bigint NOT NULL,
+PRIMARY KEY (page_id, dimension, day, value)
+);
+
+-- Roll up the hits that were recorded so far.
+INSERT INTO hits_hourly (page_id, dimension, value, hour, hits)
+SELECT h.page_id, d.dimension, d.value, h.timestamp - h.timestamp % 3600, COUNT(*)
+FROM page_hits h,
+     LATERAL (VALUES ('total', CASE WHEN h.event IS NULL THEN '' END),
+                     ('event', h.event),
+                     ('referrer', CASE WHEN h.event IS NULL THEN h.referrer END))
+     AS d (dimension, value)
+WHERE d.value IS NOT NULL
+GROUP BY 1, 2, 3, 4;
+INSERT INTO hits_daily (page_id, dimension, value, day, hits)
+SELECT page_id, dimension, value, hour - hour % 86400, SUM(hits)
+FROM hits_hourly
+GROUP BY 1, 2, 3, 4;
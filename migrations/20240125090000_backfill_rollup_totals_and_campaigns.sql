@@ -0,0 +1,36 @@
+-- Hits from before page_hits only have a timestamp in
+-- pages.timestamps, so recount the totals from both. Hits that
+-- are in both only count once.
+DELETE FROM hits_hourly WHERE dimension = 'total';
+DELETE FROM hits_daily WHERE dimension = 'total';
+WITH recorded AS (
+    SELECT page_id, timestamp, COUNT(*) AS n
+    FROM page_hits
+    WHERE event IS NULL
+    GROUP BY 1, 2
+), stamped AS (
+    SELECT p.page_id, t.timestamp, COUNT(*) AS n
+    FROM pages p, UNNEST(p.timestamps) AS t (timestamp)
+    GROUP BY 1, 2
+)
+INSERT INTO hits_hourly (page_id, dimension, value, hour, hits)
+SELECT page_id, 'total', '', timestamp - timestamp % 3600, SUM(GREATEST(r.n, s.n))::bigint
+FROM recorded r
+FULL JOIN stamped s USING (page_id, timestamp)
+GROUP BY 1, 4;
+
+-- Campaigns of plain hits, as JSON arrays of the source,
+-- medium and campaign.
+INSERT INTO hits_hourly (page_id, dimension, value, hour, hits)
+SELECT page_id, 'campaign', jsonb_build_array(utm_source, utm_medium, utm_campaign)::text,
+       timestamp - timestamp % 3600, COUNT(*)
+FROM page_hits
+WHERE event IS NULL
+  AND (utm_source IS NOT NULL OR utm_medium IS NOT NULL OR utm_campaign IS NOT NULL)
+GROUP BY 1, 2, 3, 4;
+
+INSERT INTO hits_daily (page_id, dimension, value, day, hits)
+SELECT page_id, dimension, value, hour - hour % 86400, SUM(hits)
+FROM hits_hourly
+WHERE dimension IN ('total', 'campaign')
+GROUP BY 1, 2, 3, 4;
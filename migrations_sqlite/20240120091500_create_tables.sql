@@ -0,0 +1,31 @@
+-- Schema of the sqlite storage backend, which only has what
+-- the page and visitor stores need. IDs are stored as text.
+CREATE TABLE owners(
+owner_id TEXT NOT NULL PRIMARY KEY,
+-- SHA-256 of the API key, hex encoded.
+api_key_hash TEXT NOT NULL UNIQUE
+);
+CREATE TABLE pages(
+page_id TEXT NOT NULL PRIMARY KEY,
+owner TEXT NOT NULL,
+url TEXT NOT NULL UNIQUE,
+site TEXT NOT NULL,
+title TEXT NULL,
+-- JSON array of strings.
+tags TEXT NOT NULL DEFAULT '[]',
+page_group TEXT NULL,
+hits INTEGER NOT NULL DEFAULT 0
+);
+CREATE TABLE page_hits(
+hit_id INTEGER NOT NULL PRIMARY KEY,
+page_id TEXT NOT NULL REFERENCES pages (page_id),
+timestamp INTEGER NOT NULL,
+event TEXT NULL,
+utm_source TEXT NULL,
+utm_medium TEXT NULL,
+utm_campaign TEXT NULL,
+referrer TEXT NULL,
+country TEXT NULL,
+device TEXT NULL
+);
+CREATE INDEX page_hits_page_id_timestamp_idx ON page_hits (page_id, timestamp);
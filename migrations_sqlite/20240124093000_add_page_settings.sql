@@ -0,0 +1,5 @@
+-- Settings of the pages that the page store can change.
+-- Booleans are stored as 0 or 1.
+ALTER TABLE pages ADD COLUMN badge INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE pages ADD COLUMN public INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE pages ADD COLUMN share_secret TEXT NULL;
@@ -0,0 +1,116 @@
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use actix_web::http::{header, StatusCode};
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use crate::storage::PageStore;
+use crate::utils::error_chain_fmt;
+
+#[derive(thiserror::Error)]
+pub enum AuthError {
+    #[error("Invalid API key")]
+    InvalidCredentials(#[source] anyhow::Error),
+    #[error("Page not found")]
+    NotOwner,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+	error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+	match self {
+	    AuthError::InvalidCredentials(_) => StatusCode::UNAUTHORIZED,
+	    // Pages of other owners are hidden entirely.
+	    AuthError::NotOwner => StatusCode::NOT_FOUND,
+	    AuthError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+	}
+    }
+
+    fn error_response(&self) -> HttpResponse {
+	let mut response = HttpResponse::build(self.status_code());
+	if let AuthError::InvalidCredentials(_) = self {
+	    response.insert_header((header::WWW_AUTHENTICATE, "Bearer"));
+	}
+	response.body(self.to_string())
+    }
+}
+
+/// Create a new owner. Returns the owner's ID and API key.
+///
+/// Only a hash of the API key is stored, so it can't
+/// be recovered if it's lost.
+#[tracing::instrument(
+    name = "Create owner",
+    skip(pages)
+)]
+pub async fn create_owner(pages: &dyn PageStore) -> anyhow::Result<(Uuid, String)> {
+    let owner_id = Uuid::new_v4();
+    let api_key = format!("jhm_{}", Uuid::new_v4().simple());
+    pages.insert_owner(owner_id, &hash_api_key(&api_key)).await?;
+    Ok((owner_id, api_key))
+}
+
+/// Look up the owner of the API key in the `Authorization` header.
+///
+/// Returns `None` if the request doesn't carry an API key.
+#[tracing::instrument(
+    name = "Authenticate owner",
+    skip(req, pages)
+)]
+pub async fn try_authenticate(
+    req: &HttpRequest,
+    pages: &dyn PageStore,
+) -> Result<Option<Uuid>, AuthError> {
+    let Some(header) = req.headers().get(header::AUTHORIZATION) else {
+	return Ok(None);
+    };
+    let api_key = header
+	.to_str()
+	.context("The 'Authorization' header is not a valid UTF-8 string")
+	.and_then(|header| header
+		  .strip_prefix("Bearer ")
+		  .context("The authorization scheme is not 'Bearer'"))
+	.map_err(AuthError::InvalidCredentials)?;
+
+    match pages.owner_of_api_key(&hash_api_key(api_key.trim())).await? {
+	Some(owner_id) => Ok(Some(owner_id)),
+	None => Err(AuthError::InvalidCredentials(anyhow::anyhow!("Unknown API key"))),
+    }
+}
+
+/// Like [`try_authenticate`], but the API key is required.
+pub async fn authenticate(
+    req: &HttpRequest,
+    pages: &dyn PageStore,
+) -> Result<Uuid, AuthError> {
+    try_authenticate(req, pages)
+	.await?
+	.ok_or_else(|| AuthError::InvalidCredentials(
+	    anyhow::anyhow!("Missing 'Authorization' header")))
+}
+
+/// Make sure that the page exists and belongs to the owner.
+#[tracing::instrument(
+    name = "Authorize page access",
+    skip(pages)
+)]
+pub async fn authorize_page(
+    owner_id: Uuid,
+    page_id: Uuid,
+    pages: &dyn PageStore,
+) -> Result<(), AuthError> {
+    match pages.owner_of_page(page_id).await? {
+	Some(owner) if owner == owner_id => Ok(()),
+	_ => Err(AuthError::NotOwner),
+    }
+}
+
+fn hash_api_key(api_key: &str) -> String {
+    hex::encode(Sha256::digest(api_key.as_bytes()))
+}
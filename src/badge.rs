@@ -0,0 +1,97 @@
+use crate::utils::escape_html;
+
+/// Look of a badge. These follow the styles of shields.io.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[derive(serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Style {
+    #[default]
+    Flat,
+    FlatSquare,
+    Plastic,
+}
+
+/// Colour of the value side of a badge, as a hex code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Color(String);
+
+impl Color {
+    /// Parse a named colour like `brightgreen` or a hex code
+    /// like `4c1` or `#44cc11`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+	let named = match s {
+	    "brightgreen" => Some("#4c1"),
+	    "green" => Some("#97ca00"),
+	    "yellowgreen" => Some("#a4a61d"),
+	    "yellow" => Some("#dfb317"),
+	    "orange" => Some("#fe7d37"),
+	    "red" => Some("#e05d44"),
+	    "blue" => Some("#007ec6"),
+	    "grey" | "gray" => Some("#555"),
+	    "lightgrey" | "lightgray" => Some("#9f9f9f"),
+	    _ => None,
+	};
+	if let Some(hex) = named {
+	    return Ok(Self(hex.to_string()));
+	}
+	let hex = s.strip_prefix('#').unwrap_or(s);
+	if matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+	    Ok(Self(format!("#{hex}")))
+	} else {
+	    Err(format!("{s:?} is neither a named colour nor a hex code."))
+	}
+    }
+}
+
+impl Default for Color {
+    fn default() -> Self {
+	Self("#007ec6".to_string())
+    }
+}
+
+/// Format a count the way badges show it, e.g. `1.2k`.
+pub fn format_count(n: i64) -> String {
+    const UNITS: [(i64, &str); 3] = [
+	(1_000_000_000, "G"),
+	(1_000_000, "M"),
+	(1_000, "k"),
+    ];
+    for (size, unit) in UNITS {
+	if n >= size {
+	    let scaled = n as f64 / size as f64;
+	    return if scaled < 10.0 {
+		format!("{:.1}{unit}", (scaled * 10.0).floor() / 10.0)
+	    } else {
+		format!("{}{unit}", scaled.floor())
+	    };
+	}
+    }
+    n.to_string()
+}
+
+/// Render a badge that shows `label` on the left and `value`
+/// on the right.
+pub fn render(label: &str, value: &str, color: &Color, style: Style) -> String {
+    let label_width = text_width(label);
+    let value_width = text_width(value);
+    let width = label_width + value_width;
+    let (height, radius, gradient) = match style {
+	Style::Flat => (20, 3, r##"<stop offset="0" stop-color="#bbb" stop-opacity=".1"/><stop offset="1" stop-opacity=".1"/>"##),
+	Style::FlatSquare => (20, 0, ""),
+	Style::Plastic => (18, 4, r##"<stop offset="0" stop-color="#fff" stop-opacity=".7"/><stop offset=".1" stop-color="#aaa" stop-opacity=".1"/><stop offset=".9" stop-opacity=".3"/><stop offset="1" stop-opacity=".5"/>"##),
+    };
+    let text_y = height - 6;
+    let label = escape_html(label);
+    let value = escape_html(value);
+    let color = &color.0;
+    format!(
+	r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" role="img" aria-label="{label}: {value}"><title>{label}: {value}</title><linearGradient id="s" x2="0" y2="100%">{gradient}</linearGradient><clipPath id="r"><rect width="{width}" height="{height}" rx="{radius}" fill="#fff"/></clipPath><g clip-path="url(#r)"><rect width="{label_width}" height="{height}" fill="#555"/><rect x="{label_width}" width="{value_width}" height="{height}" fill="{color}"/><rect width="{width}" height="{height}" fill="url(#s)"/></g><g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11"><text x="{}" y="{text_y}">{label}</text><text x="{}" y="{text_y}">{value}</text></g></svg>"##,
+	label_width / 2,
+	label_width + value_width / 2,
+    )
+}
+
+/// Rough width of the text in Verdana 11px, plus padding.
+fn text_width(s: &str) -> usize {
+    s.chars().count() * 7 + 10
+}
@@ -0,0 +1,83 @@
+use std::io::BufRead;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Collect the URLs from the command line and, if given, from
+/// a file with one URL per line. `-` reads the file from stdin.
+/// Empty lines and lines starting with `#` are skipped.
+pub fn read_urls(mut urls: Vec<String>, file: Option<&Path>) -> anyhow::Result<Vec<String>> {
+    let Some(file) = file else {
+	return Ok(urls);
+    };
+    let lines: Vec<String> = if file == Path::new("-") {
+	std::io::stdin().lock().lines().collect::<Result<_, _>>()
+	    .map_err(|e| anyhow::anyhow!("Failed to read URLs from stdin: {e}"))?
+    } else {
+	let reader = std::fs::File::open(file)
+	    .map_err(|e| anyhow::anyhow!("Failed to open {}: {e}", file.display()))?;
+	std::io::BufReader::new(reader).lines().collect::<Result<_, _>>()
+	    .map_err(|e| anyhow::anyhow!("Failed to read URLs from {}: {e}", file.display()))?
+    };
+    urls.extend(lines
+		.iter()
+		.map(|line| line.trim())
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.map(str::to_string));
+    Ok(urls)
+}
+
+/// Run `f` on all items, with at most `jobs` of them running
+/// at the same time. The results are in the order of the items.
+pub fn run_bounded<T, R>(
+    items: &[T],
+    jobs: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+{
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+    std::thread::scope(|scope| {
+	for _ in 0..jobs.clamp(1, items.len().max(1)) {
+	    scope.spawn(|| loop {
+		let i = next.fetch_add(1, Ordering::Relaxed);
+		let Some(item) = items.get(i) else {
+		    break;
+		};
+		let result = f(item);
+		results.lock().expect("Lock is poisoned")[i] = Some(result);
+	    });
+	}
+    });
+    results
+	.into_inner()
+	.expect("Lock is poisoned")
+	.into_iter()
+	.map(|result| result.expect("Every item has been run"))
+	.collect()
+}
+
+/// Print the rows as a table with aligned columns.
+pub fn print_table(header: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+	for (width, cell) in widths.iter_mut().zip(row) {
+	    *width = (*width).max(cell.chars().count());
+	}
+    }
+    let print_row = |cells: Vec<&str>| {
+	let line: Vec<String> = cells
+	    .iter()
+	    .zip(&widths)
+	    .map(|(cell, width)| format!("{cell:<width$}"))
+	    .collect();
+	println!("{}", line.join("  ").trim_end());
+    };
+    print_row(header.to_vec());
+    for row in rows {
+	print_row(row.iter().map(String::as_str).collect());
+    }
+}
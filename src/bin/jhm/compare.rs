@@ -0,0 +1,76 @@
+use anyhow::{bail, Context};
+use chrono::{Days, NaiveDate, NaiveTime, Utc};
+use jhm::routes::{Comparison, PageComparison, TimeRange};
+use super::{csv_field, print_table, OutputFormat};
+
+/// Time range of the days from `since` to `until`, both
+/// included.
+fn days(since: NaiveDate, until: NaiveDate) -> TimeRange {
+    let start = |date: NaiveDate| date.and_time(NaiveTime::MIN).and_utc().timestamp();
+    TimeRange { since: start(since), until: start(until + Days::new(1)) }
+}
+
+/// The days to compare, and the days to compare them to. By
+/// default, the last 7 days up to today, and as many days
+/// right before them.
+pub fn ranges(
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+    previous_since: Option<NaiveDate>,
+    previous_until: Option<NaiveDate>,
+) -> anyhow::Result<(TimeRange, TimeRange)> {
+    let until = until.unwrap_or_else(|| Utc::now().date_naive());
+    let since = since.unwrap_or(until - Days::new(6));
+    if since > until {
+	bail!("--since {since} is after --until {until}");
+    }
+    let len = Days::new((until - since).num_days() as u64);
+    let previous_until = previous_until.unwrap_or(since - Days::new(1));
+    let previous_since = previous_since.unwrap_or(previous_until - len);
+    if previous_since > previous_until {
+	bail!("--previous-since {previous_since} is after --previous-until {previous_until}");
+    }
+    Ok((days(since, until), days(previous_since, previous_until)))
+}
+
+/// Change from the previous range, e.g. `+20.0%`.
+fn change_percent(page: &PageComparison) -> String {
+    match page.change_percent {
+	Some(percent) => format!("{percent:+.1}%"),
+	None if page.hits > 0 => "new".to_string(),
+	None => "–".to_string(),
+    }
+}
+
+pub fn print(comparison: &Comparison, format: Option<OutputFormat>) -> anyhow::Result<()> {
+    match format {
+	None | Some(OutputFormat::Table) => {
+	    let rows: Vec<Vec<String>> = comparison.pages.iter()
+		.map(|page| vec![
+		    page.rank.to_string(),
+		    page.url.clone(),
+		    page.hits.to_string(),
+		    page.previous_hits.to_string(),
+		    format!("{:+}", page.change),
+		    change_percent(page),
+		    page.previous_rank.to_string(),
+		])
+		.collect();
+	    print_table(&["rank", "url", "hits", "previous", "change", "%", "previous rank"], &rows);
+	},
+	Some(OutputFormat::Csv) => {
+	    println!("rank,url,title,hits,previous_hits,change,change_percent,previous_rank");
+	    for page in &comparison.pages {
+		let percent = page.change_percent.map(|p| p.to_string()).unwrap_or_default();
+		println!("{},{},{},{},{},{},{},{}", page.rank, csv_field(&page.url),
+			 csv_field(page.title.as_deref().unwrap_or_default()), page.hits,
+			 page.previous_hits, page.change, percent, page.previous_rank);
+	    }
+	},
+	Some(OutputFormat::Json) => {
+	    println!("{}", serde_json::to_string_pretty(comparison)
+		     .context("Failed to encode comparison")?);
+	},
+    }
+    Ok(())
+}
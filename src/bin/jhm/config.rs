@@ -0,0 +1,182 @@
+use anyhow::Context;
+use clap::{Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+use url::Url;
+
+use crate::OutputFormat;
+
+/// Profile that's used if none has been chosen.
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// Set a value of the profile. An empty value removes it.
+    Set {
+	setting: Setting,
+	value: String,
+    },
+    /// Print a value of the profile, or all of them.
+    Get {
+	setting: Option<Setting>,
+    },
+    /// Use the profile from now on, unless `--profile` says otherwise.
+    Use {
+	profile: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Setting {
+    /// Address of the JHM service.
+    Service,
+    /// API key of the owner of the pages.
+    ApiKey,
+    /// Output format of `jhm hits` and `jhm import-sitemap`.
+    Format,
+}
+
+impl Setting {
+    fn name(&self) -> &'static str {
+	match self {
+	    Setting::Service => "service",
+	    Setting::ApiKey => "api-key",
+	    Setting::Format => "format",
+	}
+    }
+}
+
+/// Contents of the config file.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Config {
+    /// Profile that's used without `--profile`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// Settings of one instance of the service.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Profile {
+    pub service: Option<Url>,
+    pub api_key: Option<String>,
+    pub format: Option<OutputFormat>,
+}
+
+impl Config {
+    /// `$JHM_CONFIG`, or `jhm/config.toml` in the XDG config
+    /// directory.
+    pub fn path() -> anyhow::Result<PathBuf> {
+	if let Some(path) = std::env::var_os("JHM_CONFIG") {
+	    return Ok(path.into());
+	}
+	let config_home = std::env::var_os("XDG_CONFIG_HOME")
+	    .map(PathBuf::from)
+	    .filter(|path| path.is_absolute())
+	    .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+	    .context("Neither XDG_CONFIG_HOME nor HOME is set")?;
+	Ok(config_home.join("jhm").join("config.toml"))
+    }
+
+    /// Read the config file. A missing file is an empty config.
+    pub fn load() -> anyhow::Result<Self> {
+	let path = Self::path()?;
+	match std::fs::read_to_string(&path) {
+	    Ok(s) => toml::from_str(&s)
+		.with_context(|| format!("Failed to parse {}", path.display())),
+	    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+	    Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+	}
+    }
+
+    /// Write the config file. It holds API keys, so only its
+    /// owner may read it.
+    pub fn save(&self) -> anyhow::Result<()> {
+	let path = Self::path()?;
+	if let Some(dir) = path.parent() {
+	    std::fs::create_dir_all(dir)
+		.with_context(|| format!("Failed to create {}", dir.display()))?;
+	}
+	let s = toml::to_string_pretty(self).context("Failed to encode config")?;
+	let mut options = std::fs::OpenOptions::new();
+	options.write(true).create(true).truncate(true);
+	#[cfg(unix)]
+	std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+	options.open(&path)
+	    .and_then(|mut file| file.write_all(s.as_bytes()))
+	    .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Name of the profile to use: the one given, the current
+    /// one, or the default one.
+    pub fn profile_name(&self, name: Option<&str>) -> String {
+	name.or(self.current.as_deref())
+	    .unwrap_or(DEFAULT_PROFILE)
+	    .to_string()
+    }
+
+    pub fn profile(&self, name: Option<&str>) -> Profile {
+	self.profiles
+	    .get(&self.profile_name(name))
+	    .cloned()
+	    .unwrap_or_default()
+    }
+}
+
+/// Run a `jhm config` command.
+pub fn run(command: ConfigCommand, profile: Option<&str>) -> anyhow::Result<()> {
+    let mut config = Config::load()?;
+    let name = config.profile_name(profile);
+    match command {
+	ConfigCommand::Set { setting, value } => {
+	    let entry = config.profiles.entry(name.clone()).or_default();
+	    let value = Some(value).filter(|value| !value.is_empty());
+	    match setting {
+		Setting::Service => entry.service = value
+		    .map(|value| Url::parse(&value))
+		    .transpose()
+		    .context("Invalid service URL")?,
+		Setting::ApiKey => entry.api_key = value,
+		Setting::Format => entry.format = value
+		    .map(|value| OutputFormat::from_str(&value, true))
+		    .transpose()
+		    .map_err(|e| anyhow::anyhow!("Invalid format: {e}"))?,
+	    }
+	    config.save()?;
+	},
+	ConfigCommand::Get { setting: Some(setting) } => {
+	    let profile = config.profile(Some(&name));
+	    let value = match setting {
+		Setting::Service => profile.service.map(String::from),
+		Setting::ApiKey => profile.api_key,
+		Setting::Format => profile.format.map(|format| format.name().to_string()),
+	    };
+	    match value {
+		Some(value) => println!("{value}"),
+		None => anyhow::bail!("{} is not set in profile {name:?}", setting.name()),
+	    }
+	},
+	ConfigCommand::Get { setting: None } => {
+	    let profile = config.profile(Some(&name));
+	    println!("profile: {name}");
+	    if let Some(service) = profile.service {
+		println!("service: {service}");
+	    }
+	    if let Some(api_key) = profile.api_key {
+		println!("api-key: {api_key}");
+	    }
+	    if let Some(format) = profile.format {
+		println!("format: {}", format.name());
+	    }
+	},
+	ConfigCommand::Use { profile } => {
+	    config.profiles.entry(profile.clone()).or_default();
+	    config.current = Some(profile);
+	    config.save()?;
+	},
+    }
+    Ok(())
+}
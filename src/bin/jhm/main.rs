@@ -0,0 +1,687 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use url::Url;
+use anyhow::Context;
+use chrono::NaiveDate;
+use uuid::Uuid;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use jhm::client::blocking::JhmClient;
+use jhm::routes::{
+    BatchResult, Campaign, ComparisonQuery, Hits as JhmHits, HitsParams, NewPage, Period,
+    ReportParams, TagHits, TagsParams, MAX_BATCH_SIZE,
+};
+use jhm::snippet::Format;
+
+mod series;
+use series::{bar, series, sparkline, Bucket, Point};
+mod watch;
+mod report;
+use report::ReportFormat;
+mod batch;
+use batch::{print_table, read_urls, run_bounded};
+mod sitemap;
+mod config;
+use config::ConfigCommand;
+mod compare;
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    #[arg(env="JHM_SERVICE")]
+    /// Address of the JHM service that does the tracking.
+    /// Defaults to the one of the profile.
+    service: Option<Url>,
+    /// Profile of the config file to use.
+    #[arg(long, global = true, env = "JHM_PROFILE")]
+    profile: Option<String>,
+    /// API key of the owner of the pages. Defaults to the one
+    /// of the profile.
+    #[arg(long, global = true, env = "JHM_API_KEY", hide_env_values = true)]
+    api_key: Option<String>,
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Get the number of hits that a page has. With several
+    /// pages, print a table of them instead.
+    Hits {
+	/// Pages to get the number of hits of.
+	#[arg(required_unless_present_any = ["file", "tag"])]
+	urls: Vec<String>,
+	/// Also read URLs from this file, one per line. `-` reads
+	/// them from stdin.
+	#[arg(long)]
+	file: Option<PathBuf>,
+	/// Sum up the hits of all pages with this tag instead.
+	#[arg(long, conflicts_with_all = ["urls", "file"])]
+	tag: Option<String>,
+	/// Only count pages of this site with `--tag`.
+	#[arg(long, requires = "tag")]
+	site: Option<String>,
+	/// Only count pages of this group with `--tag`.
+	#[arg(long, requires = "tag")]
+	group: Option<String>,
+	/// Number of pages that are queried at the same time.
+	#[arg(long, default_value_t = 4)]
+	jobs: usize,
+	/// Only count hits on or after this day (YYYY-MM-DD, UTC).
+	#[arg(long)]
+	since: Option<NaiveDate>,
+	/// Only count hits on or before this day (YYYY-MM-DD, UTC).
+	#[arg(long)]
+	until: Option<NaiveDate>,
+	/// Time span that's summed up into one value of the series.
+	#[arg(long, value_enum, default_value_t)]
+	bucket: Bucket,
+	/// Print the whole series in this format instead of a
+	/// summary. With several pages, print the table in it.
+	#[arg(long, value_enum)]
+	format: Option<OutputFormat>,
+    },
+    /// Keep showing the hits of pages, and how many they
+    /// got since the start, until Ctrl-C is pressed.
+    Watch {
+	/// Pages to watch.
+	#[arg(required = true)]
+	urls: Vec<Url>,
+	/// Seconds to wait between updates.
+	#[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+	interval: u64,
+    },
+    /// Compare the hits of pages in two time ranges, e.g. this
+    /// week and the week before.
+    Compare {
+	/// IDs of the pages to compare.
+	#[arg(required_unless_present = "tag")]
+	page_ids: Vec<Uuid>,
+	/// Compare all pages with this tag instead.
+	#[arg(long, conflicts_with = "page_ids")]
+	tag: Option<String>,
+	/// Only compare pages of this site with `--tag`.
+	#[arg(long, requires = "tag")]
+	site: Option<String>,
+	/// First day to count hits of (YYYY-MM-DD, UTC). Defaults
+	/// to 6 days before `--until`.
+	#[arg(long)]
+	since: Option<NaiveDate>,
+	/// Last day to count hits of. Defaults to today.
+	#[arg(long)]
+	until: Option<NaiveDate>,
+	/// First day to compare to. Defaults to as many days before
+	/// `--previous-until` as are compared.
+	#[arg(long)]
+	previous_since: Option<NaiveDate>,
+	/// Last day to compare to. Defaults to the day before `--since`.
+	#[arg(long)]
+	previous_until: Option<NaiveDate>,
+	#[arg(long, value_enum)]
+	format: Option<OutputFormat>,
+    },
+    /// Summarize the hits of all pages of an owner.
+    Report {
+	/// Time span to report on.
+	#[arg(long, value_enum, default_value_t)]
+	period: Period,
+	#[arg(long, value_enum, default_value_t)]
+	format: ReportFormat,
+	/// File to write the report to, instead of stdout.
+	#[arg(long, short)]
+	output: Option<PathBuf>,
+    },
+    /// Register every page in a sitemap and print their page IDs.
+    ImportSitemap {
+	/// Path or URL of the sitemap. Sitemap indexes and gzipped
+	/// sitemaps work, too.
+	sitemap: String,
+	/// Write the snippet of each page into this directory, in
+	/// a directory per host and URL path.
+	#[arg(long)]
+	snippets: Option<PathBuf>,
+	/// How the snippets embed the tracker.
+	#[arg(long, value_enum, default_value_t)]
+	snippet_format: Format,
+	/// Number of requests that are sent at the same time.
+	#[arg(long, default_value_t = 4)]
+	jobs: usize,
+	/// Print the page IDs in this format.
+	#[arg(long, value_enum)]
+	format: Option<OutputFormat>,
+    },
+    /// Manage the profiles in the config file.
+    Config {
+	#[command(subcommand)]
+	command: ConfigCommand,
+    },
+    /// Generate the snippet that's needed to track a page. With
+    /// several pages, register them all and print their IDs.
+    Generate {
+	/// URLs of the pages to track.
+	#[arg(required_unless_present = "file")]
+	urls: Vec<String>,
+	/// Also read URLs from this file, one per line. `-` reads
+	/// them from stdin.
+	#[arg(long)]
+	file: Option<PathBuf>,
+	/// Number of requests that are sent at the same time.
+	#[arg(long, default_value_t = 4)]
+	jobs: usize,
+	/// How the page embeds the tracker, `style` by default.
+	/// With several pages, print the snippet of each one in
+	/// this format.
+	#[arg(long, value_enum)]
+	format: Option<Format>,
+	/// Title of the page. Only with a single page.
+	#[arg(long)]
+	title: Option<String>,
+	/// Tag of the page, can be repeated. Only with a single page.
+	#[arg(long = "tag")]
+	tags: Vec<String>,
+	/// Section of the site that the page belongs to, e.g.
+	/// `blog`. Only with a single page.
+	#[arg(long)]
+	group: Option<String>,
+    },
+}
+
+use Commands::*;
+
+#[derive(Debug, Clone, Copy, ValueEnum, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+enum OutputFormat {
+    Json,
+    Csv,
+    Table,
+}
+
+impl OutputFormat {
+    fn name(&self) -> &'static str {
+	match self {
+	    OutputFormat::Json => "json",
+	    OutputFormat::Csv => "csv",
+	    OutputFormat::Table => "table",
+	}
+    }
+}
+
+/// Width of the bars in the table output.
+const BAR_WIDTH: usize = 40;
+
+#[derive(serde::Serialize)]
+struct HitsReport<'a> {
+    url: &'a Url,
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+    bucket: Bucket,
+    total: u64,
+    series: &'a [Point],
+    events: &'a BTreeMap<String, i64>,
+    active_visitors: u64,
+}
+
+fn print_hits(
+    report: &HitsReport,
+    format: Option<OutputFormat>,
+) -> anyhow::Result<()> {
+    match format {
+	None => {
+	    let HitsReport { url, total, series, .. } = report;
+	    let s = if *total == 1 { "" } else { "s" };
+	    println!("🌟 {url} has {total} hit{s}!");
+	    if let (Some(first), Some(last)) = (series.first(), series.last()) {
+		let values: Vec<u64> = series.iter().map(|p| p.hits).collect();
+		println!("   {}  {} – {}", sparkline(&values), first.start, last.start);
+	    }
+	    for (event, n) in report.events {
+		println!("   {event}: {n}");
+	    }
+	    let active = report.active_visitors;
+	    let s = if active == 1 { "" } else { "s" };
+	    println!("   👀 {active} visitor{s} in the last 5 minutes");
+	},
+	Some(OutputFormat::Table) => {
+	    let max = report.series.iter().map(|p| p.hits).max().unwrap_or(0);
+	    println!("{:<10}  {:>8}", "start", "hits");
+	    for point in report.series {
+		println!("{:<10}  {:>8}  {}", point.start, point.hits,
+			 bar(point.hits, max, BAR_WIDTH));
+	    }
+	},
+	Some(OutputFormat::Csv) => {
+	    println!("start,hits");
+	    for point in report.series {
+		println!("{},{}", point.start, point.hits);
+	    }
+	},
+	Some(OutputFormat::Json) => {
+	    println!("{}", serde_json::to_string_pretty(report)
+		     .context("Failed to encode hits")?);
+	},
+    }
+    Ok(())
+}
+
+/// Total hits within the time range of the series.
+fn total_hits(hits: &JhmHits, series: &[Point], ranged: bool) -> u64 {
+    // Without a time range, the total also includes
+    // hits that were counted before timestamps were.
+    if ranged {
+	series.iter().map(|p| p.hits).sum()
+    } else {
+	hits.n.try_into().unwrap_or(0)
+    }
+}
+
+/// Result of one page of a batch.
+#[derive(serde::Serialize)]
+struct HitsRow {
+    url: String,
+    hits: Option<u64>,
+    active_visitors: Option<u64>,
+    error: Option<String>,
+}
+
+fn print_hits_rows(rows: &[HitsRow], format: Option<OutputFormat>) -> anyhow::Result<()> {
+    let show = |n: Option<u64>| n.map(|n| n.to_string()).unwrap_or_default();
+    match format {
+	None | Some(OutputFormat::Table) => {
+	    let rows: Vec<Vec<String>> = rows.iter()
+		.map(|row| vec![
+		    row.url.clone(),
+		    show(row.hits),
+		    show(row.active_visitors),
+		    row.error.clone().unwrap_or_default(),
+		])
+		.collect();
+	    print_table(&["url", "hits", "active", "error"], &rows);
+	},
+	Some(OutputFormat::Csv) => {
+	    println!("url,hits,active_visitors,error");
+	    for row in rows {
+		println!("{},{},{},{}", csv_field(&row.url), show(row.hits),
+			 show(row.active_visitors),
+			 csv_field(row.error.as_deref().unwrap_or_default()));
+	    }
+	},
+	Some(OutputFormat::Json) => {
+	    println!("{}", serde_json::to_string_pretty(rows)
+		     .context("Failed to encode hits")?);
+	},
+    }
+    Ok(())
+}
+
+fn print_tag_hits(hits: &TagHits, format: Option<OutputFormat>) -> anyhow::Result<()> {
+    let rows = || hits.pages.iter().map(|page| vec![
+	page.url.clone(),
+	page.title.clone().unwrap_or_default(),
+	page.group.clone().unwrap_or_default(),
+	page.n.to_string(),
+    ]);
+    match format {
+	None | Some(OutputFormat::Table) => {
+	    if format.is_none() {
+		let TagHits { tag, n, pages } = hits;
+		let s = if *n == 1 { "" } else { "s" };
+		let pages_s = if pages.len() == 1 { "" } else { "s" };
+		println!("🌟 {tag} has {n} hit{s} on {} page{pages_s}!", pages.len());
+		println!();
+	    }
+	    print_table(&["url", "title", "group", "hits"], &rows().collect::<Vec<_>>());
+	},
+	Some(OutputFormat::Csv) => {
+	    println!("url,title,group,hits");
+	    for row in rows() {
+		let row: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+		println!("{}", row.join(","));
+	    }
+	},
+	Some(OutputFormat::Json) => {
+	    println!("{}", serde_json::to_string_pretty(hits)
+		     .context("Failed to encode hits")?);
+	},
+    }
+    Ok(())
+}
+
+/// Quote the field if it would break the CSV otherwise.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+	format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+	s.to_string()
+    }
+}
+
+fn get_hits(client: &JhmClient, url: &Url) -> anyhow::Result<JhmHits> {
+    let params = HitsParams { url: url.clone(), campaign: Campaign::default() };
+    Ok(client.hits(&params)?)
+}
+
+/// Register all pages, in batches of at most [`MAX_BATCH_SIZE`].
+/// If a batch fails, every page in it carries the error.
+fn register_all(
+    client: &JhmClient,
+    urls: &[String],
+    jobs: usize,
+) -> Vec<BatchResult> {
+    let chunks: Vec<&[String]> = urls.chunks(MAX_BATCH_SIZE).collect();
+    run_bounded(&chunks, jobs, |chunk| {
+	client.register_batch(chunk)
+	    .unwrap_or_else(|e| {
+		let error = format!("{:#}", anyhow::Error::from(e));
+		chunk.iter()
+		    .map(|url| BatchResult {
+			url: url.clone(),
+			page_id: None,
+			error: Some(error.clone()),
+		    })
+		    .collect()
+	    })
+    })
+	.into_iter()
+	.flatten()
+	.collect()
+}
+
+/// Snippet of the page, indented to stand out from the text
+/// around it.
+fn indented_snippet(format: Format, service: &Url, page_id: Uuid) -> String {
+    jhm::snippet::render(format, service, page_id)
+	.lines()
+	.map(|line| format!("  {line}\n"))
+	.collect()
+}
+
+fn print_registrations(
+    results: &[BatchResult],
+    format: Option<OutputFormat>,
+) -> anyhow::Result<()> {
+    let page_id = |result: &BatchResult| result.page_id
+	.map(|id| id.to_string())
+	.unwrap_or_default();
+    match format {
+	None | Some(OutputFormat::Table) => {
+	    let rows: Vec<Vec<String>> = results.iter()
+		.map(|result| vec![
+		    result.url.clone(),
+		    page_id(result),
+		    result.error.clone().unwrap_or_default(),
+		])
+		.collect();
+	    print_table(&["url", "page id", "error"], &rows);
+	},
+	Some(OutputFormat::Csv) => {
+	    println!("url,page_id,error");
+	    for result in results {
+		println!("{},{},{}", csv_field(&result.url), page_id(result),
+			 csv_field(result.error.as_deref().unwrap_or_default()));
+	    }
+	},
+	Some(OutputFormat::Json) => {
+	    println!("{}", serde_json::to_string_pretty(results)
+		     .context("Failed to encode page IDs")?);
+	},
+    }
+    Ok(())
+}
+
+/// Exit with an error if any page of a batch failed.
+fn exit_on_failures(failures: usize, total: usize) {
+    if failures > 0 {
+	eprintln!("💥 {failures} of {total} pages failed.");
+	std::process::exit(1);
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    if let Config { command } = cli.command {
+	config::run(command, cli.profile.as_deref())
+	    .unwrap_or_else(|e| {
+		eprintln!("💥 {e:#}");
+		std::process::exit(1);
+	    });
+	return;
+    }
+    let profile = config::Config::load()
+	.map(|config| config.profile(cli.profile.as_deref()))
+	.unwrap_or_else(|e| {
+	    eprintln!("💥 {e:#}");
+	    std::process::exit(1);
+	});
+    let service = cli.service.or(profile.service).unwrap_or_else(|| {
+	eprintln!("💥 No service given. Pass its address, or set it with `jhm config set service <url>`.");
+	std::process::exit(1);
+    });
+    let api_key = cli.api_key.or(profile.api_key);
+
+    let mut client = JhmClient::new(service.clone());
+    if let Some(api_key) = &api_key {
+	client = client.with_api_key(api_key);
+    }
+    
+    match cli.command {
+	Hits { urls, file, tag, site, group, jobs, since, until, bucket, format } => {
+	    if let Some(tag) = tag {
+		let hits = client.tag_hits(&tag, &TagsParams { site, group })
+		    .unwrap_or_else(|e| {
+			eprintln!("💥 Failed to get hits of tag {tag}: {:#}", anyhow::Error::from(e));
+			std::process::exit(1);
+		    });
+		print_tag_hits(&hits, format.or(profile.format))
+		    .expect("Failed to print hits");
+		return;
+	    }
+	    let urls = read_urls(urls, file.as_deref())
+		.unwrap_or_else(|e| {
+		    eprintln!("💥 {e:#}");
+		    std::process::exit(1);
+		});
+	    let ranged = since.is_some() || until.is_some();
+	    if urls.len() != 1 {
+		let rows = run_bounded(&urls, jobs, |url| {
+		    let hits = Url::parse(url)
+			.context("Invalid URL")
+			.and_then(|parsed| get_hits(&client, &parsed));
+		    match hits {
+			Ok(hits) => {
+			    let series = series(&hits.timestamps, since, until, bucket);
+			    HitsRow {
+				url: url.clone(),
+				hits: Some(total_hits(&hits, &series, ranged)),
+				active_visitors: Some(hits.active_visitors),
+				error: None,
+			    }
+			},
+			Err(e) => HitsRow {
+			    url: url.clone(),
+			    hits: None,
+			    active_visitors: None,
+			    error: Some(format!("{e:#}")),
+			},
+		    }
+		});
+		print_hits_rows(&rows, format.or(profile.format))
+		    .expect("Failed to print hits");
+		let failures = rows.iter().filter(|row| row.error.is_some()).count();
+		exit_on_failures(failures, rows.len());
+		return;
+	    }
+
+	    let url = Url::parse(&urls[0])
+		.unwrap_or_else(|e| {
+		    eprintln!("💥 {} is not a valid URL: {e}", urls[0]);
+		    std::process::exit(1);
+		});
+	    let hits = get_hits(&client, &url)
+		.unwrap_or_else(|e| {
+		    eprintln!("💥 Failed to get page hits of {url}: {e:#}");
+		    std::process::exit(1);
+		});
+	    let series = series(&hits.timestamps, since, until, bucket);
+	    let total = total_hits(&hits, &series, ranged);
+	    let report = HitsReport {
+		url: &url,
+		since,
+		until,
+		bucket,
+		total,
+		series: &series,
+		events: &hits.events,
+		active_visitors: hits.active_visitors,
+	    };
+	    print_hits(&report, format.or(profile.format))
+		.expect("Failed to print hits");
+	},
+	Watch { urls, interval } => {
+	    let started = chrono::Local::now();
+	    let mut rows: Vec<watch::Row> = urls.into_iter().map(watch::Row::new).collect();
+	    loop {
+		for row in &mut rows {
+		    let hits = get_hits(&client, &row.url);
+		    if let Err(err) = &hits {
+			if watch::is_unreachable(err) {
+			    eprintln!("💥 {} is unreachable: {err:#}", service);
+			    std::process::exit(1);
+			}
+		    }
+		    row.update(hits.map(|hits| hits.n.into()));
+		}
+		watch::render(&rows, &service, started, interval);
+		std::thread::sleep(std::time::Duration::from_secs(interval));
+	    }
+	},
+	Compare { page_ids, tag, site, since, until, previous_since, previous_until, format } => {
+	    let (current, previous) = compare::ranges(since, until, previous_since, previous_until)
+		.unwrap_or_else(|e| {
+		    eprintln!("💥 {e:#}");
+		    std::process::exit(1);
+		});
+	    let query = ComparisonQuery {
+		page_ids,
+		tag,
+		site,
+		current: Some(current),
+		previous: Some(previous),
+	    };
+	    let comparison = client.compare(&query)
+		.unwrap_or_else(|e| {
+		    eprintln!("💥 Failed to compare pages: {:#}", anyhow::Error::from(e));
+		    std::process::exit(1);
+		});
+	    compare::print(&comparison, format.or(profile.format))
+		.expect("Failed to print comparison");
+	},
+	Report { period, format, output } => {
+	    if api_key.is_none() {
+		eprintln!("💥 A report needs an API key. Pass --api-key, or set it with `jhm config set api-key <key>`.");
+		std::process::exit(1);
+	    }
+	    let report = client.report(&ReportParams { period })
+		.unwrap_or_else(|e| {
+		    eprintln!("💥 Failed to get report: {:#}", anyhow::Error::from(e));
+		    std::process::exit(1);
+		});
+	    let report = report::render(&report, format);
+	    match output {
+		Some(path) => std::fs::write(&path, report)
+		    .unwrap_or_else(|e| {
+			eprintln!("💥 Failed to write report to {}: {e}", path.display());
+			std::process::exit(1);
+		    }),
+		None => print!("{report}"),
+	    }
+	},
+	ImportSitemap { sitemap, snippets, snippet_format, jobs, format } => {
+	    let source = sitemap::Source::parse(&sitemap);
+	    let http = reqwest::blocking::Client::new();
+	    let urls = sitemap::page_urls(&source, &http)
+		.unwrap_or_else(|e| {
+		    eprintln!("💥 {e:#}");
+		    std::process::exit(1);
+		});
+	    let mut results = register_all(&client, &urls, jobs);
+	    if let Some(dir) = snippets {
+		for result in &mut results {
+		    let (Some(page_id), Ok(url)) = (result.page_id, Url::parse(&result.url)) else {
+			continue;
+		    };
+		    let path = sitemap::snippet_path(&dir, &url, snippet_format.extension());
+		    let snippet = jhm::snippet::render(snippet_format, &service, page_id);
+		    let written = path.parent()
+			.map_or(Ok(()), std::fs::create_dir_all)
+			.and_then(|()| std::fs::write(&path, snippet));
+		    if let Err(e) = written {
+			result.error = Some(format!("Failed to write {}: {e}", path.display()));
+		    }
+		}
+	    }
+	    print_registrations(&results, format.or(profile.format))
+		.expect("Failed to print page IDs");
+	    let failures = results.iter().filter(|result| result.error.is_some()).count();
+	    exit_on_failures(failures, results.len());
+	},
+	Config { .. } => unreachable!("Handled above"),
+	Generate { urls, file, jobs, format, title, tags, group } => {
+	    let urls = read_urls(urls, file.as_deref())
+		.unwrap_or_else(|e| {
+		    eprintln!("💥 {e:#}");
+		    std::process::exit(1);
+		});
+	    if urls.len() != 1 {
+		if title.is_some() || !tags.is_empty() || group.is_some() {
+		    eprintln!("💥 --title, --tag and --group only work with a single page.");
+		    std::process::exit(1);
+		}
+		let results = register_all(&client, &urls, jobs);
+		print_registrations(&results, None)
+		    .expect("Failed to print page IDs");
+		match format {
+		    Some(format) => for result in &results {
+			if let Some(page_id) = result.page_id {
+			    println!();
+			    println!("{}:", result.url);
+			    print!("{}", indented_snippet(format, &service, page_id));
+			}
+		    },
+		    None => {
+			println!();
+			println!("Get the snippet of a page with `jhm generate <url>`, or from {}pages/<page id>/snippet.",
+				 service);
+		    },
+		}
+		let failures = results.iter().filter(|result| result.page_id.is_none()).count();
+		exit_on_failures(failures, results.len());
+		return;
+	    }
+
+	    let url = Url::parse(&urls[0])
+		.unwrap_or_else(|e| {
+		    eprintln!("💥 {} is not a valid URL: {e}", urls[0]);
+		    std::process::exit(1);
+		});
+	    let page = NewPage { title, tags, group, ..NewPage::new(url.clone()) };
+	    let page_id = client.register_page(&page)
+		.unwrap_or_else(|e| {
+		    eprintln!("💥 Failed to register {url}: {:#}", anyhow::Error::from(e));
+		    std::process::exit(1);
+		});
+	    let format = format.unwrap_or_default();
+	    let snippet = indented_snippet(format, &service, page_id);
+	    let place = match format {
+		Format::Css => "CSS the in style sheets",
+		Format::Style | Format::Img | Format::Prefetch => "HTML in the body",
+	    };
+	    print!(r#"🗃️ SUCCESS! {url} is registered under the page ID {page_id}.
+This ID is used to track your page.
+
+Just put the following {place} of the page to track, and you're done!
+
+{snippet}"#);
+	},
+    }
+}
@@ -0,0 +1,141 @@
+use chrono::DateTime;
+use jhm::routes::{Period, Report};
+use jhm::utils::escape_html;
+
+/// Formats of a report.
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum ReportFormat {
+    #[default]
+    Markdown,
+    Html,
+}
+
+fn title(period: Period) -> &'static str {
+    match period {
+	Period::Day => "Daily report",
+	Period::Week => "Weekly report",
+	Period::Month => "Monthly report",
+    }
+}
+
+fn previous(period: Period) -> &'static str {
+    match period {
+	Period::Day => "Previous day",
+	Period::Week => "Previous week",
+	Period::Month => "Previous month",
+    }
+}
+
+fn format_date(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+	.map(|date| date.format("%Y-%m-%d").to_string())
+	.unwrap_or_default()
+}
+
+/// Change from the previous period, e.g. `+20%`.
+fn change(hits: i64, previous_hits: i64) -> String {
+    match (hits, previous_hits) {
+	(0, 0) => "–".to_string(),
+	(_, 0) => "new".to_string(),
+	_ => format!("{:+.0}%", (hits - previous_hits) as f64 * 100.0 / previous_hits as f64),
+    }
+}
+
+pub fn render(report: &Report, format: ReportFormat) -> String {
+    match format {
+	ReportFormat::Markdown => markdown(report),
+	ReportFormat::Html => html(report),
+    }
+}
+
+/// Keep text from breaking out of a Markdown table cell.
+fn escape_cell(s: &str) -> String {
+    s.replace('|', "\\|")
+}
+
+fn markdown(report: &Report) -> String {
+    let mut out = format!(
+	"# {}\n\n{} – {}\n\n## Top pages\n\n",
+	title(report.period),
+	format_date(report.since),
+	format_date(report.until),
+    );
+    if report.pages.is_empty() {
+	out.push_str("No pages yet.\n");
+    } else {
+	out.push_str(&format!("| Page | Hits | {} | Change |\n", previous(report.period)));
+	out.push_str("| --- | ---: | ---: | ---: |\n");
+	for page in &report.pages {
+	    out.push_str(&format!(
+		"| {} | {} | {} | {} |\n",
+		escape_cell(&page.url),
+		page.hits,
+		page.previous_hits,
+		change(page.hits, page.previous_hits),
+	    ));
+	}
+    }
+    out.push_str("\n## Top referrers\n\n");
+    if report.top_referrers.is_empty() {
+	out.push_str("No referrers yet.\n");
+    } else {
+	out.push_str("| Referrer | Hits |\n| --- | ---: |\n");
+	for referrer in &report.top_referrers {
+	    out.push_str(&format!("| {} | {} |\n", escape_cell(&referrer.referrer), referrer.n));
+	}
+    }
+    out
+}
+
+fn html(report: &Report) -> String {
+    let pages = if report.pages.is_empty() {
+	"<p>No pages yet.</p>".to_string()
+    } else {
+	let rows: String = report.pages.iter()
+	    .map(|page| format!(
+		"<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+		escape_html(&page.url),
+		page.hits,
+		page.previous_hits,
+		change(page.hits, page.previous_hits),
+	    ))
+	    .collect();
+	format!(
+	    "<table>\n<tr><th>Page</th><th>Hits</th><th>{}</th><th>Change</th></tr>\n{rows}</table>",
+	    previous(report.period),
+	)
+    };
+    let referrers = if report.top_referrers.is_empty() {
+	"<p>No referrers yet.</p>".to_string()
+    } else {
+	let rows: String = report.top_referrers.iter()
+	    .map(|referrer| format!(
+		"<tr><td>{}</td><td>{}</td></tr>\n",
+		escape_html(&referrer.referrer),
+		referrer.n,
+	    ))
+	    .collect();
+	format!("<table>\n<tr><th>Referrer</th><th>Hits</th></tr>\n{rows}</table>")
+    };
+    format!(
+	r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+</head>
+<body>
+<h1>{title}</h1>
+<p>{since} – {until}</p>
+<h2>Top pages</h2>
+{pages}
+<h2>Top referrers</h2>
+{referrers}
+</body>
+</html>
+"#,
+	title = title(report.period),
+	since = format_date(report.since),
+	until = format_date(report.until),
+    )
+}
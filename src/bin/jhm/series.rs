@@ -0,0 +1,103 @@
+use chrono::{DateTime, Datelike, Days, Months, NaiveDate, Utc};
+
+/// Length of the time span that's summed up into one value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bucket {
+    #[default]
+    Day,
+    Week,
+    Month,
+}
+
+impl Bucket {
+    /// First day of the bucket that contains `date`.
+    /// Weeks start on Monday.
+    pub fn start(&self, date: NaiveDate) -> NaiveDate {
+	match self {
+	    Bucket::Day => date,
+	    Bucket::Week => date - Days::new(date.weekday().num_days_from_monday().into()),
+	    Bucket::Month => date.with_day(1).expect("Every month has a first day"),
+	}
+    }
+
+    /// First day of the bucket after the one that starts at `start`.
+    pub fn next(&self, start: NaiveDate) -> NaiveDate {
+	match self {
+	    Bucket::Day => start + Days::new(1),
+	    Bucket::Week => start + Days::new(7),
+	    Bucket::Month => start + Months::new(1),
+	}
+    }
+}
+
+/// Number of hits in one bucket.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Point {
+    /// First day of the bucket.
+    pub start: NaiveDate,
+    pub hits: u64,
+}
+
+/// Sum up the hits at the given Unix timestamps per bucket.
+///
+/// `since` and `until` are inclusive. Without them, the series
+/// spans from the first hit to today. Buckets without hits are
+/// part of the series, too.
+pub fn series(
+    timestamps: &[i64],
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+    bucket: Bucket,
+) -> Vec<Point> {
+    let dates: Vec<NaiveDate> = timestamps
+	.iter()
+	.filter_map(|ts| DateTime::from_timestamp(*ts, 0))
+	.map(|dt| dt.date_naive())
+	.filter(|date| !matches!(since, Some(since) if *date < since))
+	.filter(|date| !matches!(until, Some(until) if *date > until))
+	.collect();
+    let first = match since.or_else(|| dates.iter().min().copied()) {
+	Some(first) => first,
+	None => return vec![],
+    };
+    let last = until.unwrap_or_else(|| Utc::now().date_naive());
+
+    let mut points = vec![];
+    let mut start = bucket.start(first);
+    while start <= last {
+	let end = bucket.next(start);
+	let hits = dates
+	    .iter()
+	    .filter(|date| **date >= start && **date < end)
+	    .count();
+	points.push(Point { start, hits: hits as u64 });
+	start = end;
+    }
+    points
+}
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Render the values as a one-line sparkline.
+pub fn sparkline(values: &[u64]) -> String {
+    let max = values.iter().copied().max().unwrap_or(0).max(1);
+    values
+	.iter()
+	.map(|v| SPARKS[(v * (SPARKS.len() as u64 - 1) / max) as usize])
+	.collect()
+}
+
+const EIGHTHS: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+/// Render the value as a horizontal bar, where `max` is
+/// `width` characters wide.
+pub fn bar(value: u64, max: u64, width: usize) -> String {
+    let eighths = (value * width as u64 * 8 / max.max(1)) as usize;
+    let mut bar = "█".repeat(eighths / 8);
+    let rest = eighths % 8;
+    if rest > 0 {
+	bar.push(EIGHTHS[rest]);
+    }
+    bar
+}
@@ -0,0 +1,340 @@
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// Sitemap indexes that point to further indexes are followed
+/// this deep at most.
+const MAX_DEPTH: usize = 3;
+
+/// Sitemaps can't be larger than this uncompressed, see
+/// <https://www.sitemaps.org/protocol.html>.
+const MAX_SITEMAP_SIZE: u64 = 50 * 1024 * 1024;
+
+/// Where a sitemap is read from.
+#[derive(Debug, Clone)]
+pub enum Source {
+    Remote(Url),
+    Local(PathBuf),
+}
+
+impl Source {
+    /// Anything that isn't an http(s) URL is taken as a path.
+    pub fn parse(s: &str) -> Self {
+	match Url::parse(s) {
+	    Ok(url) if matches!(url.scheme(), "http" | "https") => Source::Remote(url),
+	    Ok(url) if url.scheme() == "file" => match url.to_file_path() {
+		Ok(path) => Source::Local(path),
+		Err(()) => Source::Local(PathBuf::from(s)),
+	    },
+	    _ => Source::Local(PathBuf::from(s)),
+	}
+    }
+
+    /// Source of a sitemap that an index points to. Next to a
+    /// local index, a file with the same name as the sitemap is
+    /// preferred, so that sitemaps can be imported offline.
+    /// Remote indexes may only point to http(s) URLs, so that
+    /// they can't make the CLI read local files.
+    fn child(&self, loc: &str) -> anyhow::Result<Self> {
+	let url = Url::parse(loc)
+	    .with_context(|| format!("Invalid sitemap location {loc:?}"))?;
+	match self {
+	    Source::Local(path) => {
+		let name = url.path_segments()
+		    .and_then(|mut segments| segments.next_back())
+		    .filter(|name| !name.is_empty());
+		if let Some(name) = name {
+		    let sibling = path.parent().unwrap_or(Path::new(".")).join(name);
+		    if sibling.is_file() {
+			return Ok(Source::Local(sibling));
+		    }
+		}
+		Ok(Source::parse(url.as_str()))
+	    },
+	    Source::Remote(_) if matches!(url.scheme(), "http" | "https") => Ok(Source::Remote(url)),
+	    Source::Remote(_) => anyhow::bail!(
+		"Sitemap index {self} points to {loc:?}, which isn't an http(s) URL"
+	    ),
+	}
+    }
+
+    fn load(&self, client: &reqwest::blocking::Client) -> anyhow::Result<Vec<u8>> {
+	let bytes = match self {
+	    Source::Remote(url) => {
+		let response = client.get(url.clone())
+		    .send()
+		    .context("reqwest GET failed")?
+		    .error_for_status()
+		    .with_context(|| format!("Failed to fetch {url}"))?;
+		response.bytes()
+		    .context("Failed to receive sitemap")?
+		    .to_vec()
+	    },
+	    Source::Local(path) => std::fs::read(path)
+		.with_context(|| format!("Failed to read {}", path.display()))?,
+	};
+	decompress(bytes, MAX_SITEMAP_SIZE)
+    }
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+	match self {
+	    Source::Remote(url) => write!(f, "{url}"),
+	    Source::Local(path) => write!(f, "{}", path.display()),
+	}
+    }
+}
+
+/// Unpack gzipped sitemaps, which are recognized by their
+/// magic bytes rather than their name. Fails if a sitemap
+/// unpacks to more than `limit` bytes.
+fn decompress(bytes: Vec<u8>, limit: u64) -> anyhow::Result<Vec<u8>> {
+    if !bytes.starts_with(&[0x1f, 0x8b]) {
+	return Ok(bytes);
+    }
+    let mut unpacked = vec![];
+    flate2::read::GzDecoder::new(bytes.as_slice())
+	.take(limit + 1)
+	.read_to_end(&mut unpacked)
+	.context("Failed to unpack gzipped sitemap")?;
+    if unpacked.len() as u64 > limit {
+	anyhow::bail!("Gzipped sitemap is larger than {limit} bytes unpacked");
+    }
+    Ok(unpacked)
+}
+
+enum Sitemap {
+    /// Locations of pages.
+    UrlSet(Vec<String>),
+    /// Locations of further sitemaps.
+    Index(Vec<String>),
+}
+
+fn parse(bytes: &[u8]) -> anyhow::Result<Sitemap> {
+    let text = std::str::from_utf8(bytes).context("Sitemap is not UTF-8")?;
+    let doc = roxmltree::Document::parse(text).context("Sitemap is not valid XML")?;
+    let root = doc.root_element();
+    let locs = root
+	.descendants()
+	.filter(|node| node.is_element() && node.tag_name().name() == "loc")
+	.filter_map(|node| node.text())
+	.map(|loc| loc.trim().to_string())
+	.filter(|loc| !loc.is_empty())
+	.collect();
+    match root.tag_name().name() {
+	"urlset" => Ok(Sitemap::UrlSet(locs)),
+	"sitemapindex" => Ok(Sitemap::Index(locs)),
+	other => anyhow::bail!("Expected <urlset> or <sitemapindex>, found <{other}>"),
+    }
+}
+
+/// URLs of all pages in the sitemap, following sitemap indexes.
+/// Each URL is only returned once, in the order of the sitemaps.
+pub fn page_urls(
+    source: &Source,
+    client: &reqwest::blocking::Client,
+) -> anyhow::Result<Vec<String>> {
+    let mut urls = vec![];
+    let mut seen = HashSet::new();
+    collect(source, client, 0, &mut urls, &mut seen)?;
+    Ok(urls)
+}
+
+fn collect(
+    source: &Source,
+    client: &reqwest::blocking::Client,
+    depth: usize,
+    urls: &mut Vec<String>,
+    seen: &mut HashSet<String>,
+) -> anyhow::Result<()> {
+    let sitemap = source.load(client)
+	.and_then(|bytes| parse(&bytes))
+	.with_context(|| format!("Failed to load sitemap {source}"))?;
+    match sitemap {
+	Sitemap::UrlSet(locs) => {
+	    for loc in locs {
+		if seen.insert(loc.clone()) {
+		    urls.push(loc);
+		}
+	    }
+	},
+	Sitemap::Index(locs) => {
+	    if depth >= MAX_DEPTH {
+		anyhow::bail!("Sitemap indexes are nested too deep at {source}");
+	    }
+	    for loc in locs {
+		collect(&source.child(&loc)?, client, depth + 1, urls, seen)?;
+	    }
+	},
+    }
+    Ok(())
+}
+
+/// Path of the snippet file of a page: a directory per host
+/// and path segment, e.g. `example.com/blog/post/jhm.css`.
+/// Pages with a query get a short hash of it in the file name,
+/// e.g. `example.com/search/jhm-1a2b3c4d.css`, so that they
+/// don't overwrite each other.
+pub fn snippet_path(dir: &Path, url: &Url, extension: &str) -> PathBuf {
+    let mut path = dir.join(url.host_str().unwrap_or("_"));
+    for segment in url.path_segments().into_iter().flatten() {
+	if !matches!(segment, "" | "." | "..") {
+	    path.push(segment);
+	}
+    }
+    match url.query() {
+	Some(query) => {
+	    let hash = hex::encode(Sha256::digest(query.as_bytes()));
+	    path.push(format!("jhm-{}.{extension}", &hash[..8]));
+	},
+	None => path.push(format!("jhm.{extension}")),
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn urlset(locs: &[&str]) -> String {
+	let urls: String = locs.iter()
+	    .map(|loc| format!("<url><loc>{loc}</loc></url>"))
+	    .collect();
+	format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">{urls}</urlset>"#)
+    }
+
+    fn index(locs: &[&str]) -> String {
+	let sitemaps: String = locs.iter()
+	    .map(|loc| format!("<sitemap><loc>{loc}</loc></sitemap>"))
+	    .collect();
+	format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">{sitemaps}</sitemapindex>"#)
+    }
+
+    fn gzip(text: &str) -> Vec<u8> {
+	let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+	encoder.write_all(text.as_bytes()).unwrap();
+	encoder.finish().unwrap()
+    }
+
+    fn page_urls_of(path: &Path) -> anyhow::Result<Vec<String>> {
+	page_urls(&Source::Local(path.to_owned()), &reqwest::blocking::Client::new())
+    }
+
+    #[test]
+    fn indexes_are_followed_to_local_siblings() {
+	let dir = tempfile::tempdir().unwrap();
+	std::fs::write(dir.path().join("sitemap.xml"), index(&[
+	    "https://example.com/sitemaps/posts.xml",
+	    "https://example.com/sitemaps/pages.xml.gz",
+	])).unwrap();
+	std::fs::write(dir.path().join("posts.xml"), urlset(&[
+	    "https://example.com/a",
+	    "https://example.com/b",
+	])).unwrap();
+	std::fs::write(dir.path().join("pages.xml.gz"), gzip(&urlset(&[
+	    "https://example.com/b",
+	    "https://example.com/c",
+	]))).unwrap();
+
+	let urls = page_urls_of(&dir.path().join("sitemap.xml")).unwrap();
+
+	assert_eq!(urls, [
+	    "https://example.com/a",
+	    "https://example.com/b",
+	    "https://example.com/c",
+	]);
+    }
+
+    #[test]
+    fn gzipped_sitemaps_are_unpacked() {
+	let text = urlset(&["https://example.com/"]);
+	assert_eq!(decompress(gzip(&text), MAX_SITEMAP_SIZE).unwrap(), text.as_bytes());
+	assert_eq!(decompress(text.clone().into_bytes(), MAX_SITEMAP_SIZE).unwrap(), text.as_bytes());
+    }
+
+    #[test]
+    fn gzipped_sitemaps_that_unpack_too_large_fail() {
+	let text = urlset(&["https://example.com/"]);
+	let limit = text.len() as u64;
+	assert!(decompress(gzip(&text), limit).is_ok());
+
+	let e = decompress(gzip(&text), limit - 1).unwrap_err();
+
+	assert!(e.to_string().contains("larger than"), "{e:#}");
+    }
+
+    #[test]
+    fn remote_indexes_only_point_to_http() {
+	let remote = Source::parse("https://example.com/sitemap.xml");
+	assert!(matches!(
+	    remote.child("https://example.com/posts.xml"),
+	    Ok(Source::Remote(url)) if url.as_str() == "https://example.com/posts.xml"
+	));
+	for loc in ["file:///etc/passwd", "ftp://example.com/posts.xml"] {
+	    assert!(remote.child(loc).is_err(), "{loc}");
+	}
+    }
+
+    #[test]
+    fn indexes_that_nest_too_deep_fail() {
+	let dir = tempfile::tempdir().unwrap();
+	let path = dir.path().join("sitemap.xml");
+	std::fs::write(&path, index(&["https://example.com/sitemap.xml"])).unwrap();
+
+	let e = page_urls_of(&path).unwrap_err();
+
+	assert!(format!("{e:#}").contains("nested too deep"), "{e:#}");
+    }
+
+    #[test]
+    fn other_documents_are_rejected() {
+	let dir = tempfile::tempdir().unwrap();
+	let path = dir.path().join("sitemap.xml");
+	std::fs::write(&path, "<html></html>").unwrap();
+
+	assert!(page_urls_of(&path).is_err());
+    }
+
+    #[test]
+    fn sources_are_urls_or_paths() {
+	assert!(matches!(Source::parse("https://example.com/sitemap.xml"), Source::Remote(_)));
+	assert!(matches!(Source::parse("sitemap.xml"), Source::Local(_)));
+	assert!(matches!(
+	    Source::parse("file:///tmp/sitemap.xml"),
+	    Source::Local(path) if path == Path::new("/tmp/sitemap.xml")
+	));
+    }
+
+    #[test]
+    fn snippets_get_a_directory_per_path_segment() {
+	let dir = Path::new("snippets");
+	let path = |url: &str| snippet_path(dir, &Url::parse(url).unwrap(), "css");
+
+	assert_eq!(path("https://example.com/"), dir.join("example.com/jhm.css"));
+	assert_eq!(
+	    path("https://example.com/blog/post/"),
+	    dir.join("example.com/blog/post/jhm.css"),
+	);
+	assert_eq!(path("https://example.com/../a/./b"), dir.join("example.com/a/b/jhm.css"));
+    }
+
+    #[test]
+    fn snippets_of_pages_with_queries_get_their_own_file() {
+	let url = |s: &str| Url::parse(s).unwrap();
+	let dir = Path::new("snippets");
+	let first = snippet_path(dir, &url("https://example.com/search?q=a"), "css");
+	let second = snippet_path(dir, &url("https://example.com/search?q=b"), "css");
+
+	assert_ne!(first, second);
+	assert_eq!(first.parent(), Some(dir.join("example.com/search").as_path()));
+	assert_ne!(first, snippet_path(dir, &url("https://example.com/search"), "css"));
+	assert_eq!(first, snippet_path(dir, &url("https://example.com/search?q=a"), "css"));
+    }
+}
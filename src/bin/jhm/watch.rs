@@ -0,0 +1,71 @@
+use chrono::{DateTime, Local};
+use url::Url;
+
+/// Latest state of one watched page.
+pub struct Row {
+    pub url: Url,
+    /// Hits at the first successful poll.
+    pub start: Option<i64>,
+    /// Hits at the latest successful poll.
+    pub current: Option<i64>,
+    /// Error of the latest poll, if it failed.
+    pub error: Option<String>,
+}
+
+impl Row {
+    pub fn new(url: Url) -> Self {
+	Self { url, start: None, current: None, error: None }
+    }
+
+    /// Record the outcome of a poll.
+    pub fn update(&mut self, hits: anyhow::Result<i64>) {
+	match hits {
+	    Ok(n) => {
+		self.start.get_or_insert(n);
+		self.current = Some(n);
+		self.error = None;
+	    },
+	    Err(err) => self.error = Some(format!("{err:#}")),
+	}
+    }
+}
+
+/// Whether the error means that the service can't be reached
+/// at all, rather than that it failed to answer for one page.
+pub fn is_unreachable(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<jhm::client::Error>()
+	.is_some_and(jhm::client::Error::is_unreachable)
+}
+
+/// Clear the terminal and draw the table of all rows.
+pub fn render(
+    rows: &[Row],
+    service: &Url,
+    started: DateTime<Local>,
+    interval: u64,
+) {
+    // Move the cursor home and clear the screen.
+    print!("\x1b[H\x1b[2J");
+    println!("👀 Watching {service} every {interval}s since {}. Press Ctrl-C to stop.",
+	     started.format("%H:%M:%S"));
+    println!("   Updated {}", Local::now().format("%H:%M:%S"));
+    println!();
+    let width = rows.iter()
+	.map(|row| row.url.as_str().len())
+	.max()
+	.unwrap_or(0)
+	.max(3);
+    println!("{:<width$}  {:>10}  {:>8}", "url", "hits", "delta");
+    for row in rows {
+	let hits = row.current.map_or("–".to_string(), |n| n.to_string());
+	let delta = match (row.start, row.current) {
+	    (Some(start), Some(current)) => format!("{:+}", current - start),
+	    _ => "–".to_string(),
+	};
+	print!("{:<width$}  {hits:>10}  {delta:>8}", row.url.as_str());
+	if let Some(err) = &row.error {
+	    print!("  ⚠️ {err}");
+	}
+	println!();
+    }
+}
@@ -1,9 +1,30 @@
-use jhm::startup::Application;
+use clap::{Parser, Subcommand};
+use chrono::NaiveDate;
+use jhm::startup::{get_pg_connection_pool, Application};
 use jhm::configuration::get_configuration;
 use jhm::telemetry::*;
 
+#[derive(Parser)]
+#[command(author, version, about = "Serve the JHM API.", long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Recount the hourly and daily rollups of the stats from
+    /// the raw hits, then exit.
+    RebuildRollups {
+	/// Only recount from this day on (YYYY-MM-DD, UTC).
+	#[arg(long)]
+	since: Option<NaiveDate>,
+    },
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
     init_subscriber(get_subscriber(
         "api".into(),
         "info".into(),
@@ -11,7 +32,17 @@ async fn main() -> anyhow::Result<()> {
     ));
 
     let configuration = get_configuration().expect("Failed to read configuration");
-    let application = Application::build(configuration).await?;
-    application.run_until_stopped().await?;
+    match cli.command {
+	None => {
+	    let application = Application::build(configuration).await?;
+	    application.run_until_stopped().await?;
+	},
+	Some(Command::RebuildRollups { since }) => {
+	    let pg_pool = get_pg_connection_pool(&configuration.postgres).await;
+	    let since = since.map(|day| day.and_time(chrono::NaiveTime::MIN).and_utc().timestamp());
+	    let rows = jhm::rollups::rebuild(&pg_pool, since).await?;
+	    tracing::info!(rows, "Rebuilt rollups");
+	},
+    }
     Ok(())
 }
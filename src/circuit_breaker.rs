@@ -0,0 +1,102 @@
+//! Skip calls to a service while it's down, so that requests
+//! that can do without it don't wait for its timeouts.
+//!
+//! After a call fails, the circuit is open and calls are
+//! skipped for the retry interval. Then one call is let
+//! through to check if the service is back. If it succeeds,
+//! the circuit closes again, otherwise it stays open for
+//! another interval.
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::utils::RedisPool;
+
+pub struct CircuitBreaker {
+    /// Name of the service, for the logs.
+    name: &'static str,
+    retry_interval: Duration,
+    /// Until when calls are skipped, while the circuit is open.
+    open_until: Mutex<Option<Instant>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &'static str, retry_interval: Duration) -> Self {
+	Self {
+	    name,
+	    retry_interval,
+	    open_until: Mutex::new(None),
+	}
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Instant>> {
+	self.open_until.lock().expect("Circuit breaker was poisoned")
+    }
+
+    /// Whether the last call failed, as far as it's known.
+    pub fn is_open(&self) -> bool {
+	self.lock().is_some()
+    }
+
+    /// Whether a call may be made now. If so, its result must
+    /// be passed to [`CircuitBreaker::record`].
+    pub fn try_call(&self) -> bool {
+	let mut open_until = self.lock();
+	match *open_until {
+	    None => true,
+	    Some(until) if Instant::now() >= until => {
+		// The other calls are still skipped while this
+		// one checks if the service is back.
+		*open_until = Some(Instant::now() + self.retry_interval);
+		true
+	    },
+	    Some(_) => false,
+	}
+    }
+
+    pub fn record<T>(&self, result: &anyhow::Result<T>) {
+	let mut open_until = self.lock();
+	match result {
+	    Ok(_) => if open_until.take().is_some() {
+		tracing::info!("{} is back", self.name);
+	    },
+	    Err(e) => {
+		if open_until.is_none() {
+		    tracing::warn!(
+			error.cause_chain = ?e,
+			"{} is down, skipping it for {:?}",
+			self.name,
+			self.retry_interval,
+		    );
+		}
+		*open_until = Some(Instant::now() + self.retry_interval);
+	    },
+	}
+    }
+
+    /// Make the call, unless the circuit is open.
+    pub fn call<T>(&self, call: impl FnOnce() -> anyhow::Result<T>) -> Option<anyhow::Result<T>> {
+	if !self.try_call() {
+	    return None;
+	}
+	let result = call();
+	self.record(&result);
+	Some(result)
+    }
+}
+
+/// Redis, for the routes that can do without it. Calls are
+/// skipped while it's down.
+#[derive(Clone)]
+pub struct FallibleRedis {
+    pub pool: RedisPool,
+    pub circuit: Arc<CircuitBreaker>,
+}
+
+impl FallibleRedis {
+    /// Make the call with the pool, unless Redis is down.
+    pub fn call<T>(
+	&self,
+	call: impl FnOnce(&RedisPool) -> anyhow::Result<T>,
+    ) -> Option<anyhow::Result<T>> {
+	self.circuit.call(|| call(&self.pool))
+    }
+}
@@ -0,0 +1,417 @@
+//! Client of the JHM API, for services that want to register
+//! pages or query their stats.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), jhm::client::Error> {
+//! use jhm::client::JhmClient;
+//! use jhm::routes::HitsParams;
+//!
+//! let client = JhmClient::new("https://just-how-many.com".parse().unwrap());
+//! let url = "https://example.com/".parse().unwrap();
+//! let page_id = client.register(&url).await?;
+//! let hits = client.hits(&HitsParams { url, campaign: Default::default() }).await?;
+//! println!("{page_id} has {} hits", hits.n);
+//! # Ok(())
+//! # }
+//! ```
+use futures_util::stream::{BoxStream, StreamExt};
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+use url::Url;
+use uuid::Uuid;
+use crate::routes::{
+    ActiveParams, ActiveVisitors, BadgeParams, BatchRegistration, BatchResult,
+    BreakdownParams, CampaignHits, CampaignsParams, Comparison, ComparisonQuery,
+    DashboardParams, DimensionHits, Hits, HitsParams, LiveHit, NewOwner, NewPage,
+    NewWebhook, Page, PageUpdate, Report, ReportParams, SnippetParams, TagHits,
+    TagSummary, TagsParams, Webhook,
+};
+use crate::utils::error_chain_fmt;
+
+pub mod blocking;
+
+/// Version of the API that the client speaks.
+const API_PREFIX: &str = "v1/";
+
+#[derive(thiserror::Error)]
+pub enum Error {
+    #[error("Failed to send request")]
+    Request(#[source] reqwest::Error),
+    #[error("Server error ({status}): {message}")]
+    Status {
+	status: StatusCode,
+	/// Body of the response, if any.
+	message: String,
+    },
+    #[error("Failed to decode response")]
+    Decode(#[source] serde_json::Error),
+    #[error("Invalid request URL")]
+    Url(#[from] url::ParseError),
+}
+
+impl std::fmt::Debug for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+	error_chain_fmt(self, f)
+    }
+}
+
+impl Error {
+    /// Status of the response, if the server answered with an error.
+    pub fn status(&self) -> Option<StatusCode> {
+	match self {
+	    Error::Status { status, .. } => Some(*status),
+	    _ => None,
+	}
+    }
+
+    /// Whether the service can't be reached at all, rather
+    /// than that it failed to answer this request.
+    pub fn is_unreachable(&self) -> bool {
+	matches!(self, Error::Request(e) if e.is_connect() || e.is_timeout())
+    }
+
+    /// Requests that never reached the service are always worth
+    /// another try. Timeouts and server errors only are if the
+    /// request can safely be repeated.
+    fn is_retryable(&self, idempotent: bool) -> bool {
+	match self {
+	    Error::Request(e) => e.is_connect() || (idempotent && e.is_timeout()),
+	    Error::Status { status, .. } => idempotent
+		&& (status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS),
+	    _ => false,
+	}
+    }
+}
+
+/// How often failed requests are retried.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt.
+    pub max_retries: u32,
+    /// Wait before the first retry. It doubles with each retry.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+	Self { max_retries: 3, backoff: Duration::from_millis(200) }
+    }
+}
+
+impl RetryPolicy {
+    pub fn never() -> Self {
+	Self { max_retries: 0, backoff: Duration::ZERO }
+    }
+}
+
+/// Hits of a live stream, as they are counted. The stream ends
+/// when the service closes it.
+pub type LiveHits = BoxStream<'static, Result<LiveHit, Error>>;
+
+#[derive(Debug, Clone)]
+pub struct JhmClient {
+    http: reqwest::Client,
+    base_url: Url,
+    api_key: Option<String>,
+    retry: RetryPolicy,
+}
+
+impl JhmClient {
+    /// Client of the service at `base_url`. The API may live
+    /// below a path, e.g. `https://example.com/jhm/`.
+    pub fn new(mut base_url: Url) -> Self {
+	if !base_url.path().ends_with('/') {
+	    let path = format!("{}/", base_url.path());
+	    base_url.set_path(&path);
+	}
+	let http = reqwest::Client::builder()
+	    .redirect(reqwest::redirect::Policy::none())
+	    .build()
+	    .expect("Failed to build reqwest client");
+	Self { http, base_url, api_key: None, retry: RetryPolicy::default() }
+    }
+
+    /// Send requests on behalf of the owner of the API key. It's
+    /// needed to manage pages, and makes registered pages belong
+    /// to the owner.
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+	self.api_key = Some(api_key.into());
+	self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+	self.retry = retry;
+	self
+    }
+
+    pub fn base_url(&self) -> &Url {
+	&self.base_url
+    }
+
+    pub async fn health_check(&self) -> Result<(), Error> {
+	let request = self.request(Method::GET, "health_check")?;
+	self.send(request, true).await?;
+	Ok(())
+    }
+
+    /// Count a hit of the page, as if a reader had viewed it.
+    pub async fn hit(&self, page_id: Uuid) -> Result<(), Error> {
+	let request = self.request(Method::GET, &format!("hit/{page_id}"))?;
+	self.send(request, false).await?;
+	Ok(())
+    }
+
+    pub async fn hit_event(&self, page_id: Uuid, event: &str) -> Result<(), Error> {
+	let mut url = self.url(&format!("hit/{page_id}/event/"))?;
+	url.path_segments_mut()
+	    .map_err(|()| url::ParseError::RelativeUrlWithCannotBeABaseBase)?
+	    .pop_if_empty()
+	    .push(event);
+	let request = self.authorized(self.http.get(url));
+	self.send(request, false).await?;
+	Ok(())
+    }
+
+    /// Register a page, or get the ID it's registered under.
+    pub async fn register(&self, url: &Url) -> Result<Uuid, Error> {
+	self.register_page(&NewPage::new(url.clone())).await
+    }
+
+    /// Register a page with metadata. The metadata is ignored if
+    /// the page is registered already.
+    pub async fn register_page(&self, page: &NewPage) -> Result<Uuid, Error> {
+	let request = self.request(Method::POST, "register")?.json(page);
+	json(self.send(request, true).await?).await
+    }
+
+    /// Register many pages at once. Invalid URLs are reported
+    /// in their result rather than failing the whole batch.
+    pub async fn register_batch(&self, urls: &[String]) -> Result<Vec<BatchResult>, Error> {
+	let request = self.request(Method::POST, "register/batch")?
+	    .json(&BatchRegistration { urls: urls.to_vec() });
+	json(self.send(request, true).await?).await
+    }
+
+    pub async fn hits(&self, params: &HitsParams) -> Result<Hits, Error> {
+	let request = self.request(Method::GET, "hits")?.query(params);
+	json(self.send(request, true).await?).await
+    }
+
+    pub async fn campaigns(&self, params: &CampaignsParams) -> Result<Vec<CampaignHits>, Error> {
+	let request = self.request(Method::GET, "campaigns")?.query(params);
+	json(self.send(request, true).await?).await
+    }
+
+    pub async fn active(&self, params: &ActiveParams) -> Result<ActiveVisitors, Error> {
+	let request = self.request(Method::GET, "active")?.query(params);
+	json(self.send(request, true).await?).await
+    }
+
+    pub async fn tags(&self, params: &TagsParams) -> Result<Vec<TagSummary>, Error> {
+	let request = self.request(Method::GET, "tags")?.query(params);
+	json(self.send(request, true).await?).await
+    }
+
+    pub async fn tag_hits(&self, tag: &str, params: &TagsParams) -> Result<TagHits, Error> {
+	let mut url = self.url("tags/")?;
+	url.path_segments_mut()
+	    .map_err(|()| url::ParseError::RelativeUrlWithCannotBeABaseBase)?
+	    .pop_if_empty()
+	    .extend([tag, "hits"]);
+	let request = self.authorized(self.http.get(url)).query(params);
+	json(self.send(request, true).await?).await
+    }
+
+    pub async fn compare(&self, query: &ComparisonQuery) -> Result<Comparison, Error> {
+	let request = self.request(Method::POST, "compare")?.json(query);
+	json(self.send(request, true).await?).await
+    }
+
+    pub async fn breakdown(
+	&self,
+	page_id: Uuid,
+	params: &BreakdownParams,
+    ) -> Result<Vec<DimensionHits>, Error> {
+	let request = self.request(Method::GET, &format!("pages/{page_id}/breakdown"))?
+	    .query(params);
+	json(self.send(request, true).await?).await
+    }
+
+    /// Report on the pages of the owner of the API key.
+    pub async fn report(&self, params: &ReportParams) -> Result<Report, Error> {
+	let request = self.request(Method::GET, "report")?.query(params);
+	json(self.send(request, true).await?).await
+    }
+
+    pub async fn new_owner(&self) -> Result<NewOwner, Error> {
+	let request = self.request(Method::POST, "owners")?;
+	json(self.send(request, false).await?).await
+    }
+
+    pub async fn update_page(&self, page_id: Uuid, update: &PageUpdate) -> Result<Page, Error> {
+	let request = self.request(Method::PATCH, &format!("pages/{page_id}"))?.json(update);
+	json(self.send(request, true).await?).await
+    }
+
+    pub async fn snippet(&self, page_id: Uuid, params: &SnippetParams) -> Result<String, Error> {
+	let request = self.request(Method::GET, &format!("pages/{page_id}/snippet"))?
+	    .query(params);
+	text(self.send(request, true).await?).await
+    }
+
+    /// SVG of the badge of the page.
+    pub async fn badge(&self, page_id: Uuid, params: &BadgeParams) -> Result<String, Error> {
+	let request = self.request(Method::GET, &format!("badge/{page_id}.svg"))?
+	    .query(params);
+	text(self.send(request, true).await?).await
+    }
+
+    /// HTML of the dashboard of a site or a page.
+    pub async fn dashboard(
+	&self,
+	site_or_page: &str,
+	params: &DashboardParams,
+    ) -> Result<String, Error> {
+	let mut url = self.url("dashboard/")?;
+	url.path_segments_mut()
+	    .map_err(|()| url::ParseError::RelativeUrlWithCannotBeABaseBase)?
+	    .pop_if_empty()
+	    .push(site_or_page);
+	let request = self.authorized(self.http.get(url)).query(params);
+	text(self.send(request, true).await?).await
+    }
+
+    pub async fn live_page(&self, page_id: Uuid) -> Result<LiveHits, Error> {
+	let request = self.request(Method::GET, &format!("pages/{page_id}/live"))?;
+	Ok(live_hits(self.send(request, true).await?))
+    }
+
+    /// Hits of all pages of the site that belong to the owner.
+    pub async fn live_site(&self, site: &str) -> Result<LiveHits, Error> {
+	let mut url = self.url("sites/")?;
+	url.path_segments_mut()
+	    .map_err(|()| url::ParseError::RelativeUrlWithCannotBeABaseBase)?
+	    .pop_if_empty()
+	    .extend([site, "live"]);
+	let request = self.authorized(self.http.get(url));
+	Ok(live_hits(self.send(request, true).await?))
+    }
+
+    pub async fn webhooks(&self, page_id: Uuid) -> Result<Vec<Webhook>, Error> {
+	let request = self.request(Method::GET, &format!("pages/{page_id}/webhooks"))?;
+	json(self.send(request, true).await?).await
+    }
+
+    pub async fn create_webhook(
+	&self,
+	page_id: Uuid,
+	webhook: &NewWebhook,
+    ) -> Result<Webhook, Error> {
+	let request = self.request(Method::POST, &format!("pages/{page_id}/webhooks"))?
+	    .json(webhook);
+	json(self.send(request, false).await?).await
+    }
+
+    pub async fn delete_webhook(&self, page_id: Uuid, webhook_id: Uuid) -> Result<(), Error> {
+	let request = self.request(
+	    Method::DELETE,
+	    &format!("pages/{page_id}/webhooks/{webhook_id}"),
+	)?;
+	self.send(request, true).await?;
+	Ok(())
+    }
+
+    fn url(&self, path: &str) -> Result<Url, Error> {
+	Ok(self.base_url.join(API_PREFIX)?.join(path)?)
+    }
+
+    fn request(&self, method: Method, path: &str) -> Result<RequestBuilder, Error> {
+	Ok(self.authorized(self.http.request(method, self.url(path)?)))
+    }
+
+    fn authorized(&self, request: RequestBuilder) -> RequestBuilder {
+	match &self.api_key {
+	    Some(api_key) => request.bearer_auth(api_key),
+	    None => request,
+	}
+    }
+
+    /// Send the request, and retry it according to the policy.
+    /// Responses that aren't successful are errors.
+    async fn send(&self, request: RequestBuilder, idempotent: bool) -> Result<Response, Error> {
+	let mut backoff = self.retry.backoff;
+	let mut retries = 0;
+	loop {
+	    let attempt = request
+		.try_clone()
+		.expect("Request bodies are never streamed");
+	    let error = match attempt.send().await {
+		Ok(response) if response.status().is_success() => return Ok(response),
+		Ok(response) => {
+		    let status = response.status();
+		    let message = response.text().await.unwrap_or_default();
+		    Error::Status { status, message }
+		},
+		Err(e) => Error::Request(e),
+	    };
+	    if retries >= self.retry.max_retries || !error.is_retryable(idempotent) {
+		return Err(error);
+	    }
+	    tracing::debug!("Retrying in {backoff:?}: {error}");
+	    tokio::time::sleep(backoff).await;
+	    backoff *= 2;
+	    retries += 1;
+	}
+    }
+}
+
+async fn json<T: DeserializeOwned>(response: Response) -> Result<T, Error> {
+    let bytes = response.bytes().await.map_err(Error::Request)?;
+    serde_json::from_slice(&bytes).map_err(Error::Decode)
+}
+
+async fn text(response: Response) -> Result<String, Error> {
+    response.text().await.map_err(Error::Request)
+}
+
+/// Parse the server-sent events of the response into hits.
+fn live_hits(response: Response) -> LiveHits {
+    let state = Some((response, Vec::new()));
+    futures_util::stream::unfold(state, |state| async move {
+	let (mut response, mut buffer) = state?;
+	loop {
+	    if let Some(end) = buffer.windows(2).position(|w| w == b"\n\n") {
+		let event: Vec<u8> = buffer.drain(..end + 2).collect();
+		match parse_event(&String::from_utf8_lossy(&event)) {
+		    Some(hit) => return Some((hit, Some((response, buffer)))),
+		    None => continue,
+		}
+	    }
+	    match response.chunk().await {
+		Ok(Some(chunk)) => buffer.extend_from_slice(&chunk),
+		Ok(None) => return None,
+		// The stream is broken, so end it after the error.
+		Err(e) => return Some((Err(Error::Request(e)), None)),
+	    }
+	}
+    })
+	.boxed()
+}
+
+/// The hit of an event. Comments and other events are skipped.
+fn parse_event(event: &str) -> Option<Result<LiveHit, Error>> {
+    let mut name = "message";
+    let mut data = vec![];
+    for line in event.lines() {
+	if let Some(value) = line.strip_prefix("event:") {
+	    name = value.trim_start();
+	} else if let Some(value) = line.strip_prefix("data:") {
+	    data.push(value.strip_prefix(' ').unwrap_or(value));
+	}
+    }
+    if name != "hit" || data.is_empty() {
+	return None;
+    }
+    Some(serde_json::from_str(&data.join("\n")).map_err(Error::Decode))
+}
@@ -0,0 +1,168 @@
+//! Blocking version of [`JhmClient`](super::JhmClient), for
+//! programs that don't run an async runtime. It runs its own, so
+//! it must not be used from within an async context.
+use futures_util::StreamExt;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+use url::Url;
+use uuid::Uuid;
+use crate::routes::{
+    ActiveParams, ActiveVisitors, BadgeParams, BatchResult, BreakdownParams,
+    CampaignHits, CampaignsParams, Comparison, ComparisonQuery, DashboardParams,
+    DimensionHits, Hits, HitsParams, LiveHit, NewOwner, NewPage, NewWebhook, Page,
+    PageUpdate, Report, ReportParams, SnippetParams, TagHits, TagSummary, TagsParams,
+    Webhook,
+};
+use super::{Error, LiveHits, RetryPolicy};
+
+/// Cheap to clone, and can be shared between threads.
+#[derive(Debug, Clone)]
+pub struct JhmClient {
+    inner: super::JhmClient,
+    runtime: Arc<Runtime>,
+}
+
+impl JhmClient {
+    pub fn new(base_url: Url) -> Self {
+	let runtime = tokio::runtime::Builder::new_multi_thread()
+	    .worker_threads(1)
+	    .enable_all()
+	    .build()
+	    .expect("Failed to build Tokio runtime");
+	Self {
+	    inner: super::JhmClient::new(base_url),
+	    runtime: Arc::new(runtime),
+	}
+    }
+
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+	self.inner = self.inner.with_api_key(api_key);
+	self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+	self.inner = self.inner.with_retry(retry);
+	self
+    }
+
+    pub fn base_url(&self) -> &Url {
+	self.inner.base_url()
+    }
+
+    pub fn health_check(&self) -> Result<(), Error> {
+	self.runtime.block_on(self.inner.health_check())
+    }
+
+    pub fn hit(&self, page_id: Uuid) -> Result<(), Error> {
+	self.runtime.block_on(self.inner.hit(page_id))
+    }
+
+    pub fn hit_event(&self, page_id: Uuid, event: &str) -> Result<(), Error> {
+	self.runtime.block_on(self.inner.hit_event(page_id, event))
+    }
+
+    pub fn register(&self, url: &Url) -> Result<Uuid, Error> {
+	self.runtime.block_on(self.inner.register(url))
+    }
+
+    pub fn register_page(&self, page: &NewPage) -> Result<Uuid, Error> {
+	self.runtime.block_on(self.inner.register_page(page))
+    }
+
+    pub fn register_batch(&self, urls: &[String]) -> Result<Vec<BatchResult>, Error> {
+	self.runtime.block_on(self.inner.register_batch(urls))
+    }
+
+    pub fn hits(&self, params: &HitsParams) -> Result<Hits, Error> {
+	self.runtime.block_on(self.inner.hits(params))
+    }
+
+    pub fn campaigns(&self, params: &CampaignsParams) -> Result<Vec<CampaignHits>, Error> {
+	self.runtime.block_on(self.inner.campaigns(params))
+    }
+
+    pub fn active(&self, params: &ActiveParams) -> Result<ActiveVisitors, Error> {
+	self.runtime.block_on(self.inner.active(params))
+    }
+
+    pub fn tags(&self, params: &TagsParams) -> Result<Vec<TagSummary>, Error> {
+	self.runtime.block_on(self.inner.tags(params))
+    }
+
+    pub fn tag_hits(&self, tag: &str, params: &TagsParams) -> Result<TagHits, Error> {
+	self.runtime.block_on(self.inner.tag_hits(tag, params))
+    }
+
+    pub fn compare(&self, query: &ComparisonQuery) -> Result<Comparison, Error> {
+	self.runtime.block_on(self.inner.compare(query))
+    }
+
+    pub fn breakdown(
+	&self,
+	page_id: Uuid,
+	params: &BreakdownParams,
+    ) -> Result<Vec<DimensionHits>, Error> {
+	self.runtime.block_on(self.inner.breakdown(page_id, params))
+    }
+
+    pub fn report(&self, params: &ReportParams) -> Result<Report, Error> {
+	self.runtime.block_on(self.inner.report(params))
+    }
+
+    pub fn new_owner(&self) -> Result<NewOwner, Error> {
+	self.runtime.block_on(self.inner.new_owner())
+    }
+
+    pub fn update_page(&self, page_id: Uuid, update: &PageUpdate) -> Result<Page, Error> {
+	self.runtime.block_on(self.inner.update_page(page_id, update))
+    }
+
+    pub fn snippet(&self, page_id: Uuid, params: &SnippetParams) -> Result<String, Error> {
+	self.runtime.block_on(self.inner.snippet(page_id, params))
+    }
+
+    pub fn badge(&self, page_id: Uuid, params: &BadgeParams) -> Result<String, Error> {
+	self.runtime.block_on(self.inner.badge(page_id, params))
+    }
+
+    pub fn dashboard(&self, site_or_page: &str, params: &DashboardParams) -> Result<String, Error> {
+	self.runtime.block_on(self.inner.dashboard(site_or_page, params))
+    }
+
+    pub fn live_page(&self, page_id: Uuid) -> Result<LiveHitsIter, Error> {
+	let hits = self.runtime.block_on(self.inner.live_page(page_id))?;
+	Ok(LiveHitsIter { hits, runtime: self.runtime.clone() })
+    }
+
+    pub fn live_site(&self, site: &str) -> Result<LiveHitsIter, Error> {
+	let hits = self.runtime.block_on(self.inner.live_site(site))?;
+	Ok(LiveHitsIter { hits, runtime: self.runtime.clone() })
+    }
+
+    pub fn webhooks(&self, page_id: Uuid) -> Result<Vec<Webhook>, Error> {
+	self.runtime.block_on(self.inner.webhooks(page_id))
+    }
+
+    pub fn create_webhook(&self, page_id: Uuid, webhook: &NewWebhook) -> Result<Webhook, Error> {
+	self.runtime.block_on(self.inner.create_webhook(page_id, webhook))
+    }
+
+    pub fn delete_webhook(&self, page_id: Uuid, webhook_id: Uuid) -> Result<(), Error> {
+	self.runtime.block_on(self.inner.delete_webhook(page_id, webhook_id))
+    }
+}
+
+/// Hits of a live stream. Each call of `next` waits for the
+/// next hit.
+pub struct LiveHitsIter {
+    hits: LiveHits,
+    runtime: Arc<Runtime>,
+}
+
+impl Iterator for LiveHitsIter {
+    type Item = Result<LiveHit, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+	self.runtime.block_on(self.hits.next())
+    }
+}
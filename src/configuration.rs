@@ -1,12 +1,80 @@
 use secrecy::{Secret, ExposeSecret};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
+use url::Url;
 
 #[derive(Clone, serde::Deserialize)]
 pub struct Settings {
+    #[serde(default)]
+    pub storage: StorageSettings,
     pub postgres: PostgresSettings,
     pub redis: RedisSettings,
     pub application: ApplicationSettings,
+    pub webhooks: WebhookSettings,
+    #[serde(default)]
+    pub write_behind: WriteBehindSettings,
+}
+
+#[derive(Clone, serde::Deserialize)]
+#[serde(default)]
+pub struct StorageSettings {
+    pub backend: StorageBackend,
+    /// File of the database of the `sqlite` backend. It's
+    /// created if it doesn't exist.
+    pub sqlite_path: String,
+    /// Where visitors are stored. Without one, they're in
+    /// Redis with the `postgres` backend, and in memory with
+    /// the others.
+    pub visitors: Option<VisitorBackend>,
+    /// Number of visits, and of active visitors, that are
+    /// kept in memory at most. The oldest ones are forgotten
+    /// first.
+    pub visitor_capacity: usize,
+}
+
+impl StorageSettings {
+    pub fn visitor_backend(&self) -> VisitorBackend {
+	self.visitors.unwrap_or(match self.backend {
+	    StorageBackend::Postgres => VisitorBackend::Redis,
+	    StorageBackend::Sqlite | StorageBackend::Memory => VisitorBackend::Memory,
+	})
+    }
+}
+
+impl Default for StorageSettings {
+    fn default() -> Self {
+	Self {
+	    backend: StorageBackend::Postgres,
+	    sqlite_path: "jhm.sqlite".into(),
+	    visitors: None,
+	    visitor_capacity: 100_000,
+	}
+    }
+}
+
+/// Where pages, hits and visitors are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// Pages and hits in Postgres, visitors in Redis. The
+    /// only backend that serves all routes.
+    Postgres,
+    /// Pages and hits in a SQLite file, visitors in memory.
+    Sqlite,
+    /// Everything in memory, until the service stops.
+    Memory,
+}
+
+/// Where the recent visitors of pages are stored, to count
+/// each one once per visit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VisitorBackend {
+    /// Shared by all instances of the service.
+    Redis,
+    /// Only known to the instance that saw the visitor, so
+    /// only for a single instance.
+    Memory,
 }
 
 #[derive(Clone, serde::Deserialize)]
@@ -48,6 +116,17 @@ pub struct RedisSettings {
     pub host: String,
     pub username: Option<String>,
     pub password: Option<Secret<String>>,
+    /// What happens to hits while Redis is down.
+    #[serde(default)]
+    pub fallback: RedisFallback,
+    /// Milliseconds that Redis is skipped for after a call to
+    /// it failed, before it's tried again.
+    #[serde(default = "default_retry_interval_ms")]
+    pub retry_interval_ms: u64,
+}
+
+fn default_retry_interval_ms() -> u64 {
+    10_000
 }
 
 impl RedisSettings {
@@ -62,6 +141,25 @@ impl RedisSettings {
 	    }
 	}
     }
+
+    pub fn retry_interval(&self) -> std::time::Duration {
+	std::time::Duration::from_millis(self.retry_interval_ms)
+    }
+}
+
+/// How hits are counted while Redis, which knows the recent
+/// visitors, is down.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RedisFallback {
+    /// Count every hit, even repeated ones of a visitor.
+    Count,
+    /// Count each visitor once per visit, as far as this
+    /// instance has seen it.
+    #[default]
+    Memory,
+    /// Don't count hits.
+    Drop,
 }
 
 #[derive(Clone, serde::Deserialize)]
@@ -69,9 +167,83 @@ pub struct ApplicationSettings {
     pub host: String,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
+    /// Public address of the service, used in the
+    /// snippets that are handed out. Without one, see
+    /// [`ApplicationSettings::public_url`].
+    #[serde(default)]
+    pub base_url: Option<Url>,
     /// Number of seconds until a visit by the same
     /// IP address counts as a new visit again.
     pub visit_duration: u64,
+    /// Header with the country code of the visitor, e.g.
+    /// `CF-IPCountry` behind Cloudflare. Without one, the
+    /// country of hits isn't recorded.
+    #[serde(default)]
+    pub country_header: Option<String>,
+    /// Number of instances of the service that run side by
+    /// side, if known. Only used to warn about settings that
+    /// don't work with more than one.
+    #[serde(default)]
+    pub replicas: Option<u32>,
+}
+
+impl ApplicationSettings {
+    /// Public address of the service. Without `base_url`, it's
+    /// the address the service listens on, which is only right
+    /// if it isn't behind a proxy.
+    pub fn public_url(&self) -> Url {
+	self.base_url.clone().unwrap_or_else(|| {
+	    Url::parse(&format!("http://{}:{}", self.host, self.port))
+		.expect("Failed to build the base URL from the host and port")
+	})
+    }
+}
+
+#[derive(Clone, serde::Deserialize)]
+pub struct WebhookSettings {
+    /// Milliseconds between checks for triggered webhooks
+    /// and deliveries that are due.
+    pub poll_interval_ms: u64,
+    /// Milliseconds until a failed delivery is retried. The
+    /// delay doubles with each attempt.
+    pub retry_backoff_ms: u64,
+    /// Number of attempts after which a delivery is dropped.
+    pub max_attempts: i32,
+    /// Let webhooks target loopback, private and link-local
+    /// addresses. Off by default, so that webhooks can't be
+    /// used to reach services behind the firewall.
+    #[serde(default)]
+    pub allow_private_targets: bool,
+}
+
+impl WebhookSettings {
+    pub fn poll_interval(&self) -> std::time::Duration {
+	std::time::Duration::from_millis(self.poll_interval_ms)
+    }
+}
+
+#[derive(Clone, serde::Deserialize)]
+pub struct WriteBehindSettings {
+    /// Buffer hits in Redis and write them to Postgres in
+    /// batches, instead of writing each one as it comes in.
+    pub enabled: bool,
+    /// Milliseconds between writes of the buffered hits.
+    pub flush_interval_ms: u64,
+}
+
+impl WriteBehindSettings {
+    pub fn flush_interval(&self) -> std::time::Duration {
+	std::time::Duration::from_millis(self.flush_interval_ms)
+    }
+}
+
+impl Default for WriteBehindSettings {
+    fn default() -> Self {
+	Self {
+	    enabled: false,
+	    flush_interval_ms: 5000,
+	}
+    }
 }
 
 pub enum Environment {
@@ -0,0 +1,107 @@
+use chrono::DateTime;
+use crate::utils::escape_html;
+
+pub const SECS_PER_DAY: i64 = 60 * 60 * 24;
+
+/// Everything that's shown on a dashboard.
+#[derive(Debug)]
+pub struct Dashboard {
+    pub title: String,
+    pub total_hits: i64,
+    /// Hits per day, oldest day first. Days are counted
+    /// since the Unix epoch.
+    pub daily_hits: Vec<(i64, i64)>,
+    pub top_referrers: Vec<(String, i64)>,
+}
+
+/// Fill in the days without hits, so that there's one entry
+/// for each day from `first_day` to `last_day`.
+pub fn fill_days(
+    hits: &[(i64, i64)],
+    first_day: i64,
+    last_day: i64,
+) -> Vec<(i64, i64)> {
+    (first_day..=last_day)
+	.map(|day| {
+	    let n = hits.iter()
+		.find(|(d, _)| *d == day)
+		.map_or(0, |(_, n)| *n);
+	    (day, n)
+	})
+	.collect()
+}
+
+fn format_day(day: i64) -> String {
+    DateTime::from_timestamp(day * SECS_PER_DAY, 0)
+	.map(|date| date.format("%Y-%m-%d").to_string())
+	.unwrap_or_default()
+}
+
+/// Render the dashboard as a HTML page. The bar chart is
+/// made of plain `<div>`s, so it works without JavaScript.
+pub fn render(dashboard: &Dashboard) -> String {
+    let title = escape_html(&dashboard.title);
+    let max = dashboard.daily_hits.iter()
+	.map(|(_, n)| *n)
+	.max()
+	.unwrap_or(0)
+	.max(1);
+    let bars: String = dashboard.daily_hits.iter()
+	.map(|(day, n)| format!(
+	    r#"<div class="bar" style="height:{}%" title="{}: {n}"></div>"#,
+	    n * 100 / max,
+	    format_day(*day),
+	))
+	.collect();
+    let (first, last) = match (dashboard.daily_hits.first(), dashboard.daily_hits.last()) {
+	(Some((first, _)), Some((last, _))) => (format_day(*first), format_day(*last)),
+	_ => Default::default(),
+    };
+    let referrers = if dashboard.top_referrers.is_empty() {
+	"<p>No referrers yet.</p>".to_string()
+    } else {
+	let rows: String = dashboard.top_referrers.iter()
+	    .map(|(referrer, n)| format!(
+		r#"<tr><td>{}</td><td class="n">{n}</td></tr>"#,
+		escape_html(referrer),
+	    ))
+	    .collect();
+	format!("<table>{rows}</table>")
+    };
+
+    format!(
+	r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="referrer" content="no-referrer">
+<title>{title} · Just How Many?</title>
+<style>
+body {{ font-family: system-ui, sans-serif; max-width: 48rem; margin: 2rem auto; padding: 0 1rem; color: #222; }}
+h1 {{ overflow-wrap: anywhere; }}
+.total {{ font-size: 3rem; font-weight: bold; margin: 0; }}
+.chart {{ display: flex; align-items: flex-end; gap: 2px; height: 10rem; border-bottom: 1px solid #999; }}
+.bar {{ flex: 1; min-height: 1px; background: #007ec6; }}
+.bar:hover {{ background: #005a8f; }}
+.axis {{ display: flex; justify-content: space-between; color: #666; font-size: .8rem; }}
+table {{ width: 100%; border-collapse: collapse; }}
+td {{ padding: .25rem 0; border-bottom: 1px solid #eee; overflow-wrap: anywhere; }}
+td.n {{ text-align: right; padding-left: 1rem; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p class="total">{total}</p>
+<p>hits in total</p>
+<h2>Daily hits</h2>
+<div class="chart">{bars}</div>
+<div class="axis"><span>{first}</span><span>{last}</span></div>
+<h2>Top referrers</h2>
+{referrers}
+</body>
+</html>
+"#,
+	total = dashboard.total_hits,
+    )
+}
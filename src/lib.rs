@@ -3,3 +3,13 @@ pub mod telemetry;
 pub mod routes;
 pub mod utils;
 pub mod configuration;
+pub mod snippet;
+pub mod authentication;
+pub mod badge;
+pub mod dashboard;
+pub mod webhooks;
+pub mod client;
+pub mod rollups;
+pub mod write_behind;
+pub mod storage;
+pub mod circuit_breaker;
@@ -0,0 +1,256 @@
+//! Hits per page, summed up per hour and per day, so that
+//! stats don't have to count the raw hits in `page_hits`.
+//!
+//! Each row counts the hits of a page that have a value of
+//! a dimension. Plain hits are counted under `total`, and
+//! under their referrer, country, device and campaign if they
+//! are known. Events are only counted under `event`.
+//!
+//! Campaigns are only rolled up for `/campaigns`, and can't be
+//! broken down by. Their values are JSON arrays of the
+//! `utm_source`, `utm_medium` and `utm_campaign`.
+use anyhow::Context;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+use crate::dashboard::SECS_PER_DAY;
+use crate::routes::{Campaign, HitDimensions};
+
+pub const SECS_PER_HOUR: i64 = 60 * 60;
+
+/// Dimension of the campaigns of plain hits.
+pub const CAMPAIGN: &str = "campaign";
+
+/// What hits are counted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Dimension {
+    /// All plain hits, with an empty value.
+    Total,
+    /// Events, by name.
+    Event,
+    Referrer,
+    /// Two letter country code, as given by the proxy.
+    Country,
+    /// `desktop`, `mobile`, `tablet` or `bot`.
+    Device,
+}
+
+impl Dimension {
+    pub fn as_str(&self) -> &'static str {
+	match self {
+	    Dimension::Total => "total",
+	    Dimension::Event => "event",
+	    Dimension::Referrer => "referrer",
+	    Dimension::Country => "country",
+	    Dimension::Device => "device",
+	}
+    }
+}
+
+/// Start of the hour of a Unix time.
+pub fn hour_of(timestamp: i64) -> i64 {
+    timestamp - timestamp.rem_euclid(SECS_PER_HOUR)
+}
+
+/// Start of the day of a Unix time.
+pub fn day_of(timestamp: i64) -> i64 {
+    timestamp - timestamp.rem_euclid(SECS_PER_DAY)
+}
+
+/// Value of a campaign in the rollups, if there is one. It's
+/// written like Postgres writes `jsonb`, so that rebuilt
+/// rollups have the same values.
+pub fn campaign_value(campaign: &Campaign) -> Option<String> {
+    if campaign.is_empty() {
+	return None;
+    }
+    let values: Vec<String> = [&campaign.utm_source, &campaign.utm_medium, &campaign.utm_campaign]
+	.iter()
+	.map(|value| serde_json::to_string(value).expect("Strings can be written as JSON"))
+	.collect();
+    Some(format!("[{}]", values.join(", ")))
+}
+
+/// Dimensions and values that a hit is counted under.
+fn values_of(
+    event: Option<&str>,
+    dimensions: &HitDimensions,
+) -> (Vec<&'static str>, Vec<String>) {
+    let values = match event {
+	Some(event) => vec![(Dimension::Event.as_str(), Some(event.to_string()))],
+	None => vec![
+	    (Dimension::Total.as_str(), Some(String::new())),
+	    (Dimension::Referrer.as_str(), dimensions.referrer.clone()),
+	    (Dimension::Country.as_str(), dimensions.country.clone()),
+	    (Dimension::Device.as_str(), dimensions.device.map(|device| device.as_str().to_string())),
+	    (CAMPAIGN, campaign_value(&dimensions.campaign)),
+	],
+    };
+    values.into_iter()
+	.filter_map(|(dimension, value)| Some((dimension, value?)))
+	.unzip()
+}
+
+/// Count a hit in the rollups. It should run in the same
+/// transaction that records the raw hit, so that a rebuild
+/// gives the same counts.
+pub async fn add_hit(
+    connection: &mut PgConnection,
+    page_id: Uuid,
+    timestamp: i64,
+    event: Option<&str>,
+    dimensions: &HitDimensions,
+) -> anyhow::Result<()> {
+    add_hits(connection, [(page_id, timestamp, event, dimensions)]).await
+}
+
+/// Count hits in the rollups, given by page, Unix time,
+/// event and dimensions, with one row update per page,
+/// dimension value and hour or day.
+#[tracing::instrument(
+    name = "Roll up hits",
+    skip_all
+)]
+pub async fn add_hits<'a>(
+    connection: &mut PgConnection,
+    hits: impl IntoIterator<Item = (Uuid, i64, Option<&'a str>, &'a HitDimensions)>,
+) -> anyhow::Result<()> {
+    let (mut page_ids, mut hours, mut days) = (vec![], vec![], vec![]);
+    let (mut names, mut values) = (vec![], vec![]);
+    for (page_id, timestamp, event, dimensions) in hits {
+	let (hit_names, hit_values) = values_of(event, dimensions);
+	for _ in 0..hit_names.len() {
+	    page_ids.push(page_id);
+	    hours.push(hour_of(timestamp));
+	    days.push(day_of(timestamp));
+	}
+	names.extend(hit_names);
+	values.extend(hit_values);
+    }
+    sqlx::query!(
+	r#"
+WITH dimensions AS (
+    SELECT *
+    FROM UNNEST($1::uuid[], $2::bigint[], $3::bigint[], $4::text[], $5::text[])
+    AS d (page_id, hour, day, dimension, value)
+), hourly AS (
+    INSERT INTO hits_hourly (page_id, dimension, value, hour, hits)
+    SELECT page_id, dimension, value, hour, COUNT(*)
+    FROM dimensions
+    GROUP BY 1, 2, 3, 4
+    ON CONFLICT (page_id, dimension, hour, value)
+    DO UPDATE SET hits = hits_hourly.hits + EXCLUDED.hits
+)
+INSERT INTO hits_daily (page_id, dimension, value, day, hits)
+SELECT page_id, dimension, value, day, COUNT(*)
+FROM dimensions
+GROUP BY 1, 2, 3, 4
+ON CONFLICT (page_id, dimension, day, value)
+DO UPDATE SET hits = hits_daily.hits + EXCLUDED.hits"#,
+	&page_ids,
+	&hours,
+	&days,
+	&names as &[&str],
+	&values,
+    )
+	.execute(connection)
+	.await
+	.context("Failed to roll up hits")?;
+    Ok(())
+}
+
+/// Recount the rollups from the raw hits, from the start of
+/// the day of `since` on, or all of them. Returns the number
+/// of hourly rows.
+///
+/// Hits from before `page_hits` only have a timestamp in
+/// `pages.timestamps`, so `total` counts those as well. Hits
+/// that are in both only count once.
+#[tracing::instrument(
+    name = "Rebuild rollups",
+    skip(pg_pool)
+)]
+pub async fn rebuild(pg_pool: &PgPool, since: Option<i64>) -> anyhow::Result<u64> {
+    let since = since.map_or(i64::MIN, day_of);
+    let mut transaction = pg_pool.begin()
+	.await
+	.context("Failed to start transaction")?;
+    sqlx::query!("DELETE FROM hits_hourly WHERE hour >= $1", since)
+	.execute(&mut *transaction)
+	.await
+	.context("Failed to delete hourly rollups")?;
+    sqlx::query!("DELETE FROM hits_daily WHERE day >= $1", since)
+	.execute(&mut *transaction)
+	.await
+	.context("Failed to delete daily rollups")?;
+    let dimension_rows = sqlx::query!(
+	r#"
+INSERT INTO hits_hourly (page_id, dimension, value, hour, hits)
+SELECT h.page_id, d.dimension, d.value, h.timestamp - h.timestamp % $2, COUNT(*)
+FROM page_hits h,
+     LATERAL (VALUES ('event', h.event),
+		     ('referrer', CASE WHEN h.event IS NULL THEN h.referrer END),
+		     ('country', CASE WHEN h.event IS NULL THEN h.country END),
+		     ('device', CASE WHEN h.event IS NULL THEN h.device END),
+		     ('campaign', CASE
+			 WHEN h.event IS NULL
+			  AND (h.utm_source IS NOT NULL
+			       OR h.utm_medium IS NOT NULL
+			       OR h.utm_campaign IS NOT NULL)
+			 THEN jsonb_build_array(h.utm_source, h.utm_medium, h.utm_campaign)::text
+		     END))
+     AS d (dimension, value)
+WHERE d.value IS NOT NULL
+  AND h.timestamp >= $1
+GROUP BY 1, 2, 3, 4"#,
+	since,
+	SECS_PER_HOUR,
+    )
+	.execute(&mut *transaction)
+	.await
+	.context("Failed to rebuild hourly rollups")?
+	.rows_affected();
+    let total_rows = sqlx::query!(
+	r#"
+WITH recorded AS (
+    SELECT page_id, timestamp, COUNT(*) AS n
+    FROM page_hits
+    WHERE event IS NULL AND timestamp >= $1
+    GROUP BY 1, 2
+), stamped AS (
+    SELECT p.page_id, t.timestamp, COUNT(*) AS n
+    FROM pages p, UNNEST(p.timestamps) AS t (timestamp)
+    WHERE t.timestamp >= $1
+    GROUP BY 1, 2
+)
+INSERT INTO hits_hourly (page_id, dimension, value, hour, hits)
+SELECT page_id, 'total', '', timestamp - timestamp % $2, SUM(GREATEST(r.n, s.n))::bigint
+FROM recorded r
+FULL JOIN stamped s USING (page_id, timestamp)
+GROUP BY 1, 4"#,
+	since,
+	SECS_PER_HOUR,
+    )
+	.execute(&mut *transaction)
+	.await
+	.context("Failed to rebuild hourly totals")?
+	.rows_affected();
+    sqlx::query!(
+	r#"
+INSERT INTO hits_daily (page_id, dimension, value, day, hits)
+SELECT page_id, dimension, value, hour - hour % $2, SUM(hits)::bigint
+FROM hits_hourly
+WHERE hour >= $1
+GROUP BY 1, 2, 3, 4"#,
+	since,
+	SECS_PER_DAY,
+    )
+	.execute(&mut *transaction)
+	.await
+	.context("Failed to rebuild daily rollups")?;
+    transaction.commit()
+	.await
+	.context("Failed to commit rebuilt rollups")?;
+    Ok(dimension_rows + total_rows)
+}
@@ -1,8 +1,38 @@
 mod health_check;
 pub use health_check::*;
+mod ready;
+pub use ready::*;
 mod hit;
 pub use hit::*;
 mod register;
 pub use register::*;
 mod hits;
 pub use hits::*;
+mod campaigns;
+pub use campaigns::*;
+mod snippet;
+pub use snippet::*;
+mod owners;
+pub use owners::*;
+mod pages;
+pub use pages::*;
+mod badge;
+pub use badge::*;
+mod dashboard;
+pub use dashboard::*;
+mod active;
+pub use active::*;
+mod webhooks;
+pub use webhooks::*;
+mod report;
+pub use report::*;
+mod live;
+pub use live::*;
+mod tags;
+pub use tags::*;
+mod compare;
+pub use compare::*;
+mod breakdown;
+pub use breakdown::*;
+mod openapi;
+pub use openapi::*;
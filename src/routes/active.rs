@@ -0,0 +1,133 @@
+use actix_web::{Responder, web};
+use sqlx::PgPool;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use std::collections::HashSet;
+use url::Url;
+use uuid::Uuid;
+use crate::circuit_breaker::FallibleRedis;
+use crate::utils::{e400, e500, e503, RedisPool, unix_time_secs};
+use redis::Commands;
+
+/// Visitors that were seen within this many seconds count
+/// as active.
+pub const ACTIVE_WINDOW_SECS: u64 = 5 * 60;
+
+fn active_key(page_id: Uuid) -> String {
+    format!("active/{page_id}")
+}
+
+/// Mark the visitor as active on the page.
+///
+/// Each page has a sorted set of visitors, scored by when they
+/// were last seen. Visitors that fall out of the window are
+/// dropped right away, so the set stays small.
+pub fn mark_active(
+    con: &mut impl redis::ConnectionLike,
+    page_id: Uuid,
+    addr: &str,
+    now: u64,
+) -> redis::RedisResult<()> {
+    let key = active_key(page_id);
+    redis::pipe()
+	.atomic()
+	.zadd(&key, addr, now).ignore()
+	.zrembyscore(&key, "-inf", now.saturating_sub(ACTIVE_WINDOW_SECS + 1)).ignore()
+	.expire(&key, ACTIVE_WINDOW_SECS as usize).ignore()
+	.query(con)
+}
+
+/// Number of distinct visitors that were active on any of the
+/// pages within the last [`ACTIVE_WINDOW_SECS`] seconds.
+#[tracing::instrument(
+    name = "Count active visitors",
+    skip(redis_pool)
+)]
+pub fn active_visitors(
+    page_ids: &[Uuid],
+    redis_pool: &RedisPool,
+) -> anyhow::Result<u64> {
+    let mut con = redis_pool.get()
+	.context("Failed to retrieve a connection")?;
+    let since = unix_time_secs().saturating_sub(ACTIVE_WINDOW_SECS);
+    let mut visitors = HashSet::new();
+    for page_id in page_ids {
+	let addrs: Vec<String> = con
+	    .zrangebyscore(active_key(*page_id), since, "+inf")
+	    .context("Failed to get active visitors")?;
+	visitors.extend(addrs);
+    }
+    Ok(visitors.len() as u64)
+}
+
+#[utoipa::path(
+    get,
+    path = "/active",
+    params(ActiveParams),
+    responses(
+	(status = 200, description = "Visitors in the last five minutes", body = ActiveVisitors),
+	(status = 400, description = "Not exactly one of `url` and `site`"),
+	(status = 503, description = "Redis is down"),
+    ),
+)]
+#[tracing::instrument(
+    name = "Retrieve the active visitors of a page or site",
+    skip(pg_pool, redis)
+)]
+pub async fn active(
+    query: web::Query<ActiveParams>,
+    pg_pool: web::Data<PgPool>,
+    redis: web::Data<FallibleRedis>,
+) -> actix_web::Result<impl Responder> {
+    let ActiveParams { url, site } = query.into_inner();
+    if url.is_some() == site.is_some() {
+	return Err(e400("Expected exactly one of `url` or `site`"));
+    }
+    let page_ids = page_ids_of(url.as_ref(), site.as_deref(), &pg_pool)
+	.await
+	.map_err(e500)?;
+    let n = redis.call(|redis_pool| active_visitors(&page_ids, redis_pool))
+	.ok_or_else(|| e503("Redis is down"))?
+	.map_err(e500)?;
+    Ok(web::Json(ActiveVisitors { n }))
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ActiveParams {
+    pub url: Option<Url>,
+    /// Host name of the pages to report on, e.g. `example.com`.
+    pub site: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct ActiveVisitors {
+    /// Visitors in the last five minutes.
+    pub n: u64,
+}
+
+/// IDs of the page with the URL, or of all pages of the site.
+#[tracing::instrument(
+    name = "Get page IDs",
+    skip(pg_pool)
+)]
+pub async fn page_ids_of(
+    url: Option<&Url>,
+    site: Option<&str>,
+    pg_pool: &PgPool,
+) -> anyhow::Result<Vec<Uuid>> {
+    let recs = sqlx::query!(
+	r#"
+SELECT page_id
+FROM pages
+WHERE ($1::text IS NULL OR url = $1)
+  AND ($2::text IS NULL OR site = $2)"#,
+	url.map(Url::as_str),
+	site,
+    )
+	.fetch_all(pg_pool)
+	.await
+	.context("Failed to get page IDs")?;
+    Ok(recs.into_iter().map(|rec| rec.page_id).collect())
+}
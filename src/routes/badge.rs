@@ -0,0 +1,63 @@
+use actix_web::{HttpResponse, web};
+use actix_web::http::header;
+use serde::{Deserialize, Serialize};
+use utoipa::IntoParams;
+use uuid::Uuid;
+use crate::badge::{format_count, render, Color, Style};
+use crate::storage::PageStore;
+use crate::utils::{e400, e500};
+
+/// Badges are cached for this many seconds.
+const BADGE_MAX_AGE: u32 = 60;
+const MAX_LABEL_LEN: usize = 64;
+
+#[utoipa::path(
+    get,
+    path = "/badge/{page_id}.svg",
+    params(("page_id" = Uuid, Path, description = "ID of the page"), BadgeParams),
+    responses(
+	(status = 200, description = "Badge with the hits of the page", body = String,
+	 content_type = "image/svg+xml"),
+	(status = 400, description = "Invalid label or color"),
+	(status = 404, description = "No page with a badge"),
+    ),
+)]
+#[tracing::instrument(
+    name = "Render the badge of a page",
+    skip(pages)
+)]
+pub async fn badge(
+    path: web::Path<Uuid>,
+    query: web::Query<BadgeParams>,
+    pages: web::Data<dyn PageStore>,
+) -> actix_web::Result<HttpResponse> {
+    let page_id = path.into_inner();
+    let BadgeParams { label, color, style } = query.into_inner();
+    let color = match color {
+	Some(color) => Color::parse(&color).map_err(e400)?,
+	None => Color::default(),
+    };
+    let label = label.unwrap_or_else(|| "views".to_string());
+    if label.chars().count() > MAX_LABEL_LEN {
+	return Err(e400(format!("The label is longer than {MAX_LABEL_LEN} characters")));
+    }
+
+    // Pages without a badge look the same as pages that don't exist.
+    let Some(hits) = pages.badge_hits(page_id).await.map_err(e500)? else {
+	return Ok(HttpResponse::NotFound().finish());
+    };
+    Ok(HttpResponse::Ok()
+       .content_type("image/svg+xml; charset=utf-8")
+       .insert_header((header::CACHE_CONTROL, format!("public, max-age={BADGE_MAX_AGE}")))
+       .body(render(&label, &format_count(hits.into()), &color, style)))
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BadgeParams {
+    pub label: Option<String>,
+    pub color: Option<String>,
+    #[serde(default)]
+    #[param(inline)]
+    pub style: Style,
+}
@@ -0,0 +1,108 @@
+use actix_web::{HttpRequest, Responder, web};
+use sqlx::PgPool;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use crate::authentication::{authenticate, authorize_page};
+use crate::rollups::{day_of, Dimension};
+use crate::storage::PageStore;
+use crate::utils::{e400, e500};
+
+/// Most values of a dimension that can be asked for.
+pub const MAX_BREAKDOWN_LIMIT: i64 = 1000;
+
+#[derive(Debug, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BreakdownParams {
+    #[param(inline)]
+    pub dimension: Dimension,
+    /// Unix time to count hits from. Hits are counted by the
+    /// day, so it's rounded down to the start of its day.
+    pub since: Option<i64>,
+    /// Unix time to count hits until, excluded.
+    pub until: Option<i64>,
+    /// Number of values with the most hits. Defaults to 10.
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct DimensionHits {
+    pub value: String,
+    pub n: i64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/pages/{page_id}/breakdown",
+    params(("page_id" = Uuid, Path, description = "ID of the page"), BreakdownParams),
+    responses(
+	(status = 200, description = "Hits per value of the dimension, most first",
+	 body = [DimensionHits]),
+	(status = 400, description = "Invalid dimension or limit"),
+	(status = 401, description = "Missing or invalid API key"),
+	(status = 404, description = "No page of the owner"),
+    ),
+    security(("api_key" = [])),
+)]
+#[tracing::instrument(
+    name = "Break down the hits of a page",
+    skip(req, pg_pool, pages)
+)]
+pub async fn breakdown(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<BreakdownParams>,
+    pg_pool: web::Data<PgPool>,
+    pages: web::Data<dyn PageStore>,
+) -> actix_web::Result<impl Responder> {
+    let page_id = path.into_inner();
+    let owner_id = authenticate(&req, pages.as_ref()).await?;
+    authorize_page(owner_id, page_id, pages.as_ref()).await?;
+    let params = query.into_inner();
+    let limit = params.limit.unwrap_or(10);
+    if !(1..=MAX_BREAKDOWN_LIMIT).contains(&limit) {
+	return Err(e400(format!("Expected a limit from 1 to {MAX_BREAKDOWN_LIMIT}")));
+    }
+    let hits = hits_per_value(page_id, &params, limit, &pg_pool)
+	.await
+	.map_err(e500)?;
+    Ok(web::Json(hits))
+}
+
+#[tracing::instrument(
+    name = "Get hits per value of a dimension",
+    skip(pg_pool)
+)]
+async fn hits_per_value(
+    page_id: Uuid,
+    params: &BreakdownParams,
+    limit: i64,
+    pg_pool: &PgPool,
+) -> anyhow::Result<Vec<DimensionHits>> {
+    let records = sqlx::query!(
+	r#"
+SELECT value, SUM(hits)::bigint AS "n!"
+FROM hits_daily
+WHERE page_id = $1
+  AND dimension = $2
+  AND day >= $3
+  AND day < $4
+GROUP BY value
+ORDER BY "n!" DESC, value
+LIMIT $5
+"#,
+	page_id,
+	params.dimension.as_str(),
+	params.since.map_or(i64::MIN, day_of),
+	params.until.unwrap_or(i64::MAX),
+	limit,
+    )
+	.fetch_all(pg_pool)
+	.await
+	.context("Failed to get hits per value")?;
+    Ok(records
+       .into_iter()
+       .map(|r| DimensionHits { value: r.value, n: r.n })
+       .collect())
+}
@@ -0,0 +1,136 @@
+use actix_web::{Responder, web};
+use sqlx::PgPool;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use url::Url;
+use crate::rollups;
+use crate::utils::{e400, e500};
+
+/// UTM values longer than this are cut off before they are stored.
+const MAX_UTM_LEN: usize = 255;
+
+/// Campaign dimensions of a hit. They are taken from the
+/// `utm_*` query parameters of the URL in the `Referer` header.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Campaign {
+    pub utm_source: Option<String>,
+    pub utm_medium: Option<String>,
+    pub utm_campaign: Option<String>,
+}
+
+impl Campaign {
+    pub fn from_url(url: &Url) -> Self {
+	let mut campaign = Self::default();
+	for (key, value) in url.query_pairs() {
+	    let field = match key.as_ref() {
+		"utm_source" => &mut campaign.utm_source,
+		"utm_medium" => &mut campaign.utm_medium,
+		"utm_campaign" => &mut campaign.utm_campaign,
+		_ => continue,
+	    };
+	    let value: String = value.chars().take(MAX_UTM_LEN).collect();
+	    if !value.is_empty() {
+		*field = Some(value);
+	    }
+	}
+	campaign
+    }
+
+    pub fn is_empty(&self) -> bool {
+	self.utm_source.is_none()
+	    && self.utm_medium.is_none()
+	    && self.utm_campaign.is_none()
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/campaigns",
+    params(CampaignsParams),
+    responses(
+	(status = 200, description = "Hits per campaign", body = [CampaignHits]),
+	(status = 400, description = "Not exactly one of `url` and `site`"),
+    ),
+)]
+#[tracing::instrument(
+    name = "Retrieve the campaigns of a page or site",
+    skip(pg_pool)
+)]
+pub async fn campaigns(
+    query: web::Query<CampaignsParams>,
+    pg_pool: web::Data<PgPool>,
+) -> actix_web::Result<impl Responder> {
+    let CampaignsParams { url, site } = query.into_inner();
+    if url.is_some() == site.is_some() {
+	return Err(e400("Expected exactly one of `url` or `site`"));
+    }
+    let campaigns = campaigns_of_pages(url, site, &pg_pool)
+	.await
+	.map_err(e500)?;
+    Ok(web::Json(campaigns))
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CampaignsParams {
+    pub url: Option<Url>,
+    /// Host name of the pages to report on, e.g. `example.com`.
+    pub site: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct CampaignHits {
+    #[serde(flatten)]
+    pub campaign: Campaign,
+    pub n: i64,
+}
+
+/// Campaign hits of the pages, from the daily rollups.
+#[tracing::instrument(
+    name = "Get campaign hits of pages",
+    skip(pg_pool)
+)]
+async fn campaigns_of_pages(
+    url: Option<Url>,
+    site: Option<String>,
+    pg_pool: &PgPool,
+) -> anyhow::Result<Vec<CampaignHits>> {
+    let records = sqlx::query!(
+	r#"
+SELECT c.campaign ->> 0 AS utm_source,
+       c.campaign ->> 1 AS utm_medium,
+       c.campaign ->> 2 AS utm_campaign,
+       c.n AS "n!"
+FROM (
+    SELECT r.value::jsonb AS campaign, SUM(r.hits)::bigint AS n
+    FROM hits_daily r
+    JOIN pages p ON p.page_id = r.page_id
+    WHERE r.dimension = $3
+      AND ($1::text IS NULL OR p.url = $1)
+      AND ($2::text IS NULL OR p.site = $2)
+    GROUP BY 1
+) c
+ORDER BY c.n DESC
+"#,
+	url.as_ref().map(Url::as_str),
+	site,
+	rollups::CAMPAIGN,
+    )
+	.fetch_all(pg_pool)
+	.await
+	.context("Failed to get campaign hits")?;
+    Ok(records
+       .into_iter()
+       .map(|r| CampaignHits {
+	   campaign: Campaign {
+	       utm_source: r.utm_source,
+	       utm_medium: r.utm_medium,
+	       utm_campaign: r.utm_campaign,
+	   },
+	   n: r.n,
+       })
+       .collect())
+}
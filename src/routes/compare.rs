@@ -0,0 +1,201 @@
+use actix_web::{HttpRequest, Responder, web};
+use actix_web::error::ErrorNotFound;
+use sqlx::PgPool;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use crate::authentication::authenticate;
+use crate::storage::PageStore;
+use crate::utils::{e400, e500};
+
+/// Most pages that can be compared by ID with one request.
+pub const MAX_COMPARED_PAGES: usize = 1000;
+
+/// Time range in Unix time. It includes `since`, but not `until`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+pub struct TimeRange {
+    pub since: i64,
+    pub until: i64,
+}
+
+/// Pages to compare, and the two time ranges to compare
+/// their hits in.
+#[derive(Debug, Default, Deserialize, Serialize, ToSchema)]
+pub struct ComparisonQuery {
+    /// Pages to compare. Either these or `tag` are given.
+    #[serde(default)]
+    pub page_ids: Vec<Uuid>,
+    /// Compare all pages with this tag.
+    pub tag: Option<String>,
+    /// Only compare the pages of this site with the tag.
+    pub site: Option<String>,
+    pub current: Option<TimeRange>,
+    /// Range to compare the current one to, usually the one
+    /// right before it.
+    pub previous: Option<TimeRange>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct Comparison {
+    pub current: TimeRange,
+    pub previous: TimeRange,
+    /// Pages by rank in the current range.
+    pub pages: Vec<PageComparison>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct PageComparison {
+    pub page_id: Uuid,
+    pub url: String,
+    pub title: Option<String>,
+    /// Hits in the current range.
+    pub hits: i64,
+    /// Hits in the previous range.
+    pub previous_hits: i64,
+    /// `hits - previous_hits`.
+    pub change: i64,
+    /// Change relative to the previous hits, or `null` if
+    /// there were none.
+    pub change_percent: Option<f64>,
+    /// Rank by hits in the current range. Pages with the same
+    /// hits share a rank.
+    pub rank: i64,
+    /// Rank by hits in the previous range.
+    pub previous_rank: i64,
+}
+
+// Compare the hits of pages in two time ranges. Only hits
+// with a timestamp are counted, by the hour in which they
+// happened, so ranges should start and end at full hours.
+// Only the pages of the owner can be compared.
+#[utoipa::path(
+    post,
+    path = "/compare",
+    request_body = ComparisonQuery,
+    responses(
+	(status = 200, description = "Hits of the pages in both ranges", body = Comparison),
+	(status = 400, description = "Invalid pages or time ranges"),
+	(status = 401, description = "Missing or invalid API key"),
+	(status = 404, description = "Unknown page IDs, or pages of other owners"),
+    ),
+    security(("api_key" = [])),
+)]
+#[tracing::instrument(
+    name = "Compare pages",
+    skip(req, query, pg_pool, pages),
+    fields(pages = query.page_ids.len(), tag = ?query.tag)
+)]
+pub async fn compare(
+    req: HttpRequest,
+    query: web::Json<ComparisonQuery>,
+    pg_pool: web::Data<PgPool>,
+    pages: web::Data<dyn PageStore>,
+) -> actix_web::Result<impl Responder> {
+    let owner_id = authenticate(&req, pages.as_ref()).await?;
+    let query = query.into_inner();
+    let (current, previous) = validate(&query).map_err(e400)?;
+    let pages = compare_pages(owner_id, &query, current, previous, &pg_pool)
+	.await
+	.map_err(e500)?;
+    let unknown: Vec<String> = query.page_ids.iter()
+	.filter(|id| !pages.iter().any(|page| page.page_id == **id))
+	.map(Uuid::to_string)
+	.collect();
+    if !unknown.is_empty() {
+	return Err(ErrorNotFound(format!("Unknown page IDs: {}", unknown.join(", "))));
+    }
+    Ok(web::Json(Comparison { current, previous, pages }))
+}
+
+fn validate(query: &ComparisonQuery) -> Result<(TimeRange, TimeRange), String> {
+    if query.page_ids.is_empty() == query.tag.is_none() {
+	return Err("Expected exactly one of `page_ids` or `tag`".to_string());
+    }
+    if query.page_ids.len() > MAX_COMPARED_PAGES {
+	return Err(format!("Expected at most {MAX_COMPARED_PAGES} page IDs"));
+    }
+    if query.site.is_some() && query.tag.is_none() {
+	return Err("`site` only works with `tag`".to_string());
+    }
+    let (Some(current), Some(previous)) = (query.current, query.previous) else {
+	return Err("Expected the `current` and `previous` time ranges".to_string());
+    };
+    for (name, range) in [("current", current), ("previous", previous)] {
+	if range.since >= range.until {
+	    return Err(format!("The {name} range ends before it starts"));
+	}
+    }
+    Ok((current, previous))
+}
+
+#[tracing::instrument(
+    name = "Get hits of pages in two time ranges",
+    skip(query, pg_pool)
+)]
+async fn compare_pages(
+    owner_id: Uuid,
+    query: &ComparisonQuery,
+    current: TimeRange,
+    previous: TimeRange,
+    pg_pool: &PgPool,
+) -> anyhow::Result<Vec<PageComparison>> {
+    let records = sqlx::query!(
+	r#"
+WITH selected AS (
+    SELECT page_id, url, title
+    FROM pages
+    WHERE owner = $8
+      AND (page_id = ANY($1)
+	   OR ($2::text IS NOT NULL
+	       AND tags @> ARRAY[$2]
+	       AND ($3::text IS NULL OR site = $3)))
+), counts AS (
+    SELECT s.page_id, s.url, s.title,
+	   COALESCE(SUM(r.hits) FILTER (WHERE r.hour >= $4 AND r.hour < $5), 0)::bigint AS hits,
+	   COALESCE(SUM(r.hits) FILTER (WHERE r.hour >= $6 AND r.hour < $7), 0)::bigint AS previous_hits
+    FROM selected s
+    LEFT JOIN hits_hourly r
+      ON r.page_id = s.page_id
+     AND r.dimension = 'total'
+     AND ((r.hour >= $4 AND r.hour < $5)
+	  OR (r.hour >= $6 AND r.hour < $7))
+    GROUP BY s.page_id, s.url, s.title
+)
+SELECT page_id, url, title,
+       hits AS "hits!",
+       previous_hits AS "previous_hits!",
+       hits - previous_hits AS "change!",
+       ROUND(100.0 * (hits - previous_hits) / NULLIF(previous_hits, 0), 1)::float8 AS change_percent,
+       RANK() OVER (ORDER BY hits DESC) AS "rank!",
+       RANK() OVER (ORDER BY previous_hits DESC) AS "previous_rank!"
+FROM counts
+ORDER BY "rank!", url
+"#,
+	&query.page_ids,
+	query.tag,
+	query.site,
+	current.since,
+	current.until,
+	previous.since,
+	previous.until,
+	owner_id,
+    )
+	.fetch_all(pg_pool)
+	.await
+	.context("Failed to compare pages")?;
+    Ok(records
+       .into_iter()
+       .map(|r| PageComparison {
+	   page_id: r.page_id,
+	   url: r.url,
+	   title: r.title,
+	   hits: r.hits,
+	   previous_hits: r.previous_hits,
+	   change: r.change,
+	   change_percent: r.change_percent,
+	   rank: r.rank,
+	   previous_rank: r.previous_rank,
+       })
+       .collect())
+}
@@ -0,0 +1,208 @@
+use actix_web::{HttpResponse, web};
+use actix_web::http::header;
+use sqlx::PgPool;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use utoipa::IntoParams;
+use uuid::Uuid;
+use crate::dashboard::{fill_days, render, Dashboard, SECS_PER_DAY};
+use crate::utils::{e500, unix_time_secs};
+
+/// Number of days in the bar chart.
+const CHART_DAYS: i64 = 30;
+const TOP_REFERRERS: i64 = 10;
+
+// Show the dashboard of a page, or of all public pages of
+// a site. Pages are only shown if they are public, or if
+// the request carries the secret of the page's share link.
+#[utoipa::path(
+    get,
+    path = "/dashboard/{site_or_page}",
+    params(
+	("site_or_page" = String, Path, description = "Host name of a site, or ID of a page"),
+	DashboardParams,
+    ),
+    responses(
+	(status = 200, description = "Dashboard of the public pages", body = String,
+	 content_type = "text/html"),
+	(status = 404, description = "No public pages, or a wrong secret"),
+    ),
+)]
+#[tracing::instrument(
+    name = "Show dashboard",
+    skip(query, pg_pool)
+)]
+pub async fn dashboard(
+    path: web::Path<String>,
+    query: web::Query<DashboardParams>,
+    pg_pool: web::Data<PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    let site_or_page = path.into_inner();
+    let secret = query.into_inner().secret;
+    let pages = match Uuid::parse_str(&site_or_page) {
+	Ok(page_id) => visible_page(page_id, secret.as_deref(), &pg_pool).await,
+	Err(_) => public_pages_of_site(&site_or_page, &pg_pool).await,
+    }
+	.map_err(e500)?;
+    let Some(pages) = pages else {
+	return Ok(HttpResponse::NotFound().finish());
+    };
+
+    let today = unix_time_secs() as i64 / SECS_PER_DAY;
+    let first_day = today - CHART_DAYS + 1;
+    let daily_hits = daily_hits(&pages.page_ids, first_day, &pg_pool)
+	.await
+	.map_err(e500)?;
+    let top_referrers = top_referrers(&pages.page_ids, &pg_pool)
+	.await
+	.map_err(e500)?;
+    let dashboard = Dashboard {
+	title: pages.title,
+	total_hits: pages.total_hits,
+	daily_hits: fill_days(&daily_hits, first_day, today),
+	top_referrers,
+    };
+    Ok(HttpResponse::Ok()
+       .content_type("text/html; charset=utf-8")
+       // Keep the secret out of the `Referer` of outgoing requests.
+       .insert_header((header::REFERRER_POLICY, "no-referrer"))
+       .body(render(&dashboard)))
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DashboardParams {
+    pub secret: Option<String>,
+}
+
+/// The pages that a dashboard is about.
+#[derive(Debug)]
+struct DashboardPages {
+    title: String,
+    page_ids: Vec<Uuid>,
+    total_hits: i64,
+}
+
+#[tracing::instrument(
+    name = "Get visible page",
+    skip(secret, pg_pool)
+)]
+async fn visible_page(
+    page_id: Uuid,
+    secret: Option<&str>,
+    pg_pool: &PgPool,
+) -> anyhow::Result<Option<DashboardPages>> {
+    let rec = sqlx::query!(
+	r#"
+SELECT url, hits, public, share_secret
+FROM pages
+WHERE page_id = $1"#,
+	page_id,
+    )
+	.fetch_optional(pg_pool)
+	.await
+	.context("Failed to get page")?;
+    Ok(rec
+       .filter(|rec| rec.public || matches_secret(rec.share_secret.as_deref(), secret))
+       .map(|rec| DashboardPages {
+	   title: rec.url,
+	   page_ids: vec![page_id],
+	   total_hits: rec.hits.into(),
+       }))
+}
+
+/// Whether the given share secret is the page's, compared in
+/// constant time so that it can't be guessed byte by byte.
+fn matches_secret(share_secret: Option<&str>, secret: Option<&str>) -> bool {
+    match (share_secret, secret) {
+	(Some(share_secret), Some(secret)) => {
+	    share_secret.as_bytes().ct_eq(secret.as_bytes()).into()
+	},
+	_ => false,
+    }
+}
+
+#[tracing::instrument(
+    name = "Get public pages of site",
+    skip(pg_pool)
+)]
+async fn public_pages_of_site(
+    site: &str,
+    pg_pool: &PgPool,
+) -> anyhow::Result<Option<DashboardPages>> {
+    let records = sqlx::query!(
+	r#"
+SELECT page_id, hits
+FROM pages
+WHERE site = $1 AND public"#,
+	site,
+    )
+	.fetch_all(pg_pool)
+	.await
+	.context("Failed to get public pages of site")?;
+    if records.is_empty() {
+	return Ok(None);
+    }
+    Ok(Some(DashboardPages {
+	title: site.to_string(),
+	total_hits: records.iter().map(|rec| i64::from(rec.hits)).sum(),
+	page_ids: records.into_iter().map(|rec| rec.page_id).collect(),
+    }))
+}
+
+#[tracing::instrument(
+    name = "Get daily hits",
+    skip(pg_pool)
+)]
+async fn daily_hits(
+    page_ids: &[Uuid],
+    first_day: i64,
+    pg_pool: &PgPool,
+) -> anyhow::Result<Vec<(i64, i64)>> {
+    let records = sqlx::query!(
+	r#"
+SELECT day / $2 AS "day!", SUM(hits)::bigint AS "n!"
+FROM hits_daily
+WHERE page_id = ANY($1)
+  AND dimension = 'total'
+  AND day >= $3
+GROUP BY "day!"
+ORDER BY "day!"
+"#,
+	page_ids,
+	SECS_PER_DAY,
+	first_day * SECS_PER_DAY,
+    )
+	.fetch_all(pg_pool)
+	.await
+	.context("Failed to get daily hits")?;
+    Ok(records.into_iter().map(|rec| (rec.day, rec.n)).collect())
+}
+
+#[tracing::instrument(
+    name = "Get top referrers",
+    skip(pg_pool)
+)]
+async fn top_referrers(
+    page_ids: &[Uuid],
+    pg_pool: &PgPool,
+) -> anyhow::Result<Vec<(String, i64)>> {
+    let records = sqlx::query!(
+	r#"
+SELECT value AS referrer, SUM(hits)::bigint AS "n!"
+FROM hits_daily
+WHERE page_id = ANY($1)
+  AND dimension = 'referrer'
+GROUP BY value
+ORDER BY "n!" DESC, value
+LIMIT $2
+"#,
+	page_ids,
+	TOP_REFERRERS,
+    )
+	.fetch_all(pg_pool)
+	.await
+	.context("Failed to get top referrers")?;
+    Ok(records.into_iter().map(|rec| (rec.referrer, rec.n)).collect())
+}
@@ -1,5 +1,35 @@
-use actix_web::HttpResponse;
+use actix_web::{HttpResponse, web};
+use utoipa::ToSchema;
+use crate::circuit_breaker::CircuitBreaker;
 
-pub async fn health_check() -> HttpResponse {
-    HttpResponse::Ok().finish()
-}
\ No newline at end of file
+#[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize, ToSchema)]
+pub struct Health {
+    /// Whether the last call to Redis succeeded. Missing if
+    /// the service doesn't use Redis.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redis: Option<ComponentStatus>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ComponentStatus {
+    Up,
+    Down,
+}
+
+// The service is up as long as it answers. While Redis is
+// down, hits are counted as the `redis.fallback` setting says.
+#[utoipa::path(
+    get,
+    path = "/health_check",
+    responses((status = 200, description = "The service is up", body = Health)),
+)]
+pub async fn health_check(
+    redis_circuit: Option<web::Data<CircuitBreaker>>,
+) -> HttpResponse {
+    let redis = redis_circuit.map(|circuit| match circuit.is_open() {
+	true => ComponentStatus::Down,
+	false => ComponentStatus::Up,
+    });
+    HttpResponse::Ok().json(Health { redis })
+}
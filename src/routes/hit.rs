@@ -1,118 +1,237 @@
 use actix_web::{HttpRequest, HttpResponse, web};
-use sqlx::PgPool;
-use anyhow::Context;
+use actix_web::http::header;
 use uuid::Uuid;
-use crate::utils::{e500, RedisPool, hash_data};
-use redis::Commands;
-use std::time::{SystemTime, UNIX_EPOCH};
+use crate::circuit_breaker::FallibleRedis;
+use crate::utils::{e400, e500, hash_data};
+use crate::routes::{Campaign, publish_hit};
+use crate::startup::CountryHeader;
+use crate::storage::{PageStore, VisitStatus, VisitorStore};
+use crate::write_behind::{BufferedHit, HitBuffer};
+use url::Url;
 
+#[utoipa::path(
+    get,
+    path = "/hit/{page_id}",
+    params(("page_id" = Uuid, Path, description = "ID of the page")),
+    responses((status = 200, description = "Counted, unless the visitor has been seen recently")),
+)]
 #[tracing::instrument(
     name = "Register page hit",
-    skip(pg_pool, redis_pool, req, visit_duration)
+    skip(pages, visitors, redis, hit_buffer, req, visit_duration)
 )]
 pub async fn hit(
     req: HttpRequest,
     path: web::Path<Uuid>,
     visit_duration: web::Data<u64>,
-    pg_pool: web::Data<PgPool>,
-    redis_pool: web::Data<RedisPool>,
+    pages: web::Data<dyn PageStore>,
+    visitors: web::Data<dyn VisitorStore>,
+    redis: Option<web::Data<FallibleRedis>>,
+    hit_buffer: web::Data<Option<HitBuffer>>,
 ) -> actix_web::Result<HttpResponse> {
     let page_id: uuid::Uuid = path.into_inner();
+    let dimensions = HitDimensions::from_request(&req);
+    let addr = visitor_addr(&req)?;
+    let redis = redis.as_ref().map(|redis| redis.get_ref());
 
-    let addr = hash_data(&req.peer_addr()
-			 .ok_or_else(|| e500("Missing IP address"))?
-			 .ip());
-
-    let visit = check_in_visitor(page_id, addr,
-				 *visit_duration.get_ref(), &redis_pool)
+    let visit = visitors.check_in(page_id, None, addr, *visit_duration.get_ref())
 	.await
         .map_err(e500)?;
     if visit == VisitStatus::New {
-	increment_hit(page_id, &pg_pool)
+	record_hit(page_id, None, &dimensions, &**pages, redis, hit_buffer.get_ref().as_ref())
 	    .await
 	    .map_err(e500)?;
+	// Live streams need Redis. The hit is counted either
+	// way, so a failure only means that live streams miss
+	// it.
+	if let Some(redis) = redis {
+	    let published = redis.call(|redis_pool| publish_hit(page_id, &dimensions, redis_pool));
+	    if let Some(Err(e)) = published {
+		tracing::warn!("Failed to publish live hit: {e:?}");
+	    }
+	}
     }
 
-    
     Ok(HttpResponse::Ok().finish())
 }
 
+/// Everything that's recorded about a hit besides its page.
+#[derive(Debug, Default, Clone, serde::Deserialize, serde::Serialize)]
+pub struct HitDimensions {
+    pub campaign: Campaign,
+    /// URL in the `Referer` header, without query and fragment.
+    pub referrer: Option<String>,
+    /// Two letter country code from the header that the proxy
+    /// in front of the service sets, if it's configured.
+    pub country: Option<String>,
+    pub device: Option<Device>,
+}
+
+impl HitDimensions {
+    pub fn from_request(req: &HttpRequest) -> Self {
+	let header_value = |name: &str| req.headers()
+	    .get(name)
+	    .and_then(|value| value.to_str().ok());
+	let referrer = header_value(header::REFERER.as_str())
+	    .and_then(|referer| Url::parse(referer).ok());
+	let country = req.app_data::<web::Data<CountryHeader>>()
+	    .and_then(|country_header| country_header.0.as_deref())
+	    .and_then(header_value)
+	    .map(str::trim)
+	    .filter(|code| code.len() == 2 && code.chars().all(|c| c.is_ascii_alphanumeric()))
+	    .map(str::to_ascii_uppercase);
+	Self {
+	    campaign: referrer
+		.as_ref()
+		.map(Campaign::from_url)
+		.unwrap_or_default(),
+	    referrer: referrer.map(|mut referrer| {
+		referrer.set_query(None);
+		referrer.set_fragment(None);
+		referrer.into()
+	    }),
+	    country,
+	    device: header_value(header::USER_AGENT.as_str()).map(Device::from_user_agent),
+	}
+    }
+}
+
+/// Kind of device that a hit comes from, as far as its
+/// `User-Agent` tells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Device {
+    Desktop,
+    Mobile,
+    Tablet,
+    Bot,
+}
+
+impl Device {
+    pub fn from_user_agent(user_agent: &str) -> Self {
+	let user_agent = user_agent.to_ascii_lowercase();
+	let has = |parts: &[&str]| parts.iter().any(|part| user_agent.contains(part));
+	if has(&["bot", "crawl", "spider", "slurp", "curl/", "wget/"]) {
+	    Device::Bot
+	} else if has(&["ipad", "tablet", "kindle", "silk/"]) {
+	    Device::Tablet
+	} else if has(&["mobi", "iphone", "ipod", "android"]) {
+	    // Android tablets don't say "mobile".
+	    if has(&["android"]) && !has(&["mobi"]) {
+		Device::Tablet
+	    } else {
+		Device::Mobile
+	    }
+	} else {
+	    Device::Desktop
+	}
+    }
+
+    pub fn as_str(&self) -> &'static str {
+	match self {
+	    Device::Desktop => "desktop",
+	    Device::Mobile => "mobile",
+	    Device::Tablet => "tablet",
+	    Device::Bot => "bot",
+	}
+    }
+}
+
+/// Name of an event that's tracked in addition to
+/// plain page hits, e.g. `read-to-end`.
+#[derive(Debug, Clone)]
+pub struct EventName(String);
+
+impl EventName {
+    pub fn parse(s: String) -> Result<Self, String> {
+	let is_valid_char = |c: char| {
+	    c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_'
+	};
+	if s.is_empty() || s.len() > 64 || !s.chars().all(is_valid_char) {
+	    Err(format!(
+		"{s:?} is not a valid event name. Use 1 to 64 \
+		 lowercase letters, digits, `-` or `_`."
+	    ))
+	} else {
+	    Ok(Self(s))
+	}
+    }
+}
+
+impl AsRef<str> for EventName {
+    fn as_ref(&self) -> &str {
+	&self.0
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/hit/{page_id}/event/{name}",
+    params(
+	("page_id" = Uuid, Path, description = "ID of the page"),
+	("name" = String, Path, description = "Name of the event, e.g. `signup`"),
+    ),
+    responses(
+	(status = 200, description = "Counted, unless the visitor has been seen recently"),
+	(status = 400, description = "Invalid event name"),
+    ),
+)]
 #[tracing::instrument(
-    name = "Increment page hits",
-    skip (pg_pool)
+    name = "Register page event",
+    skip(pages, visitors, redis, hit_buffer, req, visit_duration)
 )]
-pub async fn increment_hit(
-    page_id: uuid::Uuid,
-    pg_pool: &PgPool,
-) -> anyhow::Result<()>
-{
-    // i64 because of sqlx' type constraints.
-    let now: i64 = unix_time_secs()
-	.try_into()
-	.expect("It's not 2100");
-    sqlx::query!(
-	r#"
-UPDATE pages
-SET hits = hits + 1,
-    timestamps = ARRAY_APPEND(timestamps, $1)
-WHERE page_id = $2"#,
-	now,
-	page_id
-    )
-	.execute(pg_pool)
+pub async fn hit_event(
+    req: HttpRequest,
+    path: web::Path<(Uuid, String)>,
+    visit_duration: web::Data<u64>,
+    pages: web::Data<dyn PageStore>,
+    visitors: web::Data<dyn VisitorStore>,
+    redis: Option<web::Data<FallibleRedis>>,
+    hit_buffer: web::Data<Option<HitBuffer>>,
+) -> actix_web::Result<HttpResponse> {
+    let (page_id, event) = path.into_inner();
+    let event = EventName::parse(event).map_err(e400)?;
+    let dimensions = HitDimensions::from_request(&req);
+    let addr = visitor_addr(&req)?;
+    let redis = redis.as_ref().map(|redis| redis.get_ref());
+
+    let visit = visitors.check_in(page_id, Some(event.as_ref()), addr, *visit_duration.get_ref())
 	.await
-	.context("Failed to increase hits count")?;
-    Ok(())
+	.map_err(e500)?;
+    if visit == VisitStatus::New {
+	let event = Some(event.as_ref());
+	record_hit(page_id, event, &dimensions, &**pages, redis, hit_buffer.get_ref().as_ref())
+	    .await
+	    .map_err(e500)?;
+    }
+
+    Ok(HttpResponse::Ok().finish())
 }
 
-/// Check if the given IP address has been seen before
-/// in the last 12 hours.
-#[tracing::instrument(
-    name = "Check-in visiting IP address",
-    skip(redis_pool)
-)]
-async fn check_in_visitor(
+/// Write a counted hit, to the buffer in write-behind mode,
+/// which needs Redis. While Redis is down, the hit is written
+/// right away instead.
+async fn record_hit(
     page_id: Uuid,
-    addr: u64,
-    visit_duration: u64,
-    redis_pool: &RedisPool
-) -> anyhow::Result<VisitStatus> {
-    let page_id = &page_id.to_string();
-    let addr = &addr.to_string();
-    let mut con = redis_pool.get()
-        .context("Failed to retrieve a connection")?;
-
-    let now = unix_time_secs();
-
-    if con.hexists(page_id, addr)? {
-	let expiry: u64 = con.hget(page_id, addr)?;
-	if now - expiry > visit_duration {
-	    tracing::info!("New visit (but seen before)");
-	    con.hset(page_id, addr, now.to_string())?;
-	    Ok(VisitStatus::New)
-	} else {
-	    tracing::info!("Visitor has been seen before");
-	    Ok(VisitStatus::Old)
+    event: Option<&str>,
+    dimensions: &HitDimensions,
+    pages: &dyn PageStore,
+    redis: Option<&FallibleRedis>,
+    hit_buffer: Option<&HitBuffer>,
+) -> anyhow::Result<()> {
+    if let (Some(hit_buffer), Some(redis)) = (hit_buffer, redis) {
+	let hit = BufferedHit::now(page_id, event, dimensions);
+	match redis.call(|redis_pool| hit_buffer.push(redis_pool, &hit)) {
+	    Some(Ok(())) => return Ok(()),
+	    Some(Err(e)) => tracing::warn!("Failed to buffer hit, writing it right away: {e:?}"),
+	    None => {},
 	}
-    } else {
-	tracing::info!("New visit");
-	con.hset(page_id, addr, now.to_string())?;
-	Ok(VisitStatus::New)
     }
+    pages.record_hit(page_id, event, dimensions).await
 }
 
-#[derive(Debug, PartialEq, Eq)]
-enum VisitStatus {
-    /// Visitor hasn't been seen in the last twelve hours.
-    New,
-    /// Visitor has been seen in the last twelve hours.
-    Old,
-}
-
-fn unix_time_secs() -> u64 {
-    let start = SystemTime::now();
-    start
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_secs()
+fn visitor_addr(req: &HttpRequest) -> actix_web::Result<u64> {
+    Ok(hash_data(&req.peer_addr()
+		 .ok_or_else(|| e500("Missing IP address"))?
+		 .ip()))
 }
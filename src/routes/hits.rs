@@ -1,56 +1,81 @@
 use actix_web::{Responder, web};
-use sqlx::PgPool;
-use anyhow::Context;
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use utoipa::openapi::{KnownFormat, ObjectBuilder, Required, SchemaFormat, SchemaType};
+use utoipa::openapi::path::{Parameter, ParameterBuilder, ParameterIn};
+use std::collections::BTreeMap;
 use url::Url;
 use crate::utils::e500;
+use crate::routes::Campaign;
+use crate::storage::{PageStore, VisitorStore};
 
+#[utoipa::path(
+    get,
+    path = "/hits",
+    params(HitsParams),
+    responses(
+	(status = 200, description = "Hits of the page", body = Hits),
+	(status = 400, description = "Invalid URL"),
+    ),
+)]
 #[tracing::instrument(
     name = "Retrieve the hits a page has",
-    skip(pg_pool)
+    skip(pages, visitors)
 )]
 pub async fn hits(
     query: web::Query<HitsParams>,
-    pg_pool: web::Data<PgPool>,
+    pages: web::Data<dyn PageStore>,
+    visitors: web::Data<dyn VisitorStore>,
 ) -> actix_web::Result<impl Responder> {
-    let hits = hits_of_page_url(query.into_inner().url, &pg_pool)
+    let HitsParams { url, campaign } = query.into_inner();
+    let page_ids = pages.page_ids(&url)
+	.await
+	.map_err(e500)?;
+    let active_visitors = visitors.active_visitors(&page_ids)
 	.await
 	.map_err(e500)?;
+    let mut hits = pages.hits(&url, &campaign)
+	.await
+	.map_err(e500)?;
+    hits.active_visitors = active_visitors;
     Ok(web::Json(hits))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct HitsParams {
-    url: Url,
+    pub url: Url,
+    /// Only count hits that belong to this campaign.
+    #[serde(flatten)]
+    pub campaign: Campaign,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+// The derive can't flatten the campaign into the parameters.
+impl IntoParams for HitsParams {
+    fn into_params(_: impl Fn() -> Option<ParameterIn>) -> Vec<Parameter> {
+	let url = ParameterBuilder::new()
+	    .name("url")
+	    .parameter_in(ParameterIn::Query)
+	    .required(Required::True)
+	    .description(Some("URL of the page"))
+	    .schema(Some(ObjectBuilder::new()
+			 .schema_type(SchemaType::String)
+			 .format(Some(SchemaFormat::KnownFormat(KnownFormat::Uri)))))
+	    .build();
+	let mut params = vec![url];
+	params.extend(Campaign::into_params(|| Some(ParameterIn::Query)));
+	params
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct Hits {
     pub n: i32,
     pub timestamps: Vec<i64>,
-}
-
-#[tracing::instrument(
-    name = "Get hits of page url",
-    skip(pg_pool)
-)]
-async fn hits_of_page_url(
-    url: Url,
-    pg_pool: &PgPool,
-) -> anyhow::Result<Hits> {
-    let record = sqlx::query!(
-	r#"
-SELECT hits, timestamps
-FROM pages
-WHERE url = $1
-"#,
-	url.as_str(),
-    )
-	.fetch_one(pg_pool)
-	.await
-	.with_context(|| format!("Failed to get hits of page url: {}", url))?;
-    Ok(Hits {
-	n: record.hits,
-	timestamps: record.timestamps.unwrap_or_else(|| vec![]),
-    })
+    /// Number of times each event was triggered.
+    #[serde(default)]
+    pub events: BTreeMap<String, i64>,
+    /// Visitors in the last five minutes, regardless of
+    /// the campaign.
+    #[serde(default)]
+    pub active_visitors: u64,
 }
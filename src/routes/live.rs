@@ -0,0 +1,211 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use actix_web::http::header;
+use actix_web::web::Bytes;
+use futures_util::StreamExt;
+use sqlx::PgPool;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use crate::authentication::{authenticate, authorize_page};
+use crate::circuit_breaker::FallibleRedis;
+use crate::routes::HitDimensions;
+use crate::storage::PageStore;
+use crate::utils::{e500, e503, RedisPool, unix_time_secs};
+use redis::Commands;
+
+/// Seconds after which an idle stream gets a comment, so
+/// that proxies don't close it.
+const KEEP_ALIVE_SECS: u64 = 15;
+
+/// A counted hit, as sent to live streams.
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct LiveHit {
+    pub page_id: Uuid,
+    pub timestamp: i64,
+    pub referrer: Option<String>,
+}
+
+/// Redis client for subscriptions, which need a connection
+/// of their own for as long as a stream is open.
+pub struct LiveClient(pub redis::Client);
+
+fn live_channel(page_id: Uuid) -> String {
+    format!("live/{page_id}")
+}
+
+/// Tell the live streams of the page about a counted hit.
+#[tracing::instrument(
+    name = "Publish live hit",
+    skip(redis_pool)
+)]
+pub fn publish_hit(
+    page_id: Uuid,
+    dimensions: &HitDimensions,
+    redis_pool: &RedisPool,
+) -> anyhow::Result<()> {
+    let hit = LiveHit {
+	page_id,
+	timestamp: unix_time_secs() as i64,
+	referrer: dimensions.referrer.clone(),
+    };
+    let payload = serde_json::to_string(&hit)
+	.context("Failed to encode live hit")?;
+    let mut con = redis_pool.get()
+	.context("Failed to retrieve a connection")?;
+    con.publish::<_, _, ()>(live_channel(page_id), payload)
+	.context("Failed to publish live hit")?;
+    Ok(())
+}
+
+// Stream an event per counted hit of the page. Only the
+// owner of the page may follow it.
+#[utoipa::path(
+    get,
+    path = "/pages/{page_id}/live",
+    params(("page_id" = Uuid, Path, description = "ID of the page")),
+    responses(
+	(status = 200, description = "Server-sent `hit` events", body = LiveHit,
+	 content_type = "text/event-stream"),
+	(status = 401, description = "Missing or invalid API key"),
+	(status = 404, description = "No page of the owner"),
+	(status = 503, description = "Redis is down"),
+    ),
+    security(("api_key" = [])),
+)]
+#[tracing::instrument(
+    name = "Stream live page hits",
+    skip(req, pages, live_client, redis)
+)]
+pub async fn live_page(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    pages: web::Data<dyn PageStore>,
+    live_client: web::Data<LiveClient>,
+    redis: web::Data<FallibleRedis>,
+) -> actix_web::Result<HttpResponse> {
+    let page_id = path.into_inner();
+    let owner_id = authenticate(&req, pages.as_ref()).await?;
+    authorize_page(owner_id, page_id, pages.as_ref()).await?;
+    stream_hits(&[page_id], &live_client.0, &redis).await
+}
+
+// Stream an event per counted hit of the pages on the site
+// that belong to the owner. Pages that are registered after
+// the stream has been opened aren't part of it.
+#[utoipa::path(
+    get,
+    path = "/sites/{site}/live",
+    params(("site" = String, Path, description = "Host name of the pages, e.g. `example.com`")),
+    responses(
+	(status = 200, description = "Server-sent `hit` events", body = LiveHit,
+	 content_type = "text/event-stream"),
+	(status = 401, description = "Missing or invalid API key"),
+	(status = 404, description = "No pages of the owner on the site"),
+	(status = 503, description = "Redis is down"),
+    ),
+    security(("api_key" = [])),
+)]
+#[tracing::instrument(
+    name = "Stream live site hits",
+    skip(req, pg_pool, pages, live_client, redis)
+)]
+pub async fn live_site(
+    req: HttpRequest,
+    path: web::Path<String>,
+    pg_pool: web::Data<PgPool>,
+    pages: web::Data<dyn PageStore>,
+    live_client: web::Data<LiveClient>,
+    redis: web::Data<FallibleRedis>,
+) -> actix_web::Result<HttpResponse> {
+    let site = path.into_inner();
+    let owner_id = authenticate(&req, pages.as_ref()).await?;
+    let page_ids = owned_pages_of_site(&site, owner_id, &pg_pool)
+	.await
+	.map_err(e500)?;
+    if page_ids.is_empty() {
+	return Ok(HttpResponse::NotFound().finish());
+    }
+    stream_hits(&page_ids, &live_client.0, &redis).await
+}
+
+/// Stream the hits of the pages. Streams aren't opened while
+/// Redis is down, as they wouldn't get any hits.
+async fn stream_hits(
+    page_ids: &[Uuid],
+    client: &redis::Client,
+    redis: &FallibleRedis,
+) -> actix_web::Result<HttpResponse> {
+    if !redis.circuit.try_call() {
+	return Err(e503("Redis is down"));
+    }
+    let pubsub = subscribe(page_ids, client).await;
+    redis.circuit.record(&pubsub);
+    let messages = pubsub.map_err(e500)?.into_on_message();
+
+    // Comments are ignored by clients, but tell them that
+    // the stream is open.
+    let connected = futures_util::stream::once(async {
+	Ok::<_, actix_web::Error>(Bytes::from_static(b": connected\n\n"))
+    });
+    let events = futures_util::stream::unfold(messages, |mut messages| async {
+	let keep_alive = std::time::Duration::from_secs(KEEP_ALIVE_SECS);
+	let event = match tokio::time::timeout(keep_alive, messages.next()).await {
+	    Ok(Some(msg)) => match msg.get_payload::<String>() {
+		Ok(payload) => format!("event: hit\ndata: {payload}\n\n"),
+		Err(e) => {
+		    tracing::warn!("Dropping malformed live hit: {e}");
+		    ": dropped\n\n".to_string()
+		},
+	    },
+	    // Redis went away, so there won't be any more hits.
+	    Ok(None) => return None,
+	    Err(_) => ": keep-alive\n\n".to_string(),
+	};
+	Some((Ok::<_, actix_web::Error>(Bytes::from(event)), messages))
+    });
+
+    Ok(HttpResponse::Ok()
+       .content_type("text/event-stream")
+       .insert_header((header::CACHE_CONTROL, "no-cache"))
+       .streaming(connected.chain(events)))
+}
+
+async fn subscribe(
+    page_ids: &[Uuid],
+    client: &redis::Client,
+) -> anyhow::Result<redis::aio::PubSub> {
+    let mut pubsub = client.get_async_connection()
+	.await
+	.context("Failed to connect to Redis")?
+	.into_pubsub();
+    for page_id in page_ids {
+	pubsub.subscribe(live_channel(*page_id))
+	    .await
+	    .context("Failed to subscribe to live hits")?;
+    }
+    Ok(pubsub)
+}
+
+#[tracing::instrument(
+    name = "Get owned pages of site",
+    skip(pg_pool)
+)]
+async fn owned_pages_of_site(
+    site: &str,
+    owner_id: Uuid,
+    pg_pool: &PgPool,
+) -> anyhow::Result<Vec<Uuid>> {
+    let recs = sqlx::query!(
+	r#"
+SELECT page_id
+FROM pages
+WHERE site = $1 AND owner = $2"#,
+	site,
+	owner_id,
+    )
+	.fetch_all(pg_pool)
+	.await
+	.context("Failed to get pages of site")?;
+    Ok(recs.into_iter().map(|rec| rec.page_id).collect())
+}
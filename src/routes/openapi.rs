@@ -0,0 +1,118 @@
+use actix_web::HttpResponse;
+use utoipa::{Modify, OpenApi};
+use utoipa::openapi::{Content, PathItemType, Ref};
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use crate::rollups::Dimension;
+use crate::routes::*;
+
+/// Name of the security scheme of the owners' API keys.
+pub const API_KEY_SCHEME: &str = "api_key";
+
+/// OpenAPI document of the first version of the API. Its paths
+/// are relative to `/v1`.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+	title = "Just How Many?",
+	description = "Count the hits of web pages without tracking their readers.",
+    ),
+    servers((url = "/v1")),
+    paths(
+	health_check,
+	ready,
+	hit,
+	hit_event,
+	register,
+	register_batch,
+	hits,
+	campaigns,
+	active,
+	tags,
+	tag_hits,
+	compare,
+	report,
+	new_owner,
+	update_page,
+	snippet,
+	live_page,
+	breakdown,
+	live_site,
+	webhooks,
+	create_webhook,
+	delete_webhook,
+	badge,
+	dashboard,
+    ),
+    components(schemas(
+	ActiveVisitors,
+	BatchRegistration,
+	BatchResult,
+	Campaign,
+	CampaignHits,
+	Comparison,
+	ComparisonQuery,
+	ComponentStatus,
+	Dimension,
+	DimensionHits,
+	Health,
+	Hits,
+	LiveHit,
+	NewOwner,
+	NewPage,
+	NewWebhook,
+	Page,
+	PageComparison,
+	PageReport,
+	PageUpdate,
+	Period,
+	Readiness,
+	ReferrerHits,
+	RegisterPageForm,
+	Report,
+	Spike,
+	TagHits,
+	TagSummary,
+	TaggedPage,
+	TimeRange,
+	Webhook,
+    )),
+    modifiers(&ApiKeyScheme, &RegisterForm),
+)]
+pub struct ApiDoc;
+
+struct ApiKeyScheme;
+
+impl Modify for ApiKeyScheme {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+	let scheme = HttpBuilder::new()
+	    .scheme(HttpAuthScheme::Bearer)
+	    .description(Some("API key of the owner, from `POST /owners`"))
+	    .build();
+	openapi.components
+	    .get_or_insert_with(Default::default)
+	    .add_security_scheme(API_KEY_SCHEME, SecurityScheme::Http(scheme));
+    }
+}
+
+/// The derive documents one content type per request body, so
+/// the form that `/register` also takes is added here.
+struct RegisterForm;
+
+impl Modify for RegisterForm {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+	let body = openapi.paths.paths
+	    .get_mut("/register")
+	    .and_then(|item| item.operations.get_mut(&PathItemType::Post))
+	    .and_then(|operation| operation.request_body.as_mut());
+	if let Some(body) = body {
+	    body.content.insert(
+		"application/x-www-form-urlencoded".to_string(),
+		Content::new(Ref::from_schema_name("RegisterPageForm")),
+	    );
+	}
+    }
+}
+
+pub async fn openapi() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
@@ -0,0 +1,31 @@
+use actix_web::{web, Responder};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use crate::authentication::create_owner;
+use crate::storage::PageStore;
+use crate::utils::e500;
+
+// Create a new owner. Pages that are registered with the
+// returned API key can only be managed with that key.
+#[utoipa::path(
+    post,
+    path = "/owners",
+    responses((status = 200, description = "The new owner", body = NewOwner)),
+)]
+#[tracing::instrument(
+    name = "Create a new owner",
+    skip(pages)
+)]
+pub async fn new_owner(
+    pages: web::Data<dyn PageStore>,
+) -> actix_web::Result<impl Responder> {
+    let (owner_id, api_key) = create_owner(pages.get_ref()).await.map_err(e500)?;
+    Ok(web::Json(NewOwner { owner_id, api_key }))
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct NewOwner {
+    pub owner_id: Uuid,
+    pub api_key: String,
+}
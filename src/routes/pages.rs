@@ -0,0 +1,100 @@
+use actix_web::{web, HttpRequest, Responder};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use url::Url;
+use uuid::Uuid;
+use crate::authentication::{authenticate, authorize_page};
+use crate::startup::ApplicationBaseUrl;
+use crate::storage::PageStore;
+use crate::routes::{clean_group, clean_tags, clean_title};
+use crate::utils::{e400, e500};
+
+/// A page as seen by its owner.
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct Page {
+    pub page_id: Uuid,
+    pub url: String,
+    pub title: Option<String>,
+    pub tags: Vec<String>,
+    pub group: Option<String>,
+    pub hits: i32,
+    /// Whether the page has a public badge.
+    pub badge: bool,
+    /// Whether the dashboard of the page is public.
+    pub public: bool,
+    /// Secret link to the dashboard of the page, if any.
+    pub share_link: Option<Url>,
+}
+
+/// Changes to a page. Missing fields are left as they are.
+#[derive(Debug, Default, Deserialize, Serialize, ToSchema)]
+pub struct PageUpdate {
+    pub badge: Option<bool>,
+    pub public: Option<bool>,
+    /// `true` creates a new secret link to the dashboard,
+    /// replacing the old one. `false` revokes the link.
+    pub share_link: Option<bool>,
+    /// An empty title removes it.
+    pub title: Option<String>,
+    /// Replaces all tags of the page.
+    pub tags: Option<Vec<String>>,
+    /// An empty group removes it.
+    pub group: Option<String>,
+}
+
+impl PageUpdate {
+    /// Clean the metadata like at registration, but keep
+    /// empty values, since they remove the old ones.
+    fn validate(mut self) -> Result<Self, String> {
+	let keep_empty = |value: Option<String>| value.unwrap_or_default();
+	self.title = self.title.map(|title| clean_title(&title).map(keep_empty)).transpose()?;
+	self.tags = self.tags.map(|tags| clean_tags(&tags)).transpose()?;
+	self.group = self.group.map(|group| clean_group(&group).map(keep_empty)).transpose()?;
+	Ok(self)
+    }
+}
+
+#[utoipa::path(
+    patch,
+    path = "/pages/{page_id}",
+    params(("page_id" = Uuid, Path, description = "ID of the page")),
+    request_body = PageUpdate,
+    responses(
+	(status = 200, description = "The updated page", body = Page),
+	(status = 400, description = "Invalid metadata"),
+	(status = 401, description = "Missing or invalid API key"),
+	(status = 404, description = "No page of the owner"),
+    ),
+    security(("api_key" = [])),
+)]
+#[tracing::instrument(
+    name = "Update page",
+    skip(req, base_url, pages)
+)]
+pub async fn update_page(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    update: web::Json<PageUpdate>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    pages: web::Data<dyn PageStore>,
+) -> actix_web::Result<impl Responder> {
+    let page_id = path.into_inner();
+    let owner_id = authenticate(&req, pages.as_ref()).await?;
+    authorize_page(owner_id, page_id, pages.as_ref()).await?;
+    let update = update.into_inner().validate().map_err(e400)?;
+    let page = pages.update_page(page_id, &update, &base_url.0)
+	.await
+	.map_err(e500)?;
+    Ok(web::Json(page))
+}
+
+/// Secret link to the dashboard of a page.
+pub fn share_link(base_url: &Url, page_id: Uuid, share_secret: &str) -> Url {
+    let mut link = base_url.clone();
+    link.path_segments_mut()
+	.expect("Base URL cannot be a base")
+	.pop_if_empty()
+	.extend(["dashboard", &page_id.to_string()]);
+    link.query_pairs_mut().append_pair("secret", share_secret);
+    link
+}
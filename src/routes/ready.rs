@@ -0,0 +1,99 @@
+use std::time::Duration;
+use actix_web::{HttpResponse, web};
+use anyhow::Context;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use crate::circuit_breaker::FallibleRedis;
+use crate::configuration::RedisFallback;
+use crate::routes::ComponentStatus;
+use crate::utils::{RedisPool, ping_redis};
+
+/// Time that each component has to answer.
+const PING_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize, ToSchema)]
+pub struct Readiness {
+    /// Missing if the service doesn't use Postgres.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub postgres: Option<ComponentStatus>,
+    /// Missing if the service doesn't use Redis.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redis: Option<ComponentStatus>,
+    /// Whether the service is ready, but Redis is down, so
+    /// hits are counted with the fallback of `redis.fallback`.
+    pub degraded: bool,
+}
+
+// Unlike `/health_check`, which only tells that the service
+// is up, this checks Postgres and Redis, for readiness probes.
+// Redis being down only makes the service unready if hits
+// are dropped without it.
+#[utoipa::path(
+    get,
+    path = "/ready",
+    responses(
+	(status = 200, description = "Ready to count hits, maybe `degraded`", body = Readiness),
+	(status = 503, description = "Postgres, or Redis with the `drop` fallback, doesn't answer",
+	 body = Readiness),
+    ),
+)]
+#[tracing::instrument(
+    name = "Check readiness",
+    skip(pg_pool, redis, redis_fallback)
+)]
+pub async fn ready(
+    pg_pool: Option<web::Data<PgPool>>,
+    redis: Option<web::Data<FallibleRedis>>,
+    redis_fallback: web::Data<RedisFallback>,
+) -> HttpResponse {
+    let postgres = match pg_pool {
+	Some(pg_pool) => Some(status(ping_postgres(&pg_pool).await, "Postgres")),
+	None => None,
+    };
+    let redis = match redis {
+	Some(redis) => {
+	    let result = ping_redis_in_time(redis.pool.clone()).await;
+	    // Hits use Redis again right away if it's back.
+	    redis.circuit.record(&result);
+	    Some(status(result, "Redis"))
+	},
+	None => None,
+    };
+
+    // Postgres is needed, but Redis only if hits can't be
+    // counted without it.
+    let down = |status: Option<ComponentStatus>| status == Some(ComponentStatus::Down);
+    let redis_is_needed = **redis_fallback == RedisFallback::Drop;
+    let is_ready = !down(postgres) && (!redis_is_needed || !down(redis));
+    let readiness = Readiness { postgres, redis, degraded: is_ready && down(redis) };
+    if is_ready {
+	HttpResponse::Ok().json(readiness)
+    } else {
+	HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
+
+async fn ping_postgres(pg_pool: &PgPool) -> anyhow::Result<()> {
+    tokio::time::timeout(PING_TIMEOUT, sqlx::query("SELECT 1").execute(pg_pool))
+	.await
+	.context("Timed out")?
+	.context("Failed to query Postgres")?;
+    Ok(())
+}
+
+async fn ping_redis_in_time(redis_pool: RedisPool) -> anyhow::Result<()> {
+    tokio::time::timeout(PING_TIMEOUT, web::block(move || ping_redis(&redis_pool)))
+	.await
+	.context("Timed out")?
+	.context("Failed to ping Redis")?
+}
+
+fn status(result: anyhow::Result<()>, component: &str) -> ComponentStatus {
+    match result {
+	Ok(()) => ComponentStatus::Up,
+	Err(e) => {
+	    tracing::warn!(error.cause_chain = ?e, "{component} isn't ready");
+	    ComponentStatus::Down
+	},
+    }
+}
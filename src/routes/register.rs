@@ -1,66 +1,230 @@
-use actix_web::{web, Responder};
-use sqlx::PgPool;
-use serde::Deserialize;
+use actix_web::{dev::Payload, web, FromRequest, HttpMessage, HttpRequest, Responder};
+use actix_web::error::ErrorUnsupportedMediaType;
+use futures_util::future::{FutureExt, LocalBoxFuture};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use url::Url;
 use uuid::Uuid;
-use anyhow::Context;
-use crate::utils::e500;
+use crate::utils::{e400, e500};
+use crate::authentication::try_authenticate;
+use crate::storage::PageStore;
 
 // Register a new page with a given URL.
 // Returns the UUID that will be used to refer to that page.
+// If the request carries an API key, the page belongs to
+// its owner. Otherwise, nobody can manage the page.
+// The metadata is only stored if the page is new.
+#[utoipa::path(
+    post,
+    path = "/register",
+    request_body = NewPage,
+    responses(
+	(status = 200, description = "ID of the page", body = Uuid,
+	 content_type = "application/json"),
+	(status = 400, description = "Invalid URL or metadata"),
+	(status = 401, description = "Invalid API key"),
+	(status = 415, description = "Neither JSON nor a form"),
+    ),
+    security((), ("api_key" = [])),
+)]
 #[tracing::instrument(
     name = "Register page by URL",
-    skip(db_pool)
+    skip(req, pages)
 )]
 pub async fn register(
-    form: web::Form<RegisterPageForm>,
-    db_pool: web::Data<PgPool>,
+    req: HttpRequest,
+    page: NewPage,
+    pages: web::Data<dyn PageStore>,
 ) -> actix_web::Result<impl Responder> {
-    let url = form.into_inner().url;
-    let page_id = insert_page(url, &db_pool).await.map_err(e500)?;
+    let owner = try_authenticate(&req, pages.get_ref())
+	.await?
+	.unwrap_or_else(Uuid::new_v4);
+    let page = page.validate().map_err(e400)?;
+    let page_id = pages.register(&page, owner).await.map_err(e500)?;
     Ok(web::Json(page_id))
 }
 
-#[derive(Debug, Deserialize)]
+/// Longest title of a page.
+pub const MAX_TITLE_LEN: usize = 200;
+/// Longest tag of a page.
+pub const MAX_TAG_LEN: usize = 50;
+/// Most tags that a page can have.
+pub const MAX_TAGS: usize = 20;
+/// Longest group of a page.
+pub const MAX_GROUP_LEN: usize = 100;
+
+/// Page to register.
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct NewPage {
+    pub url: Url,
+    /// Name of the page, for people.
+    pub title: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Section of the site, e.g. `blog` or `docs`.
+    pub group: Option<String>,
+    /// Site that the page belongs to. Defaults to the host
+    /// name of the URL.
+    pub site: Option<String>,
+}
+
+impl NewPage {
+    pub fn new(url: Url) -> Self {
+	Self { url, title: None, tags: vec![], group: None, site: None }
+    }
+
+    /// Trim the metadata, drop empty values and duplicate tags,
+    /// and check the limits.
+    fn validate(mut self) -> Result<Self, String> {
+	self.title = self.title.map(|title| clean_title(&title)).transpose()?.flatten();
+	self.tags = clean_tags(&self.tags)?;
+	self.group = self.group.map(|group| clean_group(&group)).transpose()?.flatten();
+	self.site = self.site
+	    .map(|site| site.trim().to_string())
+	    .filter(|site| !site.is_empty());
+	Ok(self)
+    }
+
+    /// Site of the page, the host name of the URL by default.
+    pub(crate) fn site(&self) -> &str {
+	self.site.as_deref().or(self.url.host_str()).unwrap_or_default()
+    }
+}
+
+/// Trimmed title, or `None` if it's blank.
+pub(crate) fn clean_title(title: &str) -> Result<Option<String>, String> {
+    let title = title.trim();
+    if title.chars().count() > MAX_TITLE_LEN {
+	return Err(format!("The title is longer than {MAX_TITLE_LEN} characters"));
+    }
+    Ok(Some(title.to_string()).filter(|title| !title.is_empty()))
+}
+
+/// Trimmed tags, without blank and duplicate ones.
+pub(crate) fn clean_tags(tags: &[String]) -> Result<Vec<String>, String> {
+    let mut cleaned: Vec<String> = vec![];
+    for tag in tags {
+	let tag = tag.trim();
+	if tag.chars().count() > MAX_TAG_LEN {
+	    return Err(format!("The tag {tag:?} is longer than {MAX_TAG_LEN} characters"));
+	}
+	if !tag.is_empty() && !cleaned.iter().any(|t| t == tag) {
+	    cleaned.push(tag.to_string());
+	}
+    }
+    if cleaned.len() > MAX_TAGS {
+	return Err(format!("Expected at most {MAX_TAGS} tags"));
+    }
+    Ok(cleaned)
+}
+
+/// Trimmed group, or `None` if it's blank.
+pub(crate) fn clean_group(group: &str) -> Result<Option<String>, String> {
+    let group = group.trim();
+    if group.chars().count() > MAX_GROUP_LEN {
+	return Err(format!("The group is longer than {MAX_GROUP_LEN} characters"));
+    }
+    Ok(Some(group.to_string()).filter(|group| !group.is_empty()))
+}
+
+/// The registration as a form, which only carries the URL.
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct RegisterPageForm {
-    url: Url,
+    pub url: Url,
+}
+
+// Pages are registered with JSON or a form, depending on the
+// content type.
+impl FromRequest for NewPage {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+	let mime = req.mime_type().ok().flatten();
+	match mime.as_ref().map(|mime| (mime.type_(), mime.subtype(), mime.suffix())) {
+	    Some((_, actix_web::mime::JSON, _)) | Some((_, _, Some(actix_web::mime::JSON))) => {
+		web::Json::<NewPage>::from_request(req, payload)
+		    .map(|page| Ok(page?.into_inner()))
+		    .boxed_local()
+	    },
+	    Some((actix_web::mime::APPLICATION, actix_web::mime::WWW_FORM_URLENCODED, _)) => {
+		web::Form::<RegisterPageForm>::from_request(req, payload)
+		    .map(|form| Ok(NewPage::new(form?.into_inner().url)))
+		    .boxed_local()
+	    },
+	    _ => async {
+		Err(ErrorUnsupportedMediaType(
+		    "Expected application/json or application/x-www-form-urlencoded"
+		))
+	    }
+		.boxed_local(),
+	}
+    }
+}
+
+/// Most URLs that can be registered with one request.
+pub const MAX_BATCH_SIZE: usize = 1000;
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct BatchRegistration {
+    pub urls: Vec<String>,
+}
+
+/// Outcome of registering one URL of a batch. Exactly one
+/// of `page_id` and `error` is set.
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct BatchResult {
+    pub url: String,
+    pub page_id: Option<Uuid>,
+    pub error: Option<String>,
 }
 
+// Register many pages at once. Invalid URLs don't fail the
+// whole batch, they are reported in the result of the URL.
+// The results are in the order of the URLs in the request.
+#[utoipa::path(
+    post,
+    path = "/register/batch",
+    request_body = BatchRegistration,
+    responses(
+	(status = 200, description = "Outcome of each URL, in order", body = [BatchResult]),
+	(status = 400, description = "Too many URLs"),
+	(status = 401, description = "Invalid API key"),
+    ),
+    security((), ("api_key" = [])),
+)]
 #[tracing::instrument(
-    name = "Insert a new page",
-    skip(db_pool)
+    name = "Register pages by URL",
+    skip(req, batch, pages),
+    fields(batch_size = batch.urls.len())
 )]
-async fn insert_page(
-    url: Url,
-    db_pool: &PgPool,
-) -> anyhow::Result<Uuid> {
-    let rec = sqlx::query!(
-	r#"
-SELECT page_id
-FROM pages
-WHERE url = $1"#,
-	url.as_str(),
-    )
-	.fetch_optional(db_pool)
-	.await
-	.context("Failed to check if page exists")?;
-    match rec {
-	Some (rec) => Ok(rec.page_id),
-	None => {
-	    // Create a new page.
-	    let page_id = Uuid::new_v4();
-	    sqlx::query!(
-		r#"
-INSERT INTO pages (page_id, owner, url)
-VALUES ($1, $2, $3)"#,
-		page_id,
-		Uuid::new_v4(),
-		url.as_str(),
-	    )
-		.execute(db_pool)
-		.await
-		.context("Failed to insert new page")?;
-	    Ok(page_id)	    
-	}
-    }    
+pub async fn register_batch(
+    req: HttpRequest,
+    batch: web::Json<BatchRegistration>,
+    pages: web::Data<dyn PageStore>,
+) -> actix_web::Result<impl Responder> {
+    let owner = try_authenticate(&req, pages.get_ref())
+	.await?
+	.unwrap_or_else(Uuid::new_v4);
+    let urls = batch.into_inner().urls;
+    if urls.len() > MAX_BATCH_SIZE {
+	return Err(e400(format!("Expected at most {MAX_BATCH_SIZE} URLs")));
+    }
+    let parsed: Vec<Result<Url, String>> = urls.iter()
+	.map(|url| Url::parse(url).map_err(|e| e.to_string()))
+	.collect();
+    let valid: Vec<&Url> = parsed.iter().filter_map(|url| url.as_ref().ok()).collect();
+    let page_ids = pages.register_urls(&valid, owner).await.map_err(e500)?;
+    let results: Vec<BatchResult> = urls.into_iter()
+	.zip(parsed)
+	.map(|(url, parsed)| match parsed {
+	    Ok(parsed) => BatchResult {
+		url,
+		page_id: page_ids.get(parsed.as_str()).copied(),
+		error: None,
+	    },
+	    Err(e) => BatchResult { url, page_id: None, error: Some(e) },
+	})
+	.collect();
+    Ok(web::Json(results))
 }
@@ -0,0 +1,182 @@
+use actix_web::{web, HttpRequest, Responder};
+use sqlx::PgPool;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use crate::authentication::authenticate;
+use crate::dashboard::SECS_PER_DAY;
+use crate::rollups::{hour_of, SECS_PER_HOUR};
+use crate::storage::PageStore;
+use crate::utils::{e500, unix_time_secs};
+
+const TOP_REFERRERS: i64 = 10;
+
+/// Time span that a report covers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[derive(Deserialize, Serialize, clap::ValueEnum, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Day,
+    #[default]
+    Week,
+    Month,
+}
+
+impl Period {
+    pub fn secs(&self) -> i64 {
+	match self {
+	    Period::Day => SECS_PER_DAY,
+	    Period::Week => 7 * SECS_PER_DAY,
+	    Period::Month => 30 * SECS_PER_DAY,
+	}
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReportParams {
+    #[serde(default)]
+    #[param(inline)]
+    pub period: Period,
+}
+
+/// Hits of all pages of an owner in the last period, compared
+/// to the period before.
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct Report {
+    pub period: Period,
+    /// Unix time at which the period starts.
+    pub since: i64,
+    /// Unix time at which the period ends.
+    pub until: i64,
+    /// Pages with the most hits first.
+    pub pages: Vec<PageReport>,
+    pub top_referrers: Vec<ReferrerHits>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct PageReport {
+    pub page_id: Uuid,
+    pub url: String,
+    pub hits: i64,
+    /// Hits in the period before.
+    pub previous_hits: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct ReferrerHits {
+    pub referrer: String,
+    pub n: i64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/report",
+    params(ReportParams),
+    responses(
+	(status = 200, description = "Report on the pages of the owner", body = Report),
+	(status = 401, description = "Missing or invalid API key"),
+    ),
+    security(("api_key" = [])),
+)]
+#[tracing::instrument(
+    name = "Report on the pages of an owner",
+    skip(req, pg_pool, pages)
+)]
+pub async fn report(
+    req: HttpRequest,
+    query: web::Query<ReportParams>,
+    pg_pool: web::Data<PgPool>,
+    pages: web::Data<dyn PageStore>,
+) -> actix_web::Result<impl Responder> {
+    let owner_id = authenticate(&req, pages.as_ref()).await?;
+    let period = query.into_inner().period;
+    // Hits are counted by the hour, so the period ends with
+    // the current one.
+    let until = hour_of(unix_time_secs() as i64) + SECS_PER_HOUR;
+    let since = until - period.secs();
+    let pages = page_reports(owner_id, since, period.secs(), &pg_pool)
+	.await
+	.map_err(e500)?;
+    let top_referrers = top_referrers_of_owner(owner_id, since, &pg_pool)
+	.await
+	.map_err(e500)?;
+    Ok(web::Json(Report { period, since, until, pages, top_referrers }))
+}
+
+#[tracing::instrument(
+    name = "Get page reports",
+    skip(pg_pool)
+)]
+async fn page_reports(
+    owner_id: Uuid,
+    since: i64,
+    period_secs: i64,
+    pg_pool: &PgPool,
+) -> anyhow::Result<Vec<PageReport>> {
+    let records = sqlx::query!(
+	r#"
+SELECT p.page_id, p.url,
+       COALESCE(SUM(r.hits) FILTER (WHERE r.hour >= $2), 0)::bigint AS "hits!",
+       COALESCE(SUM(r.hits) FILTER (WHERE r.hour < $2), 0)::bigint AS "previous_hits!"
+FROM pages p
+LEFT JOIN hits_hourly r
+  ON r.page_id = p.page_id
+ AND r.dimension = 'total'
+ AND r.hour >= $3
+WHERE p.owner = $1
+GROUP BY p.page_id, p.url
+ORDER BY "hits!" DESC, p.url
+"#,
+	owner_id,
+	since,
+	since - period_secs,
+    )
+	.fetch_all(pg_pool)
+	.await
+	.context("Failed to get page reports")?;
+    Ok(records
+       .into_iter()
+       .map(|rec| PageReport {
+	   page_id: rec.page_id,
+	   url: rec.url,
+	   hits: rec.hits,
+	   previous_hits: rec.previous_hits,
+       })
+       .collect())
+}
+
+#[tracing::instrument(
+    name = "Get top referrers of owner",
+    skip(pg_pool)
+)]
+async fn top_referrers_of_owner(
+    owner_id: Uuid,
+    since: i64,
+    pg_pool: &PgPool,
+) -> anyhow::Result<Vec<ReferrerHits>> {
+    let records = sqlx::query!(
+	r#"
+SELECT r.value AS referrer, SUM(r.hits)::bigint AS "n!"
+FROM hits_hourly r
+JOIN pages p ON p.page_id = r.page_id
+WHERE p.owner = $1
+  AND r.dimension = 'referrer'
+  AND r.hour >= $2
+GROUP BY r.value
+ORDER BY "n!" DESC, r.value
+LIMIT $3
+"#,
+	owner_id,
+	since,
+	TOP_REFERRERS,
+    )
+	.fetch_all(pg_pool)
+	.await
+	.context("Failed to get top referrers")?;
+    Ok(records
+       .into_iter()
+       .map(|rec| ReferrerHits { referrer: rec.referrer, n: rec.n })
+       .collect())
+}
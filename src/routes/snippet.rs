@@ -0,0 +1,46 @@
+use actix_web::{HttpResponse, web};
+use serde::{Deserialize, Serialize};
+use utoipa::IntoParams;
+use uuid::Uuid;
+use crate::snippet::{render, Format};
+use crate::startup::ApplicationBaseUrl;
+use crate::storage::PageStore;
+use crate::utils::e500;
+
+#[utoipa::path(
+    get,
+    path = "/pages/{page_id}/snippet",
+    params(("page_id" = Uuid, Path, description = "ID of the page"), SnippetParams),
+    responses(
+	(status = 200, description = "Snippet that tracks the page", body = String,
+	 content_type = ["text/css", "text/html"]),
+	(status = 404, description = "No such page"),
+    ),
+)]
+#[tracing::instrument(
+    name = "Generate the snippet of a page",
+    skip(base_url, pages)
+)]
+pub async fn snippet(
+    path: web::Path<Uuid>,
+    query: web::Query<SnippetParams>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    pages: web::Data<dyn PageStore>,
+) -> actix_web::Result<HttpResponse> {
+    let page_id = path.into_inner();
+    if pages.owner_of_page(page_id).await.map_err(e500)?.is_none() {
+	return Ok(HttpResponse::NotFound().finish());
+    }
+    let format = query.into_inner().format;
+    Ok(HttpResponse::Ok()
+       .content_type(format.content_type())
+       .body(render(format, &base_url.0, page_id)))
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SnippetParams {
+    #[serde(default)]
+    #[param(inline)]
+    pub format: Format,
+}
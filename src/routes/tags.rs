@@ -0,0 +1,178 @@
+use actix_web::{HttpRequest, Responder, web};
+use sqlx::PgPool;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use crate::authentication::authenticate;
+use crate::storage::PageStore;
+use crate::utils::e500;
+
+/// Pages that the hits of tags are summed over.
+#[derive(Debug, Default, Clone, Deserialize, Serialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TagsParams {
+    /// Host name of the pages, e.g. `example.com`.
+    pub site: Option<String>,
+    /// Only pages of this group.
+    pub group: Option<String>,
+}
+
+/// Hits of all pages with a tag.
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct TagSummary {
+    pub tag: String,
+    /// Number of pages with the tag.
+    pub pages: i64,
+    pub n: i64,
+}
+
+/// Hits of the pages with a tag, page by page.
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct TagHits {
+    pub tag: String,
+    /// Hits of all the pages.
+    pub n: i64,
+    /// Pages with the most hits first.
+    pub pages: Vec<TaggedPage>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct TaggedPage {
+    pub page_id: Uuid,
+    pub url: String,
+    pub title: Option<String>,
+    pub group: Option<String>,
+    pub n: i64,
+}
+
+// Only the pages of the owner are counted.
+#[utoipa::path(
+    get,
+    path = "/tags",
+    params(TagsParams),
+    responses(
+	(status = 200, description = "Hits per tag of the owner's pages, most first", body = [TagSummary]),
+	(status = 401, description = "Missing or invalid API key"),
+    ),
+    security(("api_key" = [])),
+)]
+#[tracing::instrument(
+    name = "Retrieve the hits per tag",
+    skip(req, pg_pool, pages)
+)]
+pub async fn tags(
+    req: HttpRequest,
+    query: web::Query<TagsParams>,
+    pg_pool: web::Data<PgPool>,
+    pages: web::Data<dyn PageStore>,
+) -> actix_web::Result<impl Responder> {
+    let owner_id = authenticate(&req, pages.as_ref()).await?;
+    let summaries = tag_summaries(owner_id, &query, &pg_pool)
+	.await
+	.map_err(e500)?;
+    Ok(web::Json(summaries))
+}
+
+// Only the pages of the owner are counted.
+#[utoipa::path(
+    get,
+    path = "/tags/{tag}/hits",
+    params(("tag" = String, Path, description = "Tag of the pages"), TagsParams),
+    responses(
+	(status = 200, description = "Hits of the owner's pages with the tag", body = TagHits),
+	(status = 401, description = "Missing or invalid API key"),
+    ),
+    security(("api_key" = [])),
+)]
+#[tracing::instrument(
+    name = "Retrieve the hits of a tag",
+    skip(req, pg_pool, pages)
+)]
+pub async fn tag_hits(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<TagsParams>,
+    pg_pool: web::Data<PgPool>,
+    pages: web::Data<dyn PageStore>,
+) -> actix_web::Result<impl Responder> {
+    let owner_id = authenticate(&req, pages.as_ref()).await?;
+    let tag = path.into_inner();
+    let pages = tagged_pages(owner_id, &tag, &query, &pg_pool)
+	.await
+	.map_err(e500)?;
+    let n = pages.iter().map(|page| page.n).sum();
+    Ok(web::Json(TagHits { tag, n, pages }))
+}
+
+#[tracing::instrument(
+    name = "Get hits per tag",
+    skip(pg_pool)
+)]
+async fn tag_summaries(
+    owner_id: Uuid,
+    params: &TagsParams,
+    pg_pool: &PgPool,
+) -> anyhow::Result<Vec<TagSummary>> {
+    let records = sqlx::query!(
+	r#"
+SELECT tag AS "tag!", COUNT(*) AS "pages!", SUM(p.hits)::bigint AS "n!"
+FROM pages p, UNNEST(p.tags) AS tag
+WHERE p.owner = $3
+  AND ($1::text IS NULL OR p.site = $1)
+  AND ($2::text IS NULL OR p.page_group = $2)
+GROUP BY tag
+ORDER BY "n!" DESC, tag
+"#,
+	params.site,
+	params.group,
+	owner_id,
+    )
+	.fetch_all(pg_pool)
+	.await
+	.context("Failed to get hits per tag")?;
+    Ok(records
+       .into_iter()
+       .map(|r| TagSummary { tag: r.tag, pages: r.pages, n: r.n })
+       .collect())
+}
+
+#[tracing::instrument(
+    name = "Get hits of tagged pages",
+    skip(pg_pool)
+)]
+async fn tagged_pages(
+    owner_id: Uuid,
+    tag: &str,
+    params: &TagsParams,
+    pg_pool: &PgPool,
+) -> anyhow::Result<Vec<TaggedPage>> {
+    let records = sqlx::query!(
+	r#"
+SELECT page_id, url, title, page_group, hits
+FROM pages
+WHERE owner = $4
+  AND tags @> ARRAY[$1]
+  AND ($2::text IS NULL OR site = $2)
+  AND ($3::text IS NULL OR page_group = $3)
+ORDER BY hits DESC, url
+"#,
+	tag,
+	params.site,
+	params.group,
+	owner_id,
+    )
+	.fetch_all(pg_pool)
+	.await
+	.with_context(|| format!("Failed to get pages with tag: {}", tag))?;
+    Ok(records
+       .into_iter()
+       .map(|r| TaggedPage {
+	   page_id: r.page_id,
+	   url: r.url,
+	   title: r.title,
+	   group: r.page_group,
+	   n: r.hits.into(),
+       })
+       .collect())
+}
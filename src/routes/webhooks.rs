@@ -0,0 +1,242 @@
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use sqlx::PgPool;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use url::Url;
+use uuid::Uuid;
+use crate::authentication::{authenticate, authorize_page};
+use crate::configuration::WebhookSettings;
+use crate::storage::PageStore;
+use crate::utils::{e400, e500};
+use crate::webhooks::{check_target, milestone};
+
+/// Longest time window of a spike trigger, in minutes.
+const MAX_SPIKE_MINUTES: i32 = 24 * 60;
+
+/// Notify when the page gets `hits` hits within `minutes`.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, ToSchema)]
+pub struct Spike {
+    pub hits: i32,
+    pub minutes: i32,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct NewWebhook {
+    /// Address that the notifications are POSTed to.
+    pub url: Url,
+    /// Notify when the page reaches 1k, 10k, 100k, ... hits.
+    #[serde(default)]
+    pub milestones: bool,
+    pub spike: Option<Spike>,
+}
+
+impl NewWebhook {
+    fn validate(&self) -> Result<(), String> {
+	if !matches!(self.url.scheme(), "http" | "https") {
+	    return Err("The webhook URL must use http or https".into());
+	}
+	if !self.milestones && self.spike.is_none() {
+	    return Err("Expected at least one of `milestones` or `spike`".into());
+	}
+	if let Some(spike) = self.spike {
+	    if spike.hits < 1 || !(1..=MAX_SPIKE_MINUTES).contains(&spike.minutes) {
+		return Err(format!(
+		    "A spike needs at least one hit within 1 to {MAX_SPIKE_MINUTES} minutes"
+		));
+	    }
+	}
+	Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct Webhook {
+    pub webhook_id: Uuid,
+    pub url: String,
+    pub milestones: bool,
+    pub spike: Option<Spike>,
+    /// Key of the HMAC-SHA256 in the `X-JHM-Signature`
+    /// header of each delivery.
+    pub secret: String,
+}
+
+// Subscribe to notifications about the page. Milestones that
+// the page has already reached aren't notified about. Unless
+// `webhooks.allow_private_targets` is set, the URL must not
+// point to a private address.
+#[utoipa::path(
+    post,
+    path = "/pages/{page_id}/webhooks",
+    params(("page_id" = Uuid, Path, description = "ID of the page")),
+    request_body = NewWebhook,
+    responses(
+	(status = 200, description = "The new webhook", body = Webhook),
+	(status = 400, description = "Invalid webhook"),
+	(status = 401, description = "Missing or invalid API key"),
+	(status = 404, description = "No page of the owner"),
+    ),
+    security(("api_key" = [])),
+)]
+#[tracing::instrument(
+    name = "Create webhook",
+    skip(req, pg_pool, pages, settings)
+)]
+pub async fn create_webhook(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    webhook: web::Json<NewWebhook>,
+    pg_pool: web::Data<PgPool>,
+    pages: web::Data<dyn PageStore>,
+    settings: web::Data<WebhookSettings>,
+) -> actix_web::Result<impl Responder> {
+    let page_id = path.into_inner();
+    let owner_id = authenticate(&req, pages.as_ref()).await?;
+    authorize_page(owner_id, page_id, pages.as_ref()).await?;
+    let webhook = webhook.into_inner();
+    webhook.validate().map_err(e400)?;
+    if !settings.allow_private_targets {
+	check_target(&webhook.url).await.map_err(e400)?;
+    }
+    let webhook = store_webhook(page_id, webhook, &pg_pool)
+	.await
+	.map_err(e500)?;
+    Ok(web::Json(webhook))
+}
+
+#[utoipa::path(
+    get,
+    path = "/pages/{page_id}/webhooks",
+    params(("page_id" = Uuid, Path, description = "ID of the page")),
+    responses(
+	(status = 200, description = "Webhooks of the page", body = [Webhook]),
+	(status = 401, description = "Missing or invalid API key"),
+	(status = 404, description = "No page of the owner"),
+    ),
+    security(("api_key" = [])),
+)]
+#[tracing::instrument(
+    name = "List webhooks",
+    skip(req, pg_pool, pages)
+)]
+pub async fn webhooks(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    pg_pool: web::Data<PgPool>,
+    pages: web::Data<dyn PageStore>,
+) -> actix_web::Result<impl Responder> {
+    let page_id = path.into_inner();
+    let owner_id = authenticate(&req, pages.as_ref()).await?;
+    authorize_page(owner_id, page_id, pages.as_ref()).await?;
+    let recs = sqlx::query!(
+	r#"
+SELECT webhook_id, url, milestones, spike_hits, spike_minutes, secret
+FROM webhooks
+WHERE page_id = $1
+ORDER BY url, webhook_id"#,
+	page_id,
+    )
+	.fetch_all(pg_pool.get_ref())
+	.await
+	.context("Failed to get webhooks")
+	.map_err(e500)?;
+    let webhooks: Vec<Webhook> = recs
+	.into_iter()
+	.map(|rec| Webhook {
+	    webhook_id: rec.webhook_id,
+	    url: rec.url,
+	    milestones: rec.milestones,
+	    spike: rec.spike_hits
+		.zip(rec.spike_minutes)
+		.map(|(hits, minutes)| Spike { hits, minutes }),
+	    secret: rec.secret,
+	})
+	.collect();
+    Ok(web::Json(webhooks))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/pages/{page_id}/webhooks/{webhook_id}",
+    params(("page_id" = Uuid, Path, description = "ID of the page"), ("webhook_id" = Uuid, Path, description = "ID of the webhook")),
+    responses(
+	(status = 204, description = "The webhook is deleted"),
+	(status = 401, description = "Missing or invalid API key"),
+	(status = 404, description = "No such webhook of the owner"),
+    ),
+    security(("api_key" = [])),
+)]
+#[tracing::instrument(
+    name = "Delete webhook",
+    skip(req, pg_pool, pages)
+)]
+pub async fn delete_webhook(
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+    pg_pool: web::Data<PgPool>,
+    pages: web::Data<dyn PageStore>,
+) -> actix_web::Result<HttpResponse> {
+    let (page_id, webhook_id) = path.into_inner();
+    let owner_id = authenticate(&req, pages.as_ref()).await?;
+    authorize_page(owner_id, page_id, pages.as_ref()).await?;
+    let deleted = sqlx::query!(
+	r#"
+DELETE FROM webhooks
+WHERE webhook_id = $1 AND page_id = $2"#,
+	webhook_id,
+	page_id,
+    )
+	.execute(pg_pool.get_ref())
+	.await
+	.context("Failed to delete webhook")
+	.map_err(e500)?;
+    if deleted.rows_affected() == 0 {
+	Ok(HttpResponse::NotFound().finish())
+    } else {
+	Ok(HttpResponse::NoContent().finish())
+    }
+}
+
+#[tracing::instrument(
+    name = "Store webhook",
+    skip(pg_pool)
+)]
+async fn store_webhook(
+    page_id: Uuid,
+    webhook: NewWebhook,
+    pg_pool: &PgPool,
+) -> anyhow::Result<Webhook> {
+    let hits = sqlx::query!(
+	"SELECT hits FROM pages WHERE page_id = $1",
+	page_id,
+    )
+	.fetch_one(pg_pool)
+	.await
+	.context("Failed to get hits of page")?
+	.hits;
+    let webhook_id = Uuid::new_v4();
+    let secret = Uuid::new_v4().simple().to_string();
+    sqlx::query!(
+	r#"
+INSERT INTO webhooks (webhook_id, page_id, url, secret, milestones, last_milestone, spike_hits, spike_minutes)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"#,
+	webhook_id,
+	page_id,
+	webhook.url.as_str(),
+	secret,
+	webhook.milestones,
+	milestone(hits.into()),
+	webhook.spike.map(|spike| spike.hits),
+	webhook.spike.map(|spike| spike.minutes),
+    )
+	.execute(pg_pool)
+	.await
+	.context("Failed to insert webhook")?;
+    Ok(Webhook {
+	webhook_id,
+	url: webhook.url.into(),
+	milestones: webhook.milestones,
+	spike: webhook.spike,
+	secret,
+    })
+}
@@ -0,0 +1,129 @@
+use url::Url;
+use uuid::Uuid;
+
+/// The different ways in which a page can embed the tracker.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[derive(serde::Deserialize, serde::Serialize, clap::ValueEnum, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// CSS for the style sheets of the page.
+    #[default]
+    Css,
+    /// The same CSS, wrapped in an inline `<style>` tag.
+    Style,
+    /// An invisible `<img>` pixel.
+    Img,
+    /// A `<link rel=prefetch>` tag.
+    Prefetch,
+}
+
+impl Format {
+    pub fn content_type(&self) -> &'static str {
+	match self {
+	    Format::Css => "text/css; charset=utf-8",
+	    Format::Style | Format::Img | Format::Prefetch => "text/html; charset=utf-8",
+	}
+    }
+
+    /// File extension of the snippet.
+    pub fn extension(&self) -> &'static str {
+	match self {
+	    Format::Css => "css",
+	    Format::Style | Format::Img | Format::Prefetch => "html",
+	}
+    }
+}
+
+/// Render the snippet that tracks the page with the given ID.
+///
+/// The `<img>` and prefetch formats don't wait for the reader
+/// to hover the page, so they also count most bots.
+pub fn render(format: Format, service: &Url, page_id: Uuid) -> String {
+    match format {
+	Format::Css => css(service, page_id),
+	Format::Style => format!("<style>\n{}</style>\n", css(service, page_id)),
+	// Without this referrer policy, browsers drop the query of
+	// the page, and with it any campaign parameters.
+	Format::Img => format!(
+	    r#"<img src="{}" alt="" width="1" height="1" style="position:absolute;opacity:0" referrerpolicy="no-referrer-when-downgrade">
+"#,
+	    hit_url(service, page_id),
+	),
+	Format::Prefetch => format!(
+	    r#"<link rel="prefetch" href="{}" referrerpolicy="no-referrer-when-downgrade">
+"#,
+	    hit_url(service, page_id),
+	),
+    }
+}
+
+/// Events that the generated CSS triggers, keyed by the
+/// selector that triggers them.
+pub const CSS_EVENTS: &[(&str, &str)] = &[
+    // The reader scrolled down to the footer.
+    ("footer:hover", "read-to-end"),
+    // The reader clicked a link to another page.
+    ("a[href^=\"http\"]:active", "outbound-click"),
+];
+
+/// Event that's triggered once a reader has had the page
+/// open for [`DWELL_SECS`] seconds.
+pub const DWELL_EVENT: &str = "dwell-30s";
+pub const DWELL_SECS: u32 = 30;
+
+/// URL that counts a hit of the page.
+pub fn hit_url(service: &Url, page_id: Uuid) -> Url {
+    service_url(service, &["hit", &page_id.to_string()])
+}
+
+/// URL that records an event on the page.
+pub fn event_url(service: &Url, page_id: Uuid, event: &str) -> Url {
+    service_url(service, &["hit", &page_id.to_string(), "event", event])
+}
+
+fn service_url(service: &Url, segments: &[&str]) -> Url {
+    let mut url = service.clone();
+    url.path_segments_mut()
+	.expect("Service URL cannot be a base")
+	.pop_if_empty()
+	.extend(segments);
+    url
+}
+
+/// CSS that tracks the page with the given ID.
+///
+/// Hovering the body counts a hit. The other rules record
+/// engagement events without any JavaScript.
+pub fn css(service: &Url, page_id: Uuid) -> String {
+    let mut css = format!(
+	r#"body:hover {{
+    border-image: url("{}");
+    border-width: 0;
+}}
+"#,
+	hit_url(service, page_id),
+    );
+    for (selector, event) in CSS_EVENTS {
+	css.push_str(&format!(
+	    r#"{selector} {{
+    border-image: url("{}");
+    border-width: 0;
+}}
+"#,
+	    event_url(service, page_id, event),
+	));
+    }
+    // The animation only applies the border image once the delay
+    // is over, which is when the browser fetches it.
+    css.push_str(&format!(
+	r#"html {{
+    animation: jhm-{DWELL_EVENT} 0s {DWELL_SECS}s forwards;
+}}
+@keyframes jhm-{DWELL_EVENT} {{
+    to {{ border-image: url("{}"); }}
+}}
+"#,
+	event_url(service, page_id, DWELL_EVENT),
+    ));
+    css
+}
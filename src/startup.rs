@@ -1,45 +1,128 @@
 use std::net::TcpListener;
-use actix_web::{web, App, HttpServer};
-use actix_web::dev::Server;
+use actix_web::{web, App, HttpResponse, HttpServer, Route};
+use actix_web::http::Method;
+use actix_web::dev::{Server, ServerHandle};
 use tracing_actix_web::TracingLogger;
+use anyhow::Context;
 use sqlx::PgPool;
 use sqlx::postgres::PgPoolOptions;
-use crate::routes;
-use crate::configuration::{Settings, PostgresSettings, RedisSettings};
+use crate::circuit_breaker::FallibleRedis;
+use crate::routes::{self, LiveClient};
+use crate::configuration::{Settings, ApplicationSettings, PostgresSettings, RedisFallback, RedisSettings, WebhookSettings, WriteBehindSettings};
+use crate::storage::Storage;
 use crate::utils::RedisPool;
+use crate::webhooks::run_worker_until_stopped;
+use crate::write_behind::HitBuffer;
+use url::Url;
 
 pub struct Application {
     port: u16,
     server: Server,
+    /// Pool of the background tasks. Connections that the
+    /// server opens belong to the runtimes of its workers,
+    /// and can't be used once the server has stopped. The
+    /// tasks only run with the `postgres` storage backend.
+    postgres: Option<PgPool>,
+    redis: Option<RedisPool>,
+    webhooks: WebhookSettings,
+    write_behind: WriteBehindSettings,
+    hit_buffer: Option<HitBuffer>,
 }
 
 impl Application {
-    pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
-        let postgres = get_pg_connection_pool(&configuration.postgres).await;
-	let redis = get_redis_connection_pool(&configuration.redis);
+    pub async fn build(mut configuration: Settings) -> Result<Self, anyhow::Error> {
+	let storage = Storage::build(&configuration).await?;
+	if configuration.write_behind.enabled && storage.postgres.is_none() {
+	    anyhow::bail!("Write-behind mode needs the postgres storage backend");
+	}
+	let postgres = match storage.postgres {
+	    Some(_) => Some(get_pg_connection_pool(&configuration.postgres).await),
+	    None => None,
+	};
+	let redis = storage.redis.clone();
+	let live_client = redis.as_ref()
+	    .map(|_| redis::Client::open(configuration.redis.with_db()))
+	    .transpose()
+	    .context("Invalid Redis settings")?;
         let address = format!(
             "{}:{}",
             configuration.application.host,
             configuration.application.port,
         );
+	let hit_buffer = configuration.write_behind.enabled
+	    .then(|| HitBuffer::new(&configuration.postgres.database_name));
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
+	// The port may have been picked by the OS.
+	configuration.application.port = port;
+	if configuration.application.base_url.is_none() {
+	    tracing::warn!(
+		base_url = %configuration.application.public_url(),
+		"application.base_url isn't set, so snippets point to the address \
+		 that the service listens on"
+	    );
+	}
         let server = run(
             listener,
-            postgres,
-	    redis,
-	    configuration.application.visit_duration,
+	    storage,
+	    live_client,
+	    &configuration.application,
+	    configuration.webhooks.clone(),
+	    configuration.redis.fallback,
+	    hit_buffer.clone(),
         ).await?;
 
-        Ok(Self{ port, server })
+	Ok(Self{
+	    port,
+	    server,
+	    postgres,
+	    redis,
+	    webhooks: configuration.webhooks,
+	    write_behind: configuration.write_behind,
+	    hit_buffer,
+	})
     }
 
     pub fn port(&self) -> u16 {
         self.port
     }
 
+    /// Handle to stop the server with.
+    pub fn handle(&self) -> ServerHandle {
+	self.server.handle()
+    }
+
+    /// Run the server, and the worker that delivers webhooks
+    /// next to it. In write-behind mode, buffered hits are
+    /// flushed periodically, and once more after the server
+    /// has stopped.
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+	let worker = self.postgres.clone().map(|postgres| tokio::spawn(
+	    run_worker_until_stopped(postgres, self.webhooks)
+	));
+	let (stop, stopped) = tokio::sync::watch::channel(false);
+	let flusher = match (self.hit_buffer, self.postgres, self.redis) {
+	    (Some(hit_buffer), Some(postgres), Some(redis)) => Some(tokio::spawn(
+		hit_buffer.run_flusher_until_stopped(
+		    postgres,
+		    redis,
+		    self.write_behind.flush_interval(),
+		    stopped,
+		)
+	    )),
+	    _ => None,
+	};
+	let result = self.server.await;
+	if let Some(worker) = worker {
+	    worker.abort();
+	}
+	if let Some(flusher) = flusher {
+	    let _ = stop.send(true);
+	    if let Err(e) = flusher.await {
+		tracing::error!("Failed to flush buffered hits: {e:?}");
+	    }
+	}
+	result
     }
 }
 
@@ -52,36 +135,156 @@ pub async fn get_pg_connection_pool(
         .connect_lazy_with(configuration.with_db())
 }
 
+/// Pool of Redis connections. They are opened as needed, so
+/// that the service starts even if Redis is down.
 pub fn get_redis_connection_pool(
     configuration: &RedisSettings,
-) -> r2d2::Pool<redis::Client> {
-    let client = redis::Client::open(configuration.with_db()).unwrap();
-    r2d2::Pool::builder()
-        .connection_timeout(std::time::Duration::from_secs(2))
-        .build(client)
-	.unwrap()
+) -> anyhow::Result<r2d2::Pool<redis::Client>> {
+    let client = redis::Client::open(configuration.with_db())
+	.context("Invalid Redis settings")?;
+    Ok(r2d2::Pool::builder()
+       .connection_timeout(std::time::Duration::from_secs(2))
+       .build_unchecked(client))
 }
 
 
+/// Routes of the first version of the API, relative to `/v1`.
+/// Each one must be documented in [`routes::ApiDoc`].
+pub fn api_v1_routes() -> Vec<(Method, &'static str, Route)> {
+    vec![
+	(Method::GET, "/health_check", web::to(routes::health_check)),
+	(Method::GET, "/ready", web::to(routes::ready)),
+	(Method::GET, "/hit/{page_id}", web::to(routes::hit)),
+	(Method::GET, "/hit/{page_id}/event/{name}", web::to(routes::hit_event)),
+	(Method::POST, "/register", web::to(routes::register)),
+	(Method::POST, "/register/batch", web::to(routes::register_batch)),
+	(Method::GET, "/hits", web::to(routes::hits)),
+	(Method::GET, "/campaigns", web::to(routes::campaigns)),
+	(Method::GET, "/active", web::to(routes::active)),
+	(Method::GET, "/tags", web::to(routes::tags)),
+	(Method::GET, "/tags/{tag}/hits", web::to(routes::tag_hits)),
+	(Method::POST, "/compare", web::to(routes::compare)),
+	(Method::GET, "/report", web::to(routes::report)),
+	(Method::POST, "/owners", web::to(routes::new_owner)),
+	(Method::PATCH, "/pages/{page_id}", web::to(routes::update_page)),
+	(Method::GET, "/pages/{page_id}/snippet", web::to(routes::snippet)),
+	(Method::GET, "/pages/{page_id}/live", web::to(routes::live_page)),
+	(Method::GET, "/pages/{page_id}/breakdown", web::to(routes::breakdown)),
+	(Method::GET, "/pages/{page_id}/webhooks", web::to(routes::webhooks)),
+	(Method::POST, "/pages/{page_id}/webhooks", web::to(routes::create_webhook)),
+	(Method::DELETE, "/pages/{page_id}/webhooks/{webhook_id}", web::to(routes::delete_webhook)),
+	(Method::GET, "/sites/{site}/live", web::to(routes::live_site)),
+	(Method::GET, "/badge/{page_id}.svg", web::to(routes::badge)),
+	(Method::GET, "/dashboard/{site_or_page}", web::to(routes::dashboard)),
+    ]
+}
+
+/// Routes that only use the [`PageStore`](crate::storage::PageStore)
+/// and the [`VisitorStore`](crate::storage::VisitorStore), so
+/// that they work with every storage backend. The others need
+/// Postgres and Redis, and answer with 501 without them.
+pub const PORTABLE_ROUTES: [&str; 11] = [
+    "/health_check",
+    "/ready",
+    "/hit/{page_id}",
+    "/hit/{page_id}/event/{name}",
+    "/register",
+    "/register/batch",
+    "/hits",
+    "/owners",
+    "/pages/{page_id}",
+    "/pages/{page_id}/snippet",
+    "/badge/{page_id}.svg",
+];
+
+fn api_v1(all_routes: bool) -> impl Fn(&mut web::ServiceConfig) + Clone {
+    move |cfg| {
+	for (method, path, route) in api_v1_routes() {
+	    if all_routes || PORTABLE_ROUTES.contains(&path) {
+		cfg.route(path, route.method(method));
+	    } else {
+		cfg.route(path, web::method(method).to(needs_postgres));
+	    }
+	}
+    }
+}
+
+async fn needs_postgres() -> HttpResponse {
+    HttpResponse::NotImplemented()
+	.body("This route needs the `postgres` storage backend and Redis")
+}
+
+/// Public address of the service.
+pub struct ApplicationBaseUrl(pub Url);
+
+/// Header that the proxy in front of the service puts the
+/// country of the visitor in, if any.
+pub struct CountryHeader(pub Option<String>);
+
 pub async fn run(
     listener: TcpListener,
-    pg: PgPool,
-    redis: RedisPool,
-    visit_duration: u64,
+    storage: Storage,
+    live_client: Option<redis::Client>,
+    application: &ApplicationSettings,
+    webhooks: WebhookSettings,
+    redis_fallback: RedisFallback,
+    hit_buffer: Option<HitBuffer>,
 ) -> Result<Server, anyhow::Error> {
-    let pg = web::Data::new(pg);
-    let redis = web::Data::new(redis);
-    let visit_duration = web::Data::new(visit_duration);
+    let all_routes = storage.postgres.is_some() && storage.redis.is_some();
+    if !all_routes {
+	for (method, path, _) in api_v1_routes() {
+	    if !PORTABLE_ROUTES.contains(&path) {
+		tracing::warn!(%method, path, "Route is disabled, as it needs Postgres and Redis");
+	    }
+	}
+    }
+    let pages = web::Data::from(storage.pages);
+    let visitors = web::Data::from(storage.visitors);
+    let pg = storage.postgres.map(web::Data::new);
+    let fallible_redis = storage.redis.clone()
+	.zip(storage.redis_circuit.clone())
+	.map(|(pool, circuit)| web::Data::new(FallibleRedis { pool, circuit }));
+    let redis_circuit = storage.redis_circuit.map(web::Data::from);
+    let redis = storage.redis.map(web::Data::new);
+    let live_client = live_client.map(|client| web::Data::new(LiveClient(client)));
+    let visit_duration = web::Data::new(application.visit_duration);
+    let base_url = web::Data::new(ApplicationBaseUrl(application.public_url()));
+    let country_header = web::Data::new(CountryHeader(application.country_header.clone()));
+    let webhooks = web::Data::new(webhooks);
+    let redis_fallback = web::Data::new(redis_fallback);
+    let hit_buffer = web::Data::new(hit_buffer);
     let server = HttpServer::new(move || {
-        App::new()
+	let mut app = App::new()
             .wrap(TracingLogger::default())
-            .route("/health_check", web::get().to(routes::health_check))
-            .route("/hit/{site_id}", web::get().to(routes::hit))
-            .route("/register", web::post().to(routes::register))
-            .route("/hits", web::get().to(routes::hits))
-            .app_data(pg.clone())
-            .app_data(redis.clone())
+	    .service(web::scope("/v1")
+		     .route("/openapi.json", web::get().to(routes::openapi))
+		     .configure(api_v1(all_routes)))
+	    // Paths from before the API was versioned.
+	    .configure(api_v1(all_routes))
+	    .app_data(pages.clone())
+	    .app_data(visitors.clone())
             .app_data(visit_duration.clone())
+            .app_data(base_url.clone())
+	    .app_data(country_header.clone())
+	    .app_data(webhooks.clone())
+	    .app_data(redis_fallback.clone())
+	    .app_data(hit_buffer.clone());
+	if let Some(pg) = &pg {
+	    app = app.app_data(pg.clone());
+	}
+	if let Some(redis) = &redis {
+	    app = app.app_data(redis.clone());
+	}
+	if let Some(fallible_redis) = &fallible_redis {
+	    app = app.app_data(fallible_redis.clone());
+	}
+	if let Some(redis_circuit) = &redis_circuit {
+	    app = app.app_data(redis_circuit.clone());
+	}
+	if let Some(live_client) = &live_client {
+	    app = app.app_data(live_client.clone());
+	}
+	app
     })
     .listen(listener)?
     .run();
@@ -0,0 +1,186 @@
+//! Stores of the pages, hits and visitors that the routes for
+//! counting hits and managing pages depend on, so that the
+//! service can run without Postgres and Redis, e.g. for a
+//! personal blog.
+//!
+//! The other routes, like reports and webhooks, and the
+//! background tasks still use Postgres and Redis directly, so
+//! they are only available with the `postgres` backend.
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use sqlx::PgPool;
+use url::Url;
+use uuid::Uuid;
+use crate::circuit_breaker::CircuitBreaker;
+use crate::configuration::{Settings, StorageBackend, VisitorBackend};
+use crate::routes::{Campaign, HitDimensions, Hits, NewPage, Page, PageUpdate};
+use crate::startup::{get_pg_connection_pool, get_redis_connection_pool};
+use crate::utils::{RedisPool, ping_redis};
+
+mod postgres;
+pub use postgres::*;
+mod sqlite;
+pub use sqlite::*;
+mod memory;
+pub use memory::*;
+mod fallback;
+pub use fallback::*;
+
+/// Pages, their hits and their owners.
+#[async_trait]
+pub trait PageStore: Send + Sync {
+    /// Add an owner, with the hash of its API key.
+    async fn insert_owner(&self, owner_id: Uuid, api_key_hash: &str) -> anyhow::Result<()>;
+
+    /// Owner of the API key with the hash, if it's known.
+    async fn owner_of_api_key(&self, api_key_hash: &str) -> anyhow::Result<Option<Uuid>>;
+
+    /// ID of the page with the URL. The page is created for
+    /// the owner if it doesn't exist, otherwise the metadata
+    /// is ignored.
+    async fn register(&self, page: &NewPage, owner: Uuid) -> anyhow::Result<Uuid>;
+
+    /// Like [`PageStore::register`], for many URLs without
+    /// metadata. Returns the page IDs keyed by URL.
+    async fn register_urls(
+	&self,
+	urls: &[&Url],
+	owner: Uuid,
+    ) -> anyhow::Result<HashMap<String, Uuid>>;
+
+    /// IDs of the pages with the URL.
+    async fn page_ids(&self, url: &Url) -> anyhow::Result<Vec<Uuid>>;
+
+    /// Owner of the page, if it exists.
+    async fn owner_of_page(&self, page_id: Uuid) -> anyhow::Result<Option<Uuid>>;
+
+    /// Hits of the page, if it exists and shows a badge.
+    async fn badge_hits(&self, page_id: Uuid) -> anyhow::Result<Option<i32>>;
+
+    /// Apply the changes to the page, which must exist. Share
+    /// links point to the dashboard at `base_url`.
+    async fn update_page(
+	&self,
+	page_id: Uuid,
+	update: &PageUpdate,
+	base_url: &Url,
+    ) -> anyhow::Result<Page>;
+
+    /// Count a hit of the page, or record an event on it,
+    /// unless the page doesn't exist.
+    async fn record_hit(
+	&self,
+	page_id: Uuid,
+	event: Option<&str>,
+	dimensions: &HitDimensions,
+    ) -> anyhow::Result<()>;
+
+    /// Hits and events of the page with the URL, optionally
+    /// only those of a campaign. Fails if the page doesn't
+    /// exist, unless a campaign is given.
+    async fn hits(&self, url: &Url, campaign: &Campaign) -> anyhow::Result<Hits>;
+}
+
+/// Recent visitors of the pages, to count every visitor
+/// once per visit, and to tell how many are active.
+#[async_trait]
+pub trait VisitorStore: Send + Sync {
+    /// Note that the visitor is on the page, and check if it's
+    /// a new visit, of the page or of an event on it. A visit
+    /// ends `visit_duration` seconds after the last one.
+    async fn check_in(
+	&self,
+	page_id: Uuid,
+	event: Option<&str>,
+	visitor: u64,
+	visit_duration: u64,
+    ) -> anyhow::Result<VisitStatus>;
+
+    /// Number of distinct visitors that were on any of the
+    /// pages within the last
+    /// [`ACTIVE_WINDOW_SECS`](crate::routes::ACTIVE_WINDOW_SECS)
+    /// seconds.
+    async fn active_visitors(&self, page_ids: &[Uuid]) -> anyhow::Result<u64>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VisitStatus {
+    /// Visitor hasn't been seen within the visit duration.
+    New,
+    /// Visitor has been seen within the visit duration.
+    Old,
+}
+
+/// The stores of the configured backend.
+#[derive(Clone)]
+pub struct Storage {
+    pub pages: Arc<dyn PageStore>,
+    pub visitors: Arc<dyn VisitorStore>,
+    /// Postgres and Redis, for everything that isn't behind
+    /// the stores. Postgres is only set with the `postgres`
+    /// backend, Redis whenever it's used at all.
+    pub postgres: Option<PgPool>,
+    pub redis: Option<RedisPool>,
+    /// Circuit breaker of Redis, if it's used.
+    pub redis_circuit: Option<Arc<CircuitBreaker>>,
+}
+
+impl Storage {
+    pub async fn build(configuration: &Settings) -> anyhow::Result<Self> {
+	let settings = &configuration.storage;
+	let (pages, postgres, redis): (Arc<dyn PageStore>, _, _) = match settings.backend {
+	    StorageBackend::Postgres => {
+		let postgres = get_pg_connection_pool(&configuration.postgres).await;
+		let redis = get_redis_connection_pool(&configuration.redis)?;
+		(Arc::new(PostgresPageStore::new(postgres.clone())), Some(postgres), Some(redis))
+	    },
+	    StorageBackend::Sqlite => {
+		(Arc::new(SqlitePageStore::open(&settings.sqlite_path).await?), None, None)
+	    },
+	    StorageBackend::Memory => (Arc::new(MemoryPageStore::default()), None, None),
+	};
+	let visitor_redis = match (settings.visitor_backend(), &redis) {
+	    (VisitorBackend::Redis, Some(redis)) => Some(redis.clone()),
+	    (VisitorBackend::Redis, None) => Some(get_redis_connection_pool(&configuration.redis)?),
+	    (VisitorBackend::Memory, _) => None,
+	};
+	let redis_circuit = Arc::new(
+	    CircuitBreaker::new("Redis", configuration.redis.retry_interval())
+	);
+	// Check Redis once, so that it's known to be down before
+	// the first request.
+	let redis = redis.or_else(|| visitor_redis.clone());
+	if let Some(redis) = &redis {
+	    redis_circuit.call(|| ping_redis(redis));
+	}
+	let visitors: Arc<dyn VisitorStore> = match visitor_redis {
+	    Some(redis) => Arc::new(FallbackVisitorStore::new(
+		RedisVisitorStore::new(redis),
+		redis_circuit.clone(),
+		configuration.redis.fallback,
+		MemoryVisitorStore::new(settings.visitor_capacity),
+	    )),
+	    None => {
+		let replicas = configuration.application.replicas.unwrap_or(1);
+		if replicas > 1 {
+		    tracing::warn!(
+			replicas,
+			"Visitors are kept in memory, but there is more than one replica. \
+			 Each replica only knows the visitors it has seen, so a visitor is \
+			 counted up to once per replica per visit, and active visitors are \
+			 undercounted. Set storage.visitors to redis."
+		    );
+		}
+		Arc::new(MemoryVisitorStore::new(settings.visitor_capacity))
+	    },
+	};
+	Ok(Self {
+	    pages,
+	    visitors,
+	    postgres,
+	    redis_circuit: redis.is_some().then_some(redis_circuit),
+	    redis,
+	})
+    }
+}
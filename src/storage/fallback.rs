@@ -0,0 +1,67 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use uuid::Uuid;
+use crate::circuit_breaker::CircuitBreaker;
+use crate::configuration::RedisFallback;
+use crate::storage::{MemoryVisitorStore, RedisVisitorStore, VisitStatus, VisitorStore};
+
+/// Visitors in Redis, with a fallback for while Redis is down,
+/// so that hits don't fail with it.
+pub struct FallbackVisitorStore {
+    redis: RedisVisitorStore,
+    circuit: Arc<CircuitBreaker>,
+    fallback: RedisFallback,
+    /// Visitors that came while Redis was down, with the
+    /// `memory` fallback.
+    memory: MemoryVisitorStore,
+}
+
+impl FallbackVisitorStore {
+    pub fn new(
+	redis: RedisVisitorStore,
+	circuit: Arc<CircuitBreaker>,
+	fallback: RedisFallback,
+	memory: MemoryVisitorStore,
+    ) -> Self {
+	Self { redis, circuit, fallback, memory }
+    }
+}
+
+#[async_trait]
+impl VisitorStore for FallbackVisitorStore {
+    async fn check_in(
+	&self,
+	page_id: Uuid,
+	event: Option<&str>,
+	visitor: u64,
+	visit_duration: u64,
+    ) -> anyhow::Result<VisitStatus> {
+	if self.circuit.try_call() {
+	    let result = self.redis.check_in(page_id, event, visitor, visit_duration).await;
+	    self.circuit.record(&result);
+	    if result.is_ok() {
+		return result;
+	    }
+	}
+	match self.fallback {
+	    RedisFallback::Count => Ok(VisitStatus::New),
+	    RedisFallback::Memory => {
+		self.memory.check_in(page_id, event, visitor, visit_duration).await
+	    },
+	    // Every visitor counts as seen, so no hit is counted.
+	    RedisFallback::Drop => Ok(VisitStatus::Old),
+	}
+    }
+
+    async fn active_visitors(&self, page_ids: &[Uuid]) -> anyhow::Result<u64> {
+	if self.circuit.try_call() {
+	    let result = self.redis.active_visitors(page_ids).await;
+	    self.circuit.record(&result);
+	    if result.is_ok() {
+		return result;
+	    }
+	}
+	// Only the `memory` fallback knows any visitors.
+	self.memory.active_visitors(page_ids).await
+    }
+}
@@ -0,0 +1,377 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+use std::sync::Mutex;
+use anyhow::Context;
+use async_trait::async_trait;
+use url::Url;
+use uuid::Uuid;
+use crate::routes::{share_link, ACTIVE_WINDOW_SECS, Campaign, HitDimensions, Hits, NewPage, Page, PageUpdate};
+use crate::storage::{PageStore, VisitStatus, VisitorStore};
+use crate::utils::unix_time_secs;
+
+/// Pages and hits in memory. Everything is lost when the
+/// service stops, so it's meant for tests and trying JHM out.
+#[derive(Default)]
+pub struct MemoryPageStore {
+    inner: Mutex<Pages>,
+}
+
+#[derive(Default)]
+struct Pages {
+    owners: HashMap<String, Uuid>,
+    page_ids: HashMap<String, Uuid>,
+    pages: HashMap<Uuid, MemoryPage>,
+    hits: Vec<RecordedHit>,
+}
+
+struct MemoryPage {
+    url: String,
+    owner: Uuid,
+    title: Option<String>,
+    tags: Vec<String>,
+    group: Option<String>,
+    hits: i32,
+    badge: bool,
+    public: bool,
+    share_secret: Option<String>,
+}
+
+impl MemoryPage {
+    fn new(url: &Url, owner: Uuid) -> Self {
+	Self {
+	    url: url.to_string(),
+	    owner,
+	    title: None,
+	    tags: vec![],
+	    group: None,
+	    hits: 0,
+	    badge: false,
+	    public: false,
+	    share_secret: None,
+	}
+    }
+}
+
+struct RecordedHit {
+    page_id: Uuid,
+    timestamp: i64,
+    event: Option<String>,
+    campaign: Campaign,
+}
+
+impl MemoryPageStore {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Pages> {
+	self.inner.lock().expect("Page store was poisoned")
+    }
+}
+
+impl Pages {
+    /// ID of the page with the URL, creating it for the owner
+    /// if it doesn't exist.
+    fn register(
+	&mut self,
+	url: &Url,
+	owner: Uuid,
+	page: impl FnOnce(MemoryPage) -> MemoryPage,
+    ) -> Uuid {
+	if let Some(page_id) = self.page_ids.get(url.as_str()) {
+	    return *page_id;
+	}
+	let page_id = Uuid::new_v4();
+	self.page_ids.insert(url.to_string(), page_id);
+	self.pages.insert(page_id, page(MemoryPage::new(url, owner)));
+	page_id
+    }
+}
+
+#[async_trait]
+impl PageStore for MemoryPageStore {
+    async fn insert_owner(&self, owner_id: Uuid, api_key_hash: &str) -> anyhow::Result<()> {
+	self.lock().owners.insert(api_key_hash.to_string(), owner_id);
+	Ok(())
+    }
+
+    async fn owner_of_api_key(&self, api_key_hash: &str) -> anyhow::Result<Option<Uuid>> {
+	Ok(self.lock().owners.get(api_key_hash).copied())
+    }
+
+    async fn register(&self, page: &NewPage, owner: Uuid) -> anyhow::Result<Uuid> {
+	Ok(self.lock().register(&page.url, owner, |new| MemoryPage {
+	    title: page.title.clone(),
+	    tags: page.tags.clone(),
+	    group: page.group.clone(),
+	    ..new
+	}))
+    }
+
+    async fn register_urls(
+	&self,
+	urls: &[&Url],
+	owner: Uuid,
+    ) -> anyhow::Result<HashMap<String, Uuid>> {
+	let mut pages = self.lock();
+	Ok(urls.iter()
+	   .map(|url| (url.to_string(), pages.register(url, owner, |new| new)))
+	   .collect())
+    }
+
+    async fn page_ids(&self, url: &Url) -> anyhow::Result<Vec<Uuid>> {
+	Ok(self.lock().page_ids.get(url.as_str()).copied().into_iter().collect())
+    }
+
+    async fn owner_of_page(&self, page_id: Uuid) -> anyhow::Result<Option<Uuid>> {
+	Ok(self.lock().pages.get(&page_id).map(|page| page.owner))
+    }
+
+    async fn badge_hits(&self, page_id: Uuid) -> anyhow::Result<Option<i32>> {
+	Ok(self.lock().pages.get(&page_id)
+	   .filter(|page| page.badge)
+	   .map(|page| page.hits))
+    }
+
+    async fn update_page(
+	&self,
+	page_id: Uuid,
+	update: &PageUpdate,
+	base_url: &Url,
+    ) -> anyhow::Result<Page> {
+	let mut pages = self.lock();
+	let page = pages.pages.get_mut(&page_id)
+	    .with_context(|| format!("No page with ID {page_id}"))?;
+	let non_empty = |value: &String| (!value.is_empty()).then(|| value.clone());
+	if let Some(badge) = update.badge {
+	    page.badge = badge;
+	}
+	if let Some(public) = update.public {
+	    page.public = public;
+	}
+	if let Some(share_link) = update.share_link {
+	    page.share_secret = share_link.then(|| Uuid::new_v4().simple().to_string());
+	}
+	if let Some(title) = &update.title {
+	    page.title = non_empty(title);
+	}
+	if let Some(tags) = &update.tags {
+	    page.tags = tags.clone();
+	}
+	if let Some(group) = &update.group {
+	    page.group = non_empty(group);
+	}
+	Ok(Page {
+	    page_id,
+	    url: page.url.clone(),
+	    title: page.title.clone(),
+	    tags: page.tags.clone(),
+	    group: page.group.clone(),
+	    hits: page.hits,
+	    badge: page.badge,
+	    public: page.public,
+	    share_link: page.share_secret.as_ref()
+		.map(|secret| share_link(base_url, page_id, secret)),
+	})
+    }
+
+    async fn record_hit(
+	&self,
+	page_id: Uuid,
+	event: Option<&str>,
+	dimensions: &HitDimensions,
+    ) -> anyhow::Result<()> {
+	let mut pages = self.lock();
+	let Some(page) = pages.pages.get_mut(&page_id) else {
+	    return Ok(());
+	};
+	// Events don't count towards the hits of the page.
+	if event.is_none() {
+	    page.hits += 1;
+	}
+	pages.hits.push(RecordedHit {
+	    page_id,
+	    timestamp: unix_time_secs().try_into().expect("It's not 2100"),
+	    event: event.map(str::to_string),
+	    campaign: dimensions.campaign.clone(),
+	});
+	Ok(())
+    }
+
+    async fn hits(&self, url: &Url, campaign: &Campaign) -> anyhow::Result<Hits> {
+	let pages = self.lock();
+	let page_id = pages.page_ids.get(url.as_str()).copied();
+	if page_id.is_none() && campaign.is_empty() {
+	    anyhow::bail!("Failed to get hits of page url: {url}");
+	}
+	let matches = |value: &Option<String>, filter: &Option<String>| {
+	    filter.is_none() || value == filter
+	};
+	let mut timestamps = vec![];
+	let mut events = BTreeMap::new();
+	for hit in pages.hits.iter().filter(|hit| {
+	    Some(hit.page_id) == page_id
+		&& matches(&hit.campaign.utm_source, &campaign.utm_source)
+		&& matches(&hit.campaign.utm_medium, &campaign.utm_medium)
+		&& matches(&hit.campaign.utm_campaign, &campaign.utm_campaign)
+	}) {
+	    match &hit.event {
+		Some(event) => *events.entry(event.clone()).or_insert(0) += 1,
+		None => timestamps.push(hit.timestamp),
+	    }
+	}
+	Ok(Hits {
+	    n: timestamps.len().try_into().context("Too many hits")?,
+	    timestamps,
+	    events,
+	    active_visitors: 0,
+	})
+    }
+}
+
+/// Visitors in memory. Only the instance that saw a visitor
+/// knows it, so it's meant for a single instance. Visits are
+/// forgotten once they have ended, and the oldest ones are
+/// forgotten first when there are more than the capacity.
+pub struct MemoryVisitorStore {
+    inner: Mutex<Visitors>,
+}
+
+struct Visitors {
+    /// When each visitor was last counted, by page and event.
+    visits: ExpiringMap<(Uuid, Option<String>, u64)>,
+    /// When each visitor was last seen, by page.
+    active: ExpiringMap<(Uuid, u64)>,
+    /// Visitors in `active`, by page, so that counting them
+    /// doesn't go through the visitors of every page.
+    active_by_page: HashMap<Uuid, HashSet<u64>>,
+}
+
+impl MemoryVisitorStore {
+    /// Store that keeps up to `capacity` visits, and as many
+    /// active visitors.
+    pub fn new(capacity: usize) -> Self {
+	Self {
+	    inner: Mutex::new(Visitors {
+		visits: ExpiringMap::new(capacity),
+		active: ExpiringMap::new(capacity),
+		active_by_page: HashMap::new(),
+	    }),
+	}
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Visitors> {
+	self.inner.lock().expect("Visitor store was poisoned")
+    }
+}
+
+#[async_trait]
+impl VisitorStore for MemoryVisitorStore {
+    async fn check_in(
+	&self,
+	page_id: Uuid,
+	event: Option<&str>,
+	visitor: u64,
+	visit_duration: u64,
+    ) -> anyhow::Result<VisitStatus> {
+	let now = unix_time_secs();
+	let mut visitors = self.lock();
+	let mut forgotten = visitors.active.evict(now, ACTIVE_WINDOW_SECS);
+	forgotten.extend(visitors.active.insert((page_id, visitor), now));
+	visitors.active_by_page.entry(page_id).or_default().insert(visitor);
+	for (page_id, visitor) in forgotten {
+	    if let Some(page_visitors) = visitors.active_by_page.get_mut(&page_id) {
+		page_visitors.remove(&visitor);
+		if page_visitors.is_empty() {
+		    visitors.active_by_page.remove(&page_id);
+		}
+	    }
+	}
+
+	visitors.visits.evict(now, visit_duration);
+	let key = (page_id, event.map(str::to_string), visitor);
+	match visitors.visits.get(&key) {
+	    Some(seen) if now.saturating_sub(seen) <= visit_duration => Ok(VisitStatus::Old),
+	    _ => {
+		visitors.visits.insert(key, now);
+		Ok(VisitStatus::New)
+	    },
+	}
+    }
+
+    async fn active_visitors(&self, page_ids: &[Uuid]) -> anyhow::Result<u64> {
+	let since = unix_time_secs().saturating_sub(ACTIVE_WINDOW_SECS);
+	let visitors = self.lock();
+	let active: HashSet<u64> = page_ids.iter()
+	    .filter_map(|page_id| visitors.active_by_page.get(page_id)
+			.map(|page_visitors| (page_id, page_visitors)))
+	    .flat_map(|(page_id, page_visitors)| page_visitors.iter()
+		      .filter(|visitor| visitors.active.get(&(*page_id, **visitor))
+			      .is_some_and(|seen| seen >= since)))
+	    .copied()
+	    .collect();
+	Ok(active.len() as u64)
+    }
+}
+
+/// When each key was last set, in seconds since the epoch.
+/// Keys are evicted in the order they were set, once they
+/// are older than the TTL, or once there are too many.
+struct ExpiringMap<K> {
+    capacity: usize,
+    /// Time of each key, and the number of the entry in the
+    /// queue that set it.
+    times: HashMap<K, (u64, u64)>,
+    /// Keys in the order they were set, with their time and
+    /// number. A key that was set again is in here more than
+    /// once, and only its last entry counts.
+    queue: VecDeque<(u64, u64, K)>,
+    next: u64,
+}
+
+impl<K: Clone + Eq + Hash> ExpiringMap<K> {
+    fn new(capacity: usize) -> Self {
+	Self {
+	    capacity,
+	    times: HashMap::new(),
+	    queue: VecDeque::new(),
+	    next: 0,
+	}
+    }
+
+    fn get(&self, key: &K) -> Option<u64> {
+	self.times.get(key).map(|(time, _)| *time)
+    }
+
+    /// Set the time of the key, evicting the oldest key if
+    /// the map is full. Returns the keys that were forgotten.
+    fn insert(&mut self, key: K, time: u64) -> Vec<K> {
+	let number = self.next;
+	self.next += 1;
+	self.times.insert(key.clone(), (time, number));
+	self.queue.push_back((time, number, key));
+	// The queue is never shorter than the map, so bounding
+	// it bounds both.
+	let mut forgotten = vec![];
+	while self.queue.len() > self.capacity {
+	    forgotten.extend(self.pop());
+	}
+	forgotten
+    }
+
+    /// Forget the keys that were set more than `ttl` seconds
+    /// before `now`, and return them.
+    fn evict(&mut self, now: u64, ttl: u64) -> Vec<K> {
+	let mut forgotten = vec![];
+	while self.queue.front().is_some_and(|(time, _, _)| time + ttl < now) {
+	    forgotten.extend(self.pop());
+	}
+	forgotten
+    }
+
+    fn pop(&mut self) -> Option<K> {
+	let (_, number, key) = self.queue.pop_front()?;
+	// Keys that were set again since stay.
+	if self.times.get(&key).is_some_and(|(_, last)| *last == number) {
+	    self.times.remove(&key);
+	    return Some(key);
+	}
+	None
+    }
+}
@@ -0,0 +1,558 @@
+use std::collections::{BTreeMap, HashMap};
+use anyhow::Context;
+use async_trait::async_trait;
+use redis::Commands;
+use sqlx::PgPool;
+use url::Url;
+use uuid::Uuid;
+use crate::rollups::{self, Dimension};
+use crate::routes::{active_visitors, mark_active, page_ids_of, share_link, Campaign, HitDimensions, Hits, NewPage, Page, PageUpdate};
+use crate::storage::{PageStore, VisitStatus, VisitorStore};
+use crate::utils::{RedisPool, unix_time_secs};
+
+/// Pages and hits in Postgres.
+#[derive(Clone)]
+pub struct PostgresPageStore {
+    pg_pool: PgPool,
+}
+
+impl PostgresPageStore {
+    pub fn new(pg_pool: PgPool) -> Self {
+	Self { pg_pool }
+    }
+}
+
+#[async_trait]
+impl PageStore for PostgresPageStore {
+    async fn insert_owner(&self, owner_id: Uuid, api_key_hash: &str) -> anyhow::Result<()> {
+	sqlx::query!(
+	    r#"
+INSERT INTO owners (owner_id, api_key_hash)
+VALUES ($1, $2)"#,
+	    owner_id,
+	    api_key_hash,
+	)
+	    .execute(&self.pg_pool)
+	    .await
+	    .context("Failed to insert new owner")?;
+	Ok(())
+    }
+
+    async fn owner_of_api_key(&self, api_key_hash: &str) -> anyhow::Result<Option<Uuid>> {
+	let rec = sqlx::query!(
+	    r#"
+SELECT owner_id
+FROM owners
+WHERE api_key_hash = $1"#,
+	    api_key_hash,
+	)
+	    .fetch_optional(&self.pg_pool)
+	    .await
+	    .context("Failed to look up API key")?;
+	Ok(rec.map(|rec| rec.owner_id))
+    }
+
+    async fn register(&self, page: &NewPage, owner: Uuid) -> anyhow::Result<Uuid> {
+	insert_page(page, owner, &self.pg_pool).await
+    }
+
+    async fn register_urls(
+	&self,
+	urls: &[&Url],
+	owner: Uuid,
+    ) -> anyhow::Result<HashMap<String, Uuid>> {
+	insert_pages(urls, owner, &self.pg_pool).await
+    }
+
+    async fn page_ids(&self, url: &Url) -> anyhow::Result<Vec<Uuid>> {
+	page_ids_of(Some(url), None, &self.pg_pool).await
+    }
+
+    async fn owner_of_page(&self, page_id: Uuid) -> anyhow::Result<Option<Uuid>> {
+	let rec = sqlx::query!(
+	    r#"
+SELECT owner
+FROM pages
+WHERE page_id = $1"#,
+	    page_id,
+	)
+	    .fetch_optional(&self.pg_pool)
+	    .await
+	    .context("Failed to look up page owner")?;
+	Ok(rec.map(|rec| rec.owner))
+    }
+
+    async fn badge_hits(&self, page_id: Uuid) -> anyhow::Result<Option<i32>> {
+	let rec = sqlx::query!(
+	    r#"
+SELECT hits
+FROM pages
+WHERE page_id = $1 AND badge"#,
+	    page_id,
+	)
+	    .fetch_optional(&self.pg_pool)
+	    .await
+	    .context("Failed to get hits of page with badge")?;
+	Ok(rec.map(|rec| rec.hits))
+    }
+
+    #[tracing::instrument(
+	name = "Store page update",
+	skip(self)
+    )]
+    async fn update_page(
+	&self,
+	page_id: Uuid,
+	update: &PageUpdate,
+	base_url: &Url,
+    ) -> anyhow::Result<Page> {
+	let new_share_secret = Uuid::new_v4().simple().to_string();
+	let rec = sqlx::query!(
+	    r#"
+UPDATE pages
+SET badge = COALESCE($2, badge),
+    public = COALESCE($3, public),
+    share_secret = CASE
+	WHEN $4::boolean IS NULL THEN share_secret
+	WHEN $4 THEN $5
+	ELSE NULL
+    END,
+    title = CASE WHEN $6::text IS NULL THEN title ELSE NULLIF($6, '') END,
+    tags = COALESCE($7, tags),
+    page_group = CASE WHEN $8::text IS NULL THEN page_group ELSE NULLIF($8, '') END
+WHERE page_id = $1
+RETURNING page_id, url, title, tags, page_group, hits, badge, public, share_secret"#,
+	    page_id,
+	    update.badge,
+	    update.public,
+	    update.share_link,
+	    new_share_secret,
+	    update.title,
+	    update.tags.as_deref(),
+	    update.group,
+	)
+	    .fetch_one(&self.pg_pool)
+	    .await
+	    .context("Failed to update page")?;
+	Ok(Page {
+	    page_id: rec.page_id,
+	    url: rec.url,
+	    title: rec.title,
+	    tags: rec.tags,
+	    group: rec.page_group,
+	    hits: rec.hits,
+	    badge: rec.badge,
+	    public: rec.public,
+	    share_link: rec.share_secret
+		.map(|secret| share_link(base_url, rec.page_id, &secret)),
+	})
+    }
+
+    async fn record_hit(
+	&self,
+	page_id: Uuid,
+	event: Option<&str>,
+	dimensions: &HitDimensions,
+    ) -> anyhow::Result<()> {
+	match event {
+	    Some(event) => record_event(page_id, event, dimensions, &self.pg_pool).await,
+	    None => increment_hit(page_id, dimensions, &self.pg_pool).await,
+	}
+    }
+
+    async fn hits(&self, url: &Url, campaign: &Campaign) -> anyhow::Result<Hits> {
+	let events = events_of_page_url(url, campaign, &self.pg_pool).await?;
+	let mut hits = if campaign.is_empty() {
+	    hits_of_page_url(url, &self.pg_pool).await
+	} else {
+	    campaign_hits_of_page_url(url, campaign, &self.pg_pool).await
+	}?;
+	hits.events = events;
+	Ok(hits)
+    }
+}
+
+/// Insert the pages that don't exist yet. Returns the page
+/// IDs of all URLs, keyed by URL.
+#[tracing::instrument(
+    name = "Insert new pages",
+    skip(urls, db_pool)
+)]
+async fn insert_pages(
+    urls: &[&Url],
+    owner: Uuid,
+    db_pool: &PgPool,
+) -> anyhow::Result<HashMap<String, Uuid>> {
+    let url_strs: Vec<String> = urls.iter().map(|url| url.to_string()).collect();
+    let mut page_ids: HashMap<String, Uuid> = sqlx::query!(
+	r#"
+SELECT page_id, url
+FROM pages
+WHERE url = ANY($1)"#,
+	&url_strs,
+    )
+	.fetch_all(db_pool)
+	.await
+	.context("Failed to check which pages exist")?
+	.into_iter()
+	.map(|rec| (rec.url, rec.page_id))
+	.collect();
+
+    let mut new_page_ids = vec![];
+    let mut new_urls = vec![];
+    let mut new_sites = vec![];
+    for url in urls {
+	if page_ids.contains_key(url.as_str()) {
+	    continue;
+	}
+	let page_id = Uuid::new_v4();
+	page_ids.insert(url.to_string(), page_id);
+	new_page_ids.push(page_id);
+	new_urls.push(url.to_string());
+	new_sites.push(url.host_str().unwrap_or_default().to_string());
+    }
+    sqlx::query!(
+	r#"
+INSERT INTO pages (page_id, owner, url, site)
+SELECT page_id, $1, url, site
+FROM UNNEST($2::uuid[], $3::text[], $4::text[]) AS new (page_id, url, site)"#,
+	owner,
+	&new_page_ids,
+	&new_urls,
+	&new_sites,
+    )
+	.execute(db_pool)
+	.await
+	.context("Failed to insert new pages")?;
+    Ok(page_ids)
+}
+
+#[tracing::instrument(
+    name = "Insert a new page",
+    skip(db_pool)
+)]
+async fn insert_page(
+    page: &NewPage,
+    owner: Uuid,
+    db_pool: &PgPool,
+) -> anyhow::Result<Uuid> {
+    let rec = sqlx::query!(
+	r#"
+SELECT page_id
+FROM pages
+WHERE url = $1"#,
+	page.url.as_str(),
+    )
+	.fetch_optional(db_pool)
+	.await
+	.context("Failed to check if page exists")?;
+    match rec {
+	Some (rec) => Ok(rec.page_id),
+	None => {
+	    // Create a new page.
+	    let page_id = Uuid::new_v4();
+	    sqlx::query!(
+		r#"
+INSERT INTO pages (page_id, owner, url, site, title, tags, page_group)
+VALUES ($1, $2, $3, $4, $5, $6, $7)"#,
+		page_id,
+		owner,
+		page.url.as_str(),
+		page.site(),
+		page.title.as_deref(),
+		&page.tags,
+		page.group.as_deref(),
+	    )
+		.execute(db_pool)
+		.await
+		.context("Failed to insert new page")?;
+	    Ok(page_id)
+	}
+    }
+}
+
+#[tracing::instrument(
+    name = "Increment page hits",
+    skip (pg_pool)
+)]
+pub async fn increment_hit(
+    page_id: uuid::Uuid,
+    dimensions: &HitDimensions,
+    pg_pool: &PgPool,
+) -> anyhow::Result<()>
+{
+    // i64 because of sqlx' type constraints.
+    let now: i64 = unix_time_secs()
+	.try_into()
+	.expect("It's not 2100");
+    let mut transaction = pg_pool.begin()
+	.await
+	.context("Failed to start transaction")?;
+    let recorded = sqlx::query!(
+	r#"
+WITH page AS (
+UPDATE pages
+SET hits = hits + 1,
+    timestamps = ARRAY_APPEND(timestamps, $1)
+WHERE page_id = $2
+RETURNING page_id
+)
+INSERT INTO page_hits (page_id, timestamp, utm_source, utm_medium, utm_campaign, referrer, country, device)
+SELECT page_id, $1, $3, $4, $5, $6, $7, $8
+FROM page"#,
+	now,
+	page_id,
+	dimensions.campaign.utm_source,
+	dimensions.campaign.utm_medium,
+	dimensions.campaign.utm_campaign,
+	dimensions.referrer,
+	dimensions.country,
+	dimensions.device.map(|device| device.as_str()),
+    )
+	.execute(&mut *transaction)
+	.await
+	.context("Failed to increase hits count")?
+	.rows_affected();
+    if recorded > 0 {
+	rollups::add_hit(&mut transaction, page_id, now, None, dimensions).await?;
+    }
+    transaction.commit()
+	.await
+	.context("Failed to commit hit")?;
+    Ok(())
+}
+
+/// Record an event. Unlike page hits, events don't
+/// count towards the number of hits of the page.
+#[tracing::instrument(
+    name = "Record page event",
+    skip (pg_pool)
+)]
+pub async fn record_event(
+    page_id: uuid::Uuid,
+    event: &str,
+    dimensions: &HitDimensions,
+    pg_pool: &PgPool,
+) -> anyhow::Result<()>
+{
+    let now: i64 = unix_time_secs()
+	.try_into()
+	.expect("It's not 2100");
+    let mut transaction = pg_pool.begin()
+	.await
+	.context("Failed to start transaction")?;
+    let recorded = sqlx::query!(
+	r#"
+INSERT INTO page_hits (page_id, timestamp, event, utm_source, utm_medium, utm_campaign, referrer, country, device)
+SELECT page_id, $1, $3, $4, $5, $6, $7, $8, $9
+FROM pages
+WHERE page_id = $2"#,
+	now,
+	page_id,
+	event,
+	dimensions.campaign.utm_source,
+	dimensions.campaign.utm_medium,
+	dimensions.campaign.utm_campaign,
+	dimensions.referrer,
+	dimensions.country,
+	dimensions.device.map(|device| device.as_str()),
+    )
+	.execute(&mut *transaction)
+	.await
+	.context("Failed to record event")?
+	.rows_affected();
+    if recorded > 0 {
+	rollups::add_hit(&mut transaction, page_id, now, Some(event), dimensions).await?;
+    }
+    transaction.commit()
+	.await
+	.context("Failed to commit event")?;
+    Ok(())
+}
+
+/// Hits of the page, with the timestamp of each one, which
+/// only the page itself has, as the rollups are by the hour.
+#[tracing::instrument(
+    name = "Get hits of page url",
+    skip(pg_pool)
+)]
+async fn hits_of_page_url(
+    url: &Url,
+    pg_pool: &PgPool,
+) -> anyhow::Result<Hits> {
+    let record = sqlx::query!(
+	r#"
+SELECT hits, timestamps
+FROM pages
+WHERE url = $1
+"#,
+	url.as_str(),
+    )
+	.fetch_one(pg_pool)
+	.await
+	.with_context(|| format!("Failed to get hits of page url: {}", url))?;
+    Ok(Hits {
+	n: record.hits,
+	timestamps: record.timestamps.unwrap_or_else(|| vec![]),
+	events: BTreeMap::new(),
+	active_visitors: 0,
+    })
+}
+
+/// Hits of the page in the campaign. Like the other hits,
+/// they come with their timestamps, so they are read from the
+/// raw hits.
+#[tracing::instrument(
+    name = "Get campaign hits of page url",
+    skip(pg_pool)
+)]
+async fn campaign_hits_of_page_url(
+    url: &Url,
+    campaign: &Campaign,
+    pg_pool: &PgPool,
+) -> anyhow::Result<Hits> {
+    let timestamps: Vec<i64> = sqlx::query!(
+	r#"
+SELECT h.timestamp
+FROM page_hits h
+JOIN pages p ON p.page_id = h.page_id
+WHERE p.url = $1
+  AND h.event IS NULL
+  AND ($2::text IS NULL OR h.utm_source = $2)
+  AND ($3::text IS NULL OR h.utm_medium = $3)
+  AND ($4::text IS NULL OR h.utm_campaign = $4)
+ORDER BY h.timestamp
+"#,
+	url.as_str(),
+	campaign.utm_source,
+	campaign.utm_medium,
+	campaign.utm_campaign,
+    )
+	.fetch_all(pg_pool)
+	.await
+	.with_context(|| format!("Failed to get campaign hits of page url: {}", url))?
+	.into_iter()
+	.map(|r| r.timestamp)
+	.collect();
+    Ok(Hits {
+	n: timestamps.len().try_into().context("Too many hits")?,
+	timestamps,
+	events: BTreeMap::new(),
+	active_visitors: 0,
+    })
+}
+
+/// Events of the page, from the daily rollups. Events aren't
+/// rolled up by campaign, so they are counted from the raw
+/// hits for one.
+#[tracing::instrument(
+    name = "Get events of page url",
+    skip(pg_pool)
+)]
+async fn events_of_page_url(
+    url: &Url,
+    campaign: &Campaign,
+    pg_pool: &PgPool,
+) -> anyhow::Result<BTreeMap<String, i64>> {
+    if campaign.is_empty() {
+	let records = sqlx::query!(
+	    r#"
+SELECT r.value AS event, SUM(r.hits)::bigint AS "n!"
+FROM hits_daily r
+JOIN pages p ON p.page_id = r.page_id
+WHERE p.url = $1
+  AND r.dimension = $2
+GROUP BY r.value
+"#,
+	    url.as_str(),
+	    Dimension::Event.as_str(),
+	)
+	    .fetch_all(pg_pool)
+	    .await
+	    .with_context(|| format!("Failed to get events of page url: {}", url))?;
+	return Ok(records.into_iter().map(|r| (r.event, r.n)).collect());
+    }
+    let records = sqlx::query!(
+	r#"
+SELECT h.event AS "event!", COUNT(*) AS "n!"
+FROM page_hits h
+JOIN pages p ON p.page_id = h.page_id
+WHERE p.url = $1
+  AND h.event IS NOT NULL
+  AND ($2::text IS NULL OR h.utm_source = $2)
+  AND ($3::text IS NULL OR h.utm_medium = $3)
+  AND ($4::text IS NULL OR h.utm_campaign = $4)
+GROUP BY h.event
+"#,
+	url.as_str(),
+	campaign.utm_source,
+	campaign.utm_medium,
+	campaign.utm_campaign,
+    )
+	.fetch_all(pg_pool)
+	.await
+	.with_context(|| format!("Failed to get events of page url: {}", url))?;
+    Ok(records.into_iter().map(|r| (r.event, r.n)).collect())
+}
+
+/// Visitors in Redis, so that all instances of the service
+/// share them.
+#[derive(Clone)]
+pub struct RedisVisitorStore {
+    redis_pool: RedisPool,
+}
+
+impl RedisVisitorStore {
+    pub fn new(redis_pool: RedisPool) -> Self {
+	Self { redis_pool }
+    }
+}
+
+#[async_trait]
+impl VisitorStore for RedisVisitorStore {
+    #[tracing::instrument(
+	name = "Check-in visiting IP address",
+	skip(self)
+    )]
+    async fn check_in(
+	&self,
+	page_id: Uuid,
+	event: Option<&str>,
+	visitor: u64,
+	visit_duration: u64,
+    ) -> anyhow::Result<VisitStatus> {
+	// Events are de-duplicated independently of page
+	// hits and of each other.
+	let visit_key = &match event {
+	    Some(event) => format!("{page_id}/{event}"),
+	    None => page_id.to_string(),
+	};
+	let addr = &visitor.to_string();
+	let mut con = self.redis_pool.get()
+	    .context("Failed to retrieve a connection")?;
+
+	let now = unix_time_secs();
+	mark_active(&mut *con, page_id, addr, now)
+	    .context("Failed to mark visitor as active")?;
+
+	if con.hexists(visit_key, addr)? {
+	    let expiry: u64 = con.hget(visit_key, addr)?;
+	    if now - expiry > visit_duration {
+		tracing::info!("New visit (but seen before)");
+		con.hset(visit_key, addr, now.to_string())?;
+		Ok(VisitStatus::New)
+	    } else {
+		tracing::info!("Visitor has been seen before");
+		Ok(VisitStatus::Old)
+	    }
+	} else {
+	    tracing::info!("New visit");
+	    con.hset(visit_key, addr, now.to_string())?;
+	    Ok(VisitStatus::New)
+	}
+    }
+
+    async fn active_visitors(&self, page_ids: &[Uuid]) -> anyhow::Result<u64> {
+	active_visitors(page_ids, &self.redis_pool)
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+use anyhow::Context;
+use async_trait::async_trait;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions};
+use url::Url;
+use uuid::Uuid;
+use crate::routes::{share_link, Campaign, HitDimensions, Hits, NewPage, Page, PageUpdate};
+use crate::storage::PageStore;
+use crate::utils::unix_time_secs;
+
+// The queries are checked at runtime, as `sqlx::query!` only
+// knows the schema of Postgres.
+
+/// Pages and hits in a SQLite file, for small deployments
+/// that don't need the stats of the Postgres backend.
+#[derive(Clone)]
+pub struct SqlitePageStore {
+    pool: SqlitePool,
+}
+
+impl SqlitePageStore {
+    /// Open the database at the path, creating and migrating
+    /// it as needed.
+    pub async fn open(path: &str) -> anyhow::Result<Self> {
+	let options = SqliteConnectOptions::new()
+	    .filename(path)
+	    .create_if_missing(true)
+	    .journal_mode(SqliteJournalMode::Wal);
+	let pool = SqlitePoolOptions::new()
+	    .acquire_timeout(std::time::Duration::from_secs(2))
+	    .connect_with(options)
+	    .await
+	    .with_context(|| format!("Failed to open SQLite database {path}"))?;
+	sqlx::migrate!("./migrations_sqlite")
+	    .run(&pool)
+	    .await
+	    .context("Failed to migrate SQLite database")?;
+	Ok(Self { pool })
+    }
+}
+
+fn parse_id(id: &str) -> anyhow::Result<Uuid> {
+    Uuid::parse_str(id).with_context(|| format!("Invalid ID in database: {id}"))
+}
+
+#[async_trait]
+impl PageStore for SqlitePageStore {
+    async fn insert_owner(&self, owner_id: Uuid, api_key_hash: &str) -> anyhow::Result<()> {
+	sqlx::query("INSERT INTO owners (owner_id, api_key_hash) VALUES (?1, ?2)")
+	    .bind(owner_id.to_string())
+	    .bind(api_key_hash)
+	    .execute(&self.pool)
+	    .await
+	    .context("Failed to insert new owner")?;
+	Ok(())
+    }
+
+    async fn owner_of_api_key(&self, api_key_hash: &str) -> anyhow::Result<Option<Uuid>> {
+	let owner_id: Option<String> = sqlx::query_scalar(
+	    "SELECT owner_id FROM owners WHERE api_key_hash = ?1"
+	)
+	    .bind(api_key_hash)
+	    .fetch_optional(&self.pool)
+	    .await
+	    .context("Failed to look up API key")?;
+	owner_id.as_deref().map(parse_id).transpose()
+    }
+
+    #[tracing::instrument(
+	name = "Insert a new page",
+	skip(self)
+    )]
+    async fn register(&self, page: &NewPage, owner: Uuid) -> anyhow::Result<Uuid> {
+	sqlx::query(
+	    r#"
+INSERT INTO pages (page_id, owner, url, site, title, tags, page_group)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+ON CONFLICT (url) DO NOTHING"#
+	)
+	    .bind(Uuid::new_v4().to_string())
+	    .bind(owner.to_string())
+	    .bind(page.url.as_str())
+	    .bind(page.site())
+	    .bind(page.title.as_deref())
+	    .bind(serde_json::to_string(&page.tags).context("Failed to encode tags")?)
+	    .bind(page.group.as_deref())
+	    .execute(&self.pool)
+	    .await
+	    .context("Failed to insert new page")?;
+	let page_id: String = sqlx::query_scalar("SELECT page_id FROM pages WHERE url = ?1")
+	    .bind(page.url.as_str())
+	    .fetch_one(&self.pool)
+	    .await
+	    .context("Failed to get page ID")?;
+	parse_id(&page_id)
+    }
+
+    #[tracing::instrument(
+	name = "Insert new pages",
+	skip(self, urls)
+    )]
+    async fn register_urls(
+	&self,
+	urls: &[&Url],
+	owner: Uuid,
+    ) -> anyhow::Result<HashMap<String, Uuid>> {
+	let mut transaction = self.pool.begin()
+	    .await
+	    .context("Failed to start transaction")?;
+	let mut page_ids = HashMap::new();
+	for url in urls {
+	    sqlx::query(
+		r#"
+INSERT INTO pages (page_id, owner, url, site)
+VALUES (?1, ?2, ?3, ?4)
+ON CONFLICT (url) DO NOTHING"#
+	    )
+		.bind(Uuid::new_v4().to_string())
+		.bind(owner.to_string())
+		.bind(url.as_str())
+		.bind(url.host_str().unwrap_or_default())
+		.execute(&mut *transaction)
+		.await
+		.context("Failed to insert new pages")?;
+	    let page_id: String = sqlx::query_scalar("SELECT page_id FROM pages WHERE url = ?1")
+		.bind(url.as_str())
+		.fetch_one(&mut *transaction)
+		.await
+		.context("Failed to get page ID")?;
+	    page_ids.insert(url.to_string(), parse_id(&page_id)?);
+	}
+	transaction.commit()
+	    .await
+	    .context("Failed to commit new pages")?;
+	Ok(page_ids)
+    }
+
+    async fn page_ids(&self, url: &Url) -> anyhow::Result<Vec<Uuid>> {
+	let page_ids: Vec<String> = sqlx::query_scalar("SELECT page_id FROM pages WHERE url = ?1")
+	    .bind(url.as_str())
+	    .fetch_all(&self.pool)
+	    .await
+	    .context("Failed to get page IDs")?;
+	page_ids.iter().map(|page_id| parse_id(page_id)).collect()
+    }
+
+    async fn owner_of_page(&self, page_id: Uuid) -> anyhow::Result<Option<Uuid>> {
+	let owner: Option<String> = sqlx::query_scalar("SELECT owner FROM pages WHERE page_id = ?1")
+	    .bind(page_id.to_string())
+	    .fetch_optional(&self.pool)
+	    .await
+	    .context("Failed to look up page owner")?;
+	owner.as_deref().map(parse_id).transpose()
+    }
+
+    async fn badge_hits(&self, page_id: Uuid) -> anyhow::Result<Option<i32>> {
+	sqlx::query_scalar("SELECT hits FROM pages WHERE page_id = ?1 AND badge")
+	    .bind(page_id.to_string())
+	    .fetch_optional(&self.pool)
+	    .await
+	    .context("Failed to get hits of page with badge")
+    }
+
+    #[tracing::instrument(
+	name = "Store page update",
+	skip(self)
+    )]
+    async fn update_page(
+	&self,
+	page_id: Uuid,
+	update: &PageUpdate,
+	base_url: &Url,
+    ) -> anyhow::Result<Page> {
+	let new_share_secret = Uuid::new_v4().simple().to_string();
+	let tags = update.tags.as_ref()
+	    .map(serde_json::to_string)
+	    .transpose()
+	    .context("Failed to encode tags")?;
+	let (url, title, tags, group, hits, badge, public, share_secret): (
+	    String, Option<String>, String, Option<String>, i32, bool, bool, Option<String>
+	) = sqlx::query_as(
+	    r#"
+UPDATE pages
+SET badge = COALESCE(?2, badge),
+    public = COALESCE(?3, public),
+    share_secret = CASE
+	WHEN ?4 IS NULL THEN share_secret
+	WHEN ?4 THEN ?5
+	ELSE NULL
+    END,
+    title = CASE WHEN ?6 IS NULL THEN title ELSE NULLIF(?6, '') END,
+    tags = COALESCE(?7, tags),
+    page_group = CASE WHEN ?8 IS NULL THEN page_group ELSE NULLIF(?8, '') END
+WHERE page_id = ?1
+RETURNING url, title, tags, page_group, hits, badge, public, share_secret"#
+	)
+	    .bind(page_id.to_string())
+	    .bind(update.badge)
+	    .bind(update.public)
+	    .bind(update.share_link)
+	    .bind(new_share_secret)
+	    .bind(update.title.as_deref())
+	    .bind(tags)
+	    .bind(update.group.as_deref())
+	    .fetch_one(&self.pool)
+	    .await
+	    .context("Failed to update page")?;
+	Ok(Page {
+	    page_id,
+	    url,
+	    title,
+	    tags: serde_json::from_str(&tags).context("Invalid tags in database")?,
+	    group,
+	    hits,
+	    badge,
+	    public,
+	    share_link: share_secret
+		.map(|secret| share_link(base_url, page_id, &secret)),
+	})
+    }
+
+    #[tracing::instrument(
+	name = "Record page hit",
+	skip(self)
+    )]
+    async fn record_hit(
+	&self,
+	page_id: Uuid,
+	event: Option<&str>,
+	dimensions: &HitDimensions,
+    ) -> anyhow::Result<()> {
+	let now: i64 = unix_time_secs()
+	    .try_into()
+	    .expect("It's not 2100");
+	let mut transaction = self.pool.begin()
+	    .await
+	    .context("Failed to start transaction")?;
+	// Events don't count towards the hits of the page.
+	if event.is_none() {
+	    sqlx::query("UPDATE pages SET hits = hits + 1 WHERE page_id = ?1")
+		.bind(page_id.to_string())
+		.execute(&mut *transaction)
+		.await
+		.context("Failed to increase hits count")?;
+	}
+	sqlx::query(
+	    r#"
+INSERT INTO page_hits (page_id, timestamp, event, utm_source, utm_medium, utm_campaign, referrer, country, device)
+SELECT page_id, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9
+FROM pages
+WHERE page_id = ?1"#
+	)
+	    .bind(page_id.to_string())
+	    .bind(now)
+	    .bind(event)
+	    .bind(dimensions.campaign.utm_source.as_deref())
+	    .bind(dimensions.campaign.utm_medium.as_deref())
+	    .bind(dimensions.campaign.utm_campaign.as_deref())
+	    .bind(dimensions.referrer.as_deref())
+	    .bind(dimensions.country.as_deref())
+	    .bind(dimensions.device.map(|device| device.as_str()))
+	    .execute(&mut *transaction)
+	    .await
+	    .context("Failed to record hit")?;
+	transaction.commit()
+	    .await
+	    .context("Failed to commit hit")?;
+	Ok(())
+    }
+
+    #[tracing::instrument(
+	name = "Get hits of page url",
+	skip(self)
+    )]
+    async fn hits(&self, url: &Url, campaign: &Campaign) -> anyhow::Result<Hits> {
+	let hits: Option<i32> = sqlx::query_scalar("SELECT hits FROM pages WHERE url = ?1")
+	    .bind(url.as_str())
+	    .fetch_optional(&self.pool)
+	    .await
+	    .with_context(|| format!("Failed to get hits of page url: {url}"))?;
+	if hits.is_none() && campaign.is_empty() {
+	    anyhow::bail!("Failed to get hits of page url: {url}");
+	}
+	let rows: Vec<(i64, Option<String>)> = sqlx::query_as(
+	    r#"
+SELECT h.timestamp, h.event
+FROM page_hits h
+JOIN pages p ON p.page_id = h.page_id
+WHERE p.url = ?1
+  AND (?2 IS NULL OR h.utm_source = ?2)
+  AND (?3 IS NULL OR h.utm_medium = ?3)
+  AND (?4 IS NULL OR h.utm_campaign = ?4)
+ORDER BY h.timestamp"#
+	)
+	    .bind(url.as_str())
+	    .bind(campaign.utm_source.as_deref())
+	    .bind(campaign.utm_medium.as_deref())
+	    .bind(campaign.utm_campaign.as_deref())
+	    .fetch_all(&self.pool)
+	    .await
+	    .with_context(|| format!("Failed to get hits of page url: {url}"))?;
+
+	let mut timestamps = vec![];
+	let mut events = std::collections::BTreeMap::new();
+	for (timestamp, event) in rows {
+	    match event {
+		Some(event) => *events.entry(event).or_insert(0) += 1,
+		None => timestamps.push(timestamp),
+	    }
+	}
+	let n = match hits {
+	    Some(hits) if campaign.is_empty() => hits,
+	    _ => timestamps.len().try_into().context("Too many hits")?,
+	};
+	Ok(Hits { n, timestamps, events, active_visitors: 0 })
+    }
+}
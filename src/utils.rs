@@ -1,5 +1,7 @@
+use anyhow::Context;
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn error_chain_fmt(
     e: &impl std::error::Error,
@@ -21,10 +23,57 @@ where
     actix_web::error::ErrorInternalServerError(e)
 }
 
+pub fn e400<T>(e: T) -> actix_web::Error
+where
+    T: std::fmt::Debug + std::fmt::Display + 'static
+{
+    actix_web::error::ErrorBadRequest(e)
+}
+
+pub fn e503<T>(e: T) -> actix_web::Error
+where
+    T: std::fmt::Debug + std::fmt::Display + 'static
+{
+    actix_web::error::ErrorServiceUnavailable(e)
+}
+
 pub type RedisPool = r2d2::Pool<redis::Client>;
 
+/// Check that Redis answers.
+pub fn ping_redis(redis_pool: &RedisPool) -> anyhow::Result<()> {
+    let mut con = redis_pool.get()
+        .context("Failed to retrieve a connection")?;
+    redis::cmd("PING")
+        .query::<()>(&mut *con)
+        .context("Failed to ping Redis")
+}
+
 pub fn hash_data<T: Hash>(t: &T) -> u64  {
     let mut s = DefaultHasher::new();
     t.hash(&mut s);
     s.finish()
 }
+
+pub fn unix_time_secs() -> u64 {
+    let start = SystemTime::now();
+    start
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
+
+/// Escape text so that it can be put into HTML or SVG.
+pub fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
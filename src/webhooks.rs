@@ -0,0 +1,411 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::PgPool;
+use url::{Host, Url};
+use uuid::Uuid;
+use crate::configuration::WebhookSettings;
+use crate::utils::unix_time_secs;
+
+/// Header that carries the signature of a delivery.
+pub const SIGNATURE_HEADER: &str = "X-JHM-Signature";
+
+/// Lowest milestone. Every further one is ten times higher.
+pub const FIRST_MILESTONE: i64 = 1_000;
+
+const DELIVERY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Body of a webhook delivery.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WebhookPayload {
+    pub webhook_id: Uuid,
+    pub page_id: Uuid,
+    pub url: String,
+    #[serde(flatten)]
+    pub trigger: Trigger,
+    /// Unix time at which the webhook was triggered.
+    pub timestamp: i64,
+}
+
+/// What triggered a webhook.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "trigger", rename_all = "lowercase")]
+pub enum Trigger {
+    /// The page reached this number of hits.
+    Milestone { hits: i64 },
+    /// The page got this number of hits within the last minutes.
+    Spike { hits: i64, minutes: i32 },
+}
+
+/// Highest milestone that the number of hits has reached,
+/// or 0 if it hasn't reached any.
+pub fn milestone(hits: i64) -> i64 {
+    let mut reached = 0;
+    let mut next = FIRST_MILESTONE;
+    while hits >= next {
+	reached = next;
+	match next.checked_mul(10) {
+	    Some(n) => next = n,
+	    None => break,
+	}
+    }
+    reached
+}
+
+/// Hex encoded HMAC-SHA256 of the payload. Receivers can
+/// compare it to the signature header to verify a delivery.
+pub fn sign(secret: &str, payload: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+	.expect("HMAC takes keys of any size");
+    mac.update(payload.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Whether webhooks must not reach the address: loopback,
+/// private and link-local addresses, which include the
+/// metadata service of cloud providers, and the like.
+pub fn is_private(ip: IpAddr) -> bool {
+    match ip {
+	IpAddr::V4(ip) => {
+	    let [a, b, ..] = ip.octets();
+	    ip.is_loopback()
+		|| ip.is_private()
+		|| ip.is_link_local()
+		|| ip.is_unspecified()
+		|| ip.is_broadcast()
+		|| ip.is_multicast()
+		// "This network" and the shared address space
+		// of carrier-grade NAT.
+		|| a == 0
+		|| (a == 100 && b & 0xc0 == 64)
+	},
+	IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+	    Some(ip) => is_private(ip.into()),
+	    None => ip.is_loopback()
+		|| ip.is_unspecified()
+		|| ip.is_multicast()
+		|| ip.is_unique_local()
+		|| ip.is_unicast_link_local(),
+	},
+    }
+}
+
+/// Check that the URL doesn't point to a private address.
+/// Hosts that don't resolve are let through, since the
+/// addresses are checked again for each delivery.
+pub async fn check_target(url: &Url) -> anyhow::Result<()> {
+    let addrs: Vec<IpAddr> = match url.host() {
+	Some(Host::Ipv4(ip)) => vec![ip.into()],
+	Some(Host::Ipv6(ip)) => vec![ip.into()],
+	Some(Host::Domain(domain)) => {
+	    let port = url.port_or_known_default().unwrap_or(80);
+	    match tokio::net::lookup_host((domain, port)).await {
+		Ok(addrs) => addrs.map(|addr| addr.ip()).collect(),
+		Err(_) => vec![],
+	    }
+	},
+	None => anyhow::bail!("The webhook URL has no host"),
+    };
+    if addrs.into_iter().any(is_private) {
+	anyhow::bail!("The webhook URL points to a private address");
+    }
+    Ok(())
+}
+
+/// Resolves the hosts of deliveries to their public addresses
+/// only, so that a host that resolved to a public address
+/// when the webhook was created can't be pointed to a private
+/// one later.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+	Box::pin(async move {
+	    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+		.await?
+		.filter(|addr| !is_private(addr.ip()))
+		.collect();
+	    if addrs.is_empty() {
+		return Err(format!("{} has no public address", name.as_str()).into());
+	    }
+	    let addrs: Addrs = Box::new(addrs.into_iter());
+	    Ok(addrs)
+	})
+    }
+}
+
+/// Check the triggers of all webhooks and deliver the
+/// notifications, until the process stops.
+pub async fn run_worker_until_stopped(pg_pool: PgPool, settings: WebhookSettings) {
+    let mut client = reqwest::Client::builder()
+	.timeout(DELIVERY_TIMEOUT)
+	.redirect(reqwest::redirect::Policy::none());
+    if !settings.allow_private_targets {
+	client = client.dns_resolver(Arc::new(PublicResolver));
+    }
+    let client = client.build().expect("Failed to build reqwest client");
+    let mut last_hit_id = 0;
+    loop {
+	if let Err(e) = check_triggers(&pg_pool, &mut last_hit_id).await {
+	    tracing::error!(error.cause_chain = ?e, "Failed to check webhook triggers");
+	}
+	loop {
+	    match try_deliver(&pg_pool, &client, &settings).await {
+		Ok(DeliveryOutcome::Attempted) => continue,
+		Ok(DeliveryOutcome::EmptyQueue) => break,
+		Err(e) => {
+		    tracing::error!(error.cause_chain = ?e, "Failed to deliver webhook");
+		    break;
+		},
+	    }
+	}
+	tokio::time::sleep(settings.poll_interval()).await;
+    }
+}
+
+/// Queue a delivery for every webhook whose trigger has fired
+/// since it was last checked. Triggers only fire with new
+/// hits, so only the webhooks of pages that were hit after
+/// `last_hit_id` are checked, and it's moved to the last hit.
+#[tracing::instrument(
+    name = "Check webhook triggers",
+    skip(pg_pool)
+)]
+async fn check_triggers(pg_pool: &PgPool, last_hit_id: &mut i64) -> anyhow::Result<()> {
+    let now = unix_time_secs() as i64;
+    let Some(max_hit_id) = sqlx::query_scalar!("SELECT MAX(hit_id) FROM page_hits")
+	.fetch_one(pg_pool)
+	.await
+	.context("Failed to get last hit")?
+    else {
+	return Ok(());
+    };
+    if max_hit_id <= *last_hit_id {
+	return Ok(());
+    }
+    // A hit whose transaction commits after this, but with a
+    // lower ID, is skipped. Its page is checked again with
+    // its next hit.
+    let recs = sqlx::query!(
+	r#"
+WITH hit_pages AS (
+  SELECT DISTINCT page_id
+  FROM page_hits
+  WHERE hit_id > $2 AND hit_id <= $3
+)
+SELECT w.webhook_id, w.page_id, p.url, p.hits,
+       w.milestones, w.last_milestone,
+       w.spike_hits, w.spike_minutes, w.last_spike_at,
+       CASE WHEN w.spike_minutes IS NULL THEN 0 ELSE (
+	 SELECT COUNT(*)
+	 FROM page_hits h
+	 WHERE h.page_id = w.page_id
+	   AND h.event IS NULL
+	   AND h.timestamp > $1 - w.spike_minutes::bigint * 60
+       ) END AS "recent_hits!"
+FROM webhooks w
+JOIN hit_pages USING (page_id)
+JOIN pages p ON p.page_id = w.page_id"#,
+	now,
+	*last_hit_id,
+	max_hit_id,
+    )
+	.fetch_all(pg_pool)
+	.await
+	.context("Failed to get webhooks")?;
+
+    for rec in recs {
+	let mut triggers = vec![];
+	let mut last_milestone = rec.last_milestone;
+	let mut last_spike_at = rec.last_spike_at;
+	let reached = milestone(rec.hits.into());
+	if rec.milestones && reached > last_milestone {
+	    triggers.push(Trigger::Milestone { hits: reached });
+	    last_milestone = reached;
+	}
+	if let (Some(spike_hits), Some(minutes)) = (rec.spike_hits, rec.spike_minutes) {
+	    // A spike is only reported once per time window.
+	    let window_over = !matches!(
+		last_spike_at, Some(at) if at > now - i64::from(minutes) * 60);
+	    if rec.recent_hits >= spike_hits.into() && window_over {
+		triggers.push(Trigger::Spike { hits: rec.recent_hits, minutes });
+		last_spike_at = Some(now);
+	    }
+	}
+	if triggers.is_empty() {
+	    continue;
+	}
+
+	let mut transaction = pg_pool.begin()
+	    .await
+	    .context("Failed to begin transaction")?;
+	// Another worker may have handled the webhook in the meantime.
+	let updated = sqlx::query!(
+	    r#"
+UPDATE webhooks
+SET last_milestone = $2,
+    last_spike_at = $3
+WHERE webhook_id = $1
+  AND last_milestone = $4
+  AND last_spike_at IS NOT DISTINCT FROM $5"#,
+	    rec.webhook_id,
+	    last_milestone,
+	    last_spike_at,
+	    rec.last_milestone,
+	    rec.last_spike_at,
+	)
+	    .execute(&mut *transaction)
+	    .await
+	    .context("Failed to update webhook")?;
+	if updated.rows_affected() == 0 {
+	    continue;
+	}
+	for trigger in triggers {
+	    let payload = WebhookPayload {
+		webhook_id: rec.webhook_id,
+		page_id: rec.page_id,
+		url: rec.url.clone(),
+		trigger,
+		timestamp: now,
+	    };
+	    sqlx::query!(
+		r#"
+INSERT INTO webhook_deliveries (webhook_id, payload)
+VALUES ($1, $2)"#,
+		rec.webhook_id,
+		serde_json::to_string(&payload).context("Failed to encode payload")?,
+	    )
+		.execute(&mut *transaction)
+		.await
+		.context("Failed to queue webhook delivery")?;
+	}
+	transaction.commit()
+	    .await
+	    .context("Failed to commit transaction")?;
+    }
+    *last_hit_id = max_hit_id;
+    Ok(())
+}
+
+enum DeliveryOutcome {
+    Attempted,
+    EmptyQueue,
+}
+
+/// Attempt the delivery that's due next. Failed deliveries
+/// are retried with exponential backoff until they run out
+/// of attempts.
+#[tracing::instrument(
+    name = "Deliver webhook",
+    skip_all
+)]
+async fn try_deliver(
+    pg_pool: &PgPool,
+    client: &reqwest::Client,
+    settings: &WebhookSettings,
+) -> anyhow::Result<DeliveryOutcome> {
+    // Claim the delivery by moving its next attempt past the
+    // time the request can take, so that no lock is held while
+    // it's sent. If the worker dies in the meantime, the
+    // delivery is retried once the claim has expired.
+    let Some(rec) = sqlx::query!(
+	r#"
+UPDATE webhook_deliveries d
+SET next_attempt_at = now() + $1::float8 * interval '1 millisecond'
+FROM webhooks w
+WHERE w.webhook_id = d.webhook_id
+  AND d.delivery_id = (
+    SELECT delivery_id
+    FROM webhook_deliveries
+    WHERE next_attempt_at <= now()
+    ORDER BY next_attempt_at
+    LIMIT 1
+    FOR UPDATE
+    SKIP LOCKED
+  )
+RETURNING d.delivery_id, d.payload, d.attempts, w.url, w.secret"#,
+	(2 * DELIVERY_TIMEOUT).as_millis() as f64,
+    )
+	.fetch_optional(pg_pool)
+	.await
+	.context("Failed to claim due delivery")?
+    else {
+	return Ok(DeliveryOutcome::EmptyQueue);
+    };
+
+    let attempts = rec.attempts + 1;
+    // The webhook may have been created with another setting.
+    let checked = match settings.allow_private_targets {
+	true => Ok(()),
+	false => check_delivery_target(&rec.url).await,
+    };
+    let result = match checked {
+	Ok(()) => send(client, &rec.url, &rec.secret, &rec.payload).await,
+	Err(e) => Err(e),
+    };
+    match result {
+	Ok(()) => delete_delivery(rec.delivery_id, pg_pool).await?,
+	Err(e) if attempts >= settings.max_attempts => {
+	    tracing::warn!(error.cause_chain = ?e, "Dropping webhook delivery after {attempts} attempts");
+	    delete_delivery(rec.delivery_id, pg_pool).await?;
+	},
+	Err(e) => {
+	    tracing::info!(error.cause_chain = ?e, "Webhook delivery failed, retrying later");
+	    let backoff_ms = settings.retry_backoff_ms
+		.saturating_mul(1 << (attempts - 1).min(20));
+	    sqlx::query!(
+		r#"
+UPDATE webhook_deliveries
+SET attempts = $2,
+    next_attempt_at = now() + $3::float8 * interval '1 millisecond'
+WHERE delivery_id = $1"#,
+		rec.delivery_id,
+		attempts,
+		backoff_ms as f64,
+	    )
+		.execute(pg_pool)
+		.await
+		.context("Failed to reschedule delivery")?;
+	},
+    }
+    Ok(DeliveryOutcome::Attempted)
+}
+
+async fn delete_delivery(delivery_id: i64, pg_pool: &PgPool) -> anyhow::Result<()> {
+    sqlx::query!(
+	"DELETE FROM webhook_deliveries WHERE delivery_id = $1",
+	delivery_id,
+    )
+	.execute(pg_pool)
+	.await
+	.context("Failed to delete delivery")?;
+    Ok(())
+}
+
+async fn check_delivery_target(url: &str) -> anyhow::Result<()> {
+    let url = Url::parse(url).context("Failed to parse webhook URL")?;
+    check_target(&url).await
+}
+
+async fn send(
+    client: &reqwest::Client,
+    url: &str,
+    secret: &str,
+    payload: &str,
+) -> anyhow::Result<()> {
+    client.post(url)
+	.header("Content-Type", "application/json")
+	.header(SIGNATURE_HEADER, format!("sha256={}", sign(secret, payload)))
+	.body(payload.to_owned())
+	.send()
+	.await
+	.context("Failed to send webhook")?
+	.error_for_status()
+	.context("Webhook receiver returned an error")?;
+    Ok(())
+}
@@ -0,0 +1,309 @@
+//! Write-behind mode for hits. Instead of writing each hit to
+//! Postgres, the hit routes push it to a list in Redis, and a
+//! background task writes the list every few seconds, in
+//! transactions of up to [`CHUNK_SIZE`] hits. A busy page then
+//! gets one row update per chunk, instead of one per hit.
+//!
+//! Hits are written at least once: a chunk is only removed
+//! from Redis after it has been committed, so a flush that
+//! fails or is interrupted is retried from the chunk it was
+//! writing.
+use std::collections::HashSet;
+use anyhow::Context;
+use redis::Commands;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tokio::sync::watch;
+use uuid::Uuid;
+use crate::rollups;
+use crate::routes::HitDimensions;
+use crate::utils::{RedisPool, unix_time_secs};
+
+/// Seconds until the lock of a flush that didn't finish
+/// expires, so that another one can take over. It's renewed
+/// after each chunk, so a chunk must be written within it.
+const LOCK_EXPIRY_SECS: u64 = 60;
+
+/// Most hits that are written in one transaction.
+pub const CHUNK_SIZE: usize = 1000;
+
+/// Renew the lock if it's still held with the token in ARGV[1].
+const RENEW_LOCK: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("EXPIRE", KEYS[1], ARGV[2])
+end
+return 0"#;
+
+/// Release the lock if it's still held with the token in ARGV[1].
+const RELEASE_LOCK: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("DEL", KEYS[1])
+end
+return 0"#;
+
+/// Hit that waits in Redis to be written to Postgres.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BufferedHit {
+    pub page_id: Uuid,
+    /// Unix time of the hit.
+    pub timestamp: i64,
+    pub event: Option<String>,
+    pub dimensions: HitDimensions,
+}
+
+impl BufferedHit {
+    pub fn now(page_id: Uuid, event: Option<&str>, dimensions: &HitDimensions) -> Self {
+	Self {
+	    page_id,
+	    timestamp: unix_time_secs()
+		.try_into()
+		.expect("It's not 2100"),
+	    event: event.map(str::to_string),
+	    dimensions: dimensions.clone(),
+	}
+    }
+}
+
+/// Redis keys of the hits that wait to be written.
+#[derive(Debug, Clone)]
+pub struct HitBuffer {
+    /// Hits that came in since the last flush.
+    pending: String,
+    /// Hits that are being written.
+    flushing: String,
+    lock: String,
+}
+
+impl HitBuffer {
+    /// Buffer for the database of the given name, so that
+    /// services with different databases can share a Redis.
+    pub fn new(database_name: &str) -> Self {
+	Self {
+	    pending: format!("hits:pending:{database_name}"),
+	    flushing: format!("hits:flushing:{database_name}"),
+	    lock: format!("hits:flush-lock:{database_name}"),
+	}
+    }
+
+    /// Queue a hit to be written by the next flush.
+    #[tracing::instrument(
+	name = "Buffer hit",
+	skip(self, redis_pool)
+    )]
+    pub fn push(&self, redis_pool: &RedisPool, hit: &BufferedHit) -> anyhow::Result<()> {
+	let hit = serde_json::to_string(hit).context("Failed to encode hit")?;
+	let mut con = redis_pool.get()
+	    .context("Failed to retrieve a connection")?;
+	con.rpush::<_, _, ()>(&self.pending, hit).context("Failed to buffer hit")?;
+	Ok(())
+    }
+
+    /// Write the buffered hits to Postgres, unless another
+    /// flush is running. Returns the number of hits written.
+    #[tracing::instrument(
+	name = "Flush buffered hits",
+	skip_all
+    )]
+    pub async fn flush(&self, pg_pool: &PgPool, redis_pool: &RedisPool) -> anyhow::Result<usize> {
+	let token = Uuid::new_v4().to_string();
+	let locked: Option<String> = redis::cmd("SET")
+	    .arg(&self.lock)
+	    .arg(&token)
+	    .arg("NX")
+	    .arg("EX")
+	    .arg(LOCK_EXPIRY_SECS)
+	    .query(&mut *redis_pool.get().context("Failed to retrieve a connection")?)
+	    .context("Failed to lock hit buffer")?;
+	if locked.is_none() {
+	    tracing::info!("Another flush is running");
+	    return Ok(0);
+	}
+
+	let mut written = 0;
+	let mut result = Ok(());
+	// A batch that's left over from a failed flush comes
+	// first, then the hits that came in since.
+	for _ in 0..2 {
+	    match self.write_batch(pg_pool, redis_pool, &token).await {
+		Ok(Some(n)) => written += n,
+		Ok(None) => break,
+		Err(e) => {
+		    result = Err(e);
+		    break;
+		},
+	    }
+	}
+
+	// Only release the lock if it hasn't expired and been
+	// taken by another flush in the meantime.
+	redis::Script::new(RELEASE_LOCK)
+	    .key(&self.lock)
+	    .arg(&token)
+	    .invoke::<()>(&mut *redis_pool.get().context("Failed to retrieve a connection")?)
+	    .context("Failed to unlock hit buffer")?;
+	result.map(|_| written)
+    }
+
+    /// Write the batch of hits that's being flushed, chunk by
+    /// chunk, taking the pending ones if there is none. Returns
+    /// `None` if there are no hits.
+    async fn write_batch(
+	&self,
+	pg_pool: &PgPool,
+	redis_pool: &RedisPool,
+	token: &str,
+    ) -> anyhow::Result<Option<usize>> {
+	{
+	    let mut con = redis_pool.get()
+		.context("Failed to retrieve a connection")?;
+	    if !con.exists(&self.flushing)? {
+		if !con.exists(&self.pending)? {
+		    return Ok(None);
+		}
+		con.rename::<_, _, ()>(&self.pending, &self.flushing)
+		    .context("Failed to take pending hits")?;
+	    }
+	}
+
+	let mut written = 0;
+	loop {
+	    let chunk: Vec<String> = redis_pool.get()
+		.context("Failed to retrieve a connection")?
+		.lrange(&self.flushing, 0, CHUNK_SIZE as isize - 1)
+		.context("Failed to read buffered hits")?;
+	    if chunk.is_empty() {
+		return Ok(Some(written));
+	    }
+	    let hits: Vec<BufferedHit> = chunk.iter()
+		.filter_map(|hit| match serde_json::from_str(hit) {
+		    Ok(hit) => Some(hit),
+		    Err(e) => {
+			tracing::warn!("Dropping malformed buffered hit {hit:?}: {e}");
+			None
+		    },
+		})
+		.collect();
+
+	    written += write_hits(pg_pool, &hits).await?;
+	    let mut con = redis_pool.get()
+		.context("Failed to retrieve a connection")?;
+	    // Removing the last hits removes the list.
+	    con.ltrim::<_, ()>(&self.flushing, chunk.len() as isize, -1)
+		.context("Failed to remove written hits")?;
+	    let renewed: bool = redis::Script::new(RENEW_LOCK)
+		.key(&self.lock)
+		.arg(token)
+		.arg(LOCK_EXPIRY_SECS)
+		.invoke(&mut *con)
+		.context("Failed to renew hit buffer lock")?;
+	    if !renewed {
+		anyhow::bail!("The lock of the hit buffer expired during the flush");
+	    }
+	}
+    }
+
+    /// Flush the buffered hits periodically. Once `stop` is
+    /// signalled, flush one last time and return.
+    pub async fn run_flusher_until_stopped(
+	self,
+	pg_pool: PgPool,
+	redis_pool: RedisPool,
+	interval: std::time::Duration,
+	mut stop: watch::Receiver<bool>,
+    ) {
+	loop {
+	    let stopping = tokio::select! {
+		_ = tokio::time::sleep(interval) => false,
+		_ = stop.changed() => true,
+	    };
+	    match self.flush(&pg_pool, &redis_pool).await {
+		Ok(0) => {},
+		Ok(hits) => tracing::info!(hits, "Flushed buffered hits"),
+		Err(e) => tracing::error!(error.cause_chain = ?e, "Failed to flush buffered hits"),
+	    }
+	    if stopping {
+		break;
+	    }
+	}
+    }
+}
+
+/// Record hits like [`crate::storage::increment_hit`] and
+/// [`crate::storage::record_event`] do, in one transaction.
+/// Returns the number of hits of pages that still exist.
+async fn write_hits(pg_pool: &PgPool, hits: &[BufferedHit]) -> anyhow::Result<usize> {
+    let mut transaction = pg_pool.begin()
+	.await
+	.context("Failed to start transaction")?;
+    let page_ids: Vec<Uuid> = hits.iter().map(|hit| hit.page_id).collect();
+    // Pages may have been deleted since they were hit.
+    let known: HashSet<Uuid> = sqlx::query_scalar!(
+	"SELECT page_id FROM pages WHERE page_id = ANY($1)",
+	&page_ids,
+    )
+	.fetch_all(&mut *transaction)
+	.await
+	.context("Failed to get pages of hits")?
+	.into_iter()
+	.collect();
+    let hits: Vec<&BufferedHit> = hits.iter()
+	.filter(|hit| known.contains(&hit.page_id))
+	.collect();
+    if hits.is_empty() {
+	return Ok(0);
+    }
+
+    let column = |f: fn(&BufferedHit) -> Option<String>| -> Vec<Option<String>> {
+	hits.iter().map(|hit| f(hit)).collect()
+    };
+    let page_ids: Vec<Uuid> = hits.iter().map(|hit| hit.page_id).collect();
+    let timestamps: Vec<i64> = hits.iter().map(|hit| hit.timestamp).collect();
+    let events = column(|hit| hit.event.clone());
+    sqlx::query!(
+	r#"
+INSERT INTO page_hits (page_id, timestamp, event, utm_source, utm_medium, utm_campaign, referrer, country, device)
+SELECT *
+FROM UNNEST($1::uuid[], $2::bigint[], $3::text[], $4::text[], $5::text[],
+	    $6::text[], $7::text[], $8::text[], $9::text[])"#,
+	&page_ids,
+	&timestamps,
+	&events as &[Option<String>],
+	&column(|hit| hit.dimensions.campaign.utm_source.clone()) as &[Option<String>],
+	&column(|hit| hit.dimensions.campaign.utm_medium.clone()) as &[Option<String>],
+	&column(|hit| hit.dimensions.campaign.utm_campaign.clone()) as &[Option<String>],
+	&column(|hit| hit.dimensions.referrer.clone()) as &[Option<String>],
+	&column(|hit| hit.dimensions.country.clone()) as &[Option<String>],
+	&column(|hit| hit.dimensions.device.map(|device| device.as_str().to_string())) as &[Option<String>],
+    )
+	.execute(&mut *transaction)
+	.await
+	.context("Failed to record buffered hits")?;
+    // Events don't count towards the hits of a page.
+    sqlx::query!(
+	r#"
+UPDATE pages p
+SET hits = p.hits + h.n,
+    timestamps = ARRAY_CAT(p.timestamps, h.timestamps)
+FROM (
+    SELECT page_id, COUNT(*) AS n, ARRAY_AGG(timestamp ORDER BY timestamp) AS timestamps
+    FROM UNNEST($1::uuid[], $2::bigint[], $3::text[]) AS h (page_id, timestamp, event)
+    WHERE event IS NULL
+    GROUP BY page_id
+) h
+WHERE p.page_id = h.page_id"#,
+	&page_ids,
+	&timestamps,
+	&events as &[Option<String>],
+    )
+	.execute(&mut *transaction)
+	.await
+	.context("Failed to increase hits counts")?;
+    rollups::add_hits(
+	&mut transaction,
+	hits.iter().map(|hit| (hit.page_id, hit.timestamp, hit.event.as_deref(), &hit.dimensions)),
+    ).await?;
+    transaction.commit()
+	.await
+	.context("Failed to commit buffered hits")?;
+    Ok(hits.len())
+}
@@ -0,0 +1,63 @@
+use crate::helper::TestApp;
+
+use jhm::routes::{ActiveVisitors, Hits};
+
+#[tokio::test]
+async fn hits_include_active_visitors() {
+    const URL: &str = "https://example.com/";
+    let test_app = TestApp::spawn().await;
+    let page_id = test_app
+	.post_register(&format!("url={URL}"))
+	.await
+	.json::<uuid::Uuid>()
+	.await
+	.unwrap();
+
+    let hits = test_app.get_hits(URL).await.json::<Hits>().await.unwrap();
+    assert_eq!(hits.active_visitors, 0);
+
+    test_app.get_route(&format!("hit/{page_id}")).await;
+    // Visitors that are seen again are still only active once.
+    test_app.get_route(&format!("hit/{page_id}")).await;
+    let hits = test_app.get_hits(URL).await.json::<Hits>().await.unwrap();
+    assert_eq!(hits.active_visitors, 1);
+}
+
+#[tokio::test]
+async fn site_counts_each_active_visitor_once() {
+    let test_app = TestApp::spawn().await;
+    for url in ["https://example.com/a", "https://example.com/b"] {
+	let page_id = test_app
+	    .post_register(&format!("url={url}"))
+	    .await
+	    .json::<uuid::Uuid>()
+	    .await
+	    .unwrap();
+	test_app.get_route(&format!("hit/{page_id}")).await;
+    }
+
+    let response = test_app.get_route("active?site=example.com").await;
+    assert!(response.status().is_success());
+    let active = response.json::<ActiveVisitors>().await.unwrap();
+    assert_eq!(active.n, 1);
+
+    let active = test_app
+	.get_route("active?site=example.org")
+	.await
+	.json::<ActiveVisitors>()
+	.await
+	.unwrap();
+    assert_eq!(active.n, 0);
+}
+
+#[tokio::test]
+async fn active_400s_without_exactly_one_of_url_and_site() {
+    let test_app = TestApp::spawn().await;
+
+    let response = test_app.get_route("active").await;
+    assert_eq!(400, response.status().as_u16());
+    let response = test_app
+	.get_route("active?site=example.com&url=https%3A%2F%2Fexample.com%2F")
+	.await;
+    assert_eq!(400, response.status().as_u16());
+}
@@ -0,0 +1,82 @@
+use crate::helper::TestApp;
+use uuid::Uuid;
+
+use jhm::routes::PageUpdate;
+
+async fn page_with_badge(test_app: &TestApp) -> Uuid {
+    let (page_id, api_key) = test_app
+	.register_owned_page("https://example.com/")
+	.await;
+    let update = PageUpdate { badge: Some(true), ..Default::default() };
+    let response = test_app.patch_page(page_id, &update, Some(&api_key)).await;
+    assert!(response.status().is_success());
+    page_id
+}
+
+#[tokio::test]
+async fn badge_shows_the_number_of_hits() {
+    let test_app = TestApp::spawn().await;
+    let page_id = page_with_badge(&test_app).await;
+    sqlx::query!("UPDATE pages SET hits = 1234 WHERE page_id = $1", page_id)
+	.execute(&test_app.db)
+	.await
+	.expect("Failed to set hits");
+
+    let response = test_app.get_route(&format!("badge/{page_id}.svg")).await;
+    assert!(response.status().is_success());
+    assert_eq!(response.headers()["Content-Type"], "image/svg+xml; charset=utf-8");
+    assert_eq!(response.headers()["Cache-Control"], "public, max-age=60");
+    let body = response.text().await.unwrap();
+    assert!(body.starts_with("<svg"));
+    assert!(body.contains("views: 1.2k"));
+}
+
+#[tokio::test]
+async fn badge_can_be_customised() {
+    let test_app = TestApp::spawn().await;
+    let page_id = page_with_badge(&test_app).await;
+
+    let response = test_app
+	.get_route(&format!(
+	    "badge/{page_id}.svg?label=readers%20%3C3&color=ff69b4&style=flat-square"
+	))
+	.await;
+    assert!(response.status().is_success());
+    let body = response.text().await.unwrap();
+    assert!(body.contains("readers &lt;3: 0"), "Label should be escaped");
+    assert!(body.contains("fill=\"#ff69b4\""));
+    assert!(body.contains("rx=\"0\""));
+}
+
+#[tokio::test]
+async fn badge_400s_on_invalid_parameters() {
+    let test_app = TestApp::spawn().await;
+    let page_id = page_with_badge(&test_app).await;
+
+    for query in [
+	"color=%22%2F%3E%3Cscript%3E",
+	"style=round",
+	&format!("label={}", "a".repeat(65)),
+    ] {
+	let response = test_app
+	    .get_route(&format!("badge/{page_id}.svg?{query}"))
+	    .await;
+	assert_eq!(400, response.status().as_u16(), "Accepted {query}");
+    }
+}
+
+#[tokio::test]
+async fn badge_is_opt_in() {
+    let test_app = TestApp::spawn().await;
+    let (page_id, _) = test_app
+	.register_owned_page("https://example.com/")
+	.await;
+
+    let response = test_app.get_route(&format!("badge/{page_id}.svg")).await;
+    assert_eq!(404, response.status().as_u16());
+
+    let response = test_app
+	.get_route(&format!("badge/{}.svg", Uuid::new_v4()))
+	.await;
+    assert_eq!(404, response.status().as_u16());
+}
@@ -0,0 +1,118 @@
+use crate::helper::TestApp;
+
+use jhm::routes::{CampaignHits, Hits};
+
+#[tokio::test]
+async fn campaign_is_extracted_from_referer() {
+    const URL: &str = "https://example.com/";
+    let test_app = TestApp::spawn().await;
+    let page_id = test_app.register_page(URL).await;
+
+    let response = test_app.get_hit_with_referer(
+	page_id,
+	"https://example.com/?utm_source=newsletter&utm_medium=email&utm_campaign=launch",
+    ).await;
+    assert!(response.status().is_success());
+
+    let response = test_app.get_campaigns(&[("url", URL)]).await;
+    assert!(response.status().is_success());
+    let campaigns = response
+	.json::<Vec<CampaignHits>>()
+	.await
+	.expect("Failed to receive campaigns");
+    assert_eq!(campaigns.len(), 1);
+    assert_eq!(campaigns[0].n, 1);
+    assert_eq!(campaigns[0].campaign.utm_source.as_deref(), Some("newsletter"));
+    assert_eq!(campaigns[0].campaign.utm_medium.as_deref(), Some("email"));
+    assert_eq!(campaigns[0].campaign.utm_campaign.as_deref(), Some("launch"));
+}
+
+#[tokio::test]
+async fn hits_without_campaign_are_not_reported() {
+    const URL: &str = "https://example.com/";
+    let test_app = TestApp::spawn().await;
+    let page_id = test_app.register_page(URL).await;
+
+    let response = test_app.get_hit_with_referer(page_id, URL).await;
+    assert!(response.status().is_success());
+
+    let campaigns = test_app
+	.get_campaigns(&[("url", URL)])
+	.await
+	.json::<Vec<CampaignHits>>()
+	.await
+	.unwrap();
+    assert!(campaigns.is_empty());
+}
+
+#[tokio::test]
+async fn campaigns_are_reported_per_site() {
+    const URL1: &str = "https://example.com/one";
+    const URL2: &str = "https://example.com/two";
+    const OTHER: &str = "https://example.org/";
+    let test_app = TestApp::spawn().await;
+
+    for url in [URL1, URL2, OTHER] {
+	let page_id = test_app.register_page(url).await;
+	let response = test_app.get_hit_with_referer(
+	    page_id,
+	    &format!("{url}?utm_campaign=launch"),
+	).await;
+	assert!(response.status().is_success());
+    }
+
+    let campaigns = test_app
+	.get_campaigns(&[("site", "example.com")])
+	.await
+	.json::<Vec<CampaignHits>>()
+	.await
+	.unwrap();
+    assert_eq!(campaigns.len(), 1);
+    assert_eq!(campaigns[0].campaign.utm_campaign.as_deref(), Some("launch"));
+    assert_eq!(campaigns[0].n, 2);
+}
+
+#[tokio::test]
+async fn campaigns_400s_without_exactly_one_of_url_or_site() {
+    let test_app = TestApp::spawn().await;
+
+    let response = test_app.get_campaigns(&[]).await;
+    assert_eq!(400, response.status().as_u16());
+
+    let response = test_app.get_campaigns(&[
+	("url", "https://example.com/"),
+	("site", "example.com"),
+    ]).await;
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn hits_can_be_filtered_by_campaign() {
+    const URL: &str = "https://example.com/";
+    let test_app = TestApp::spawn().await;
+    let page_id = test_app.register_page(URL).await;
+
+    let response = test_app.get_hit_with_referer(
+	page_id,
+	"https://example.com/?utm_campaign=launch",
+    ).await;
+    assert!(response.status().is_success());
+
+    let hits = test_app
+	.get_hits_with_query(&[("url", URL), ("utm_campaign", "launch")])
+	.await
+	.json::<Hits>()
+	.await
+	.unwrap();
+    assert_eq!(hits.n, 1);
+    assert_eq!(hits.timestamps.len(), 1);
+
+    let hits = test_app
+	.get_hits_with_query(&[("url", URL), ("utm_campaign", "other")])
+	.await
+	.json::<Hits>()
+	.await
+	.unwrap();
+    assert_eq!(hits.n, 0);
+    assert!(hits.timestamps.is_empty());
+}
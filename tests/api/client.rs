@@ -0,0 +1,171 @@
+use crate::helper::TestApp;
+use futures_util::StreamExt;
+use reqwest::StatusCode;
+use std::time::Duration;
+use url::Url;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use wiremock::matchers::{method, path};
+
+use jhm::client::{JhmClient, RetryPolicy};
+use jhm::routes::{Campaign, Hits, HitsParams, ReportParams};
+
+fn client_of(test_app: &TestApp) -> JhmClient {
+    JhmClient::new(Url::parse(&test_app.address).unwrap())
+}
+
+fn fast_retry(max_retries: u32) -> RetryPolicy {
+    RetryPolicy { max_retries, backoff: Duration::from_millis(10) }
+}
+
+fn hits_params(url: &str) -> HitsParams {
+    HitsParams { url: Url::parse(url).unwrap(), campaign: Campaign::default() }
+}
+
+#[tokio::test]
+async fn client_registers_pages_and_gets_their_hits() {
+    let test_app = TestApp::spawn().await;
+    let client = client_of(&test_app);
+    let url = Url::parse("https://example.com/post?id=1").unwrap();
+
+    let page_id = client.register(&url).await.expect("Failed to register page");
+    assert_eq!(client.register(&url).await.unwrap(), page_id);
+    client.hit(page_id).await.expect("Failed to hit page");
+
+    let hits = client.hits(&hits_params(url.as_str())).await.expect("Failed to get hits");
+    assert_eq!(hits.n, 1);
+}
+
+#[tokio::test]
+async fn client_acts_on_behalf_of_the_owner_of_the_api_key() {
+    let test_app = TestApp::spawn().await;
+    let owner = client_of(&test_app).new_owner().await.unwrap();
+    let client = client_of(&test_app).with_api_key(&owner.api_key);
+
+    let page_id = client
+	.register(&Url::parse("https://example.com/").unwrap())
+	.await
+	.unwrap();
+    let report = client.report(&ReportParams::default()).await.unwrap();
+    assert_eq!(report.pages.len(), 1);
+    assert_eq!(report.pages[0].page_id, page_id);
+}
+
+#[tokio::test]
+async fn client_reports_the_status_of_failed_requests() {
+    let test_app = TestApp::spawn().await;
+    let client = client_of(&test_app).with_retry(fast_retry(3));
+
+    let err = client.report(&ReportParams::default()).await.unwrap_err();
+    assert_eq!(err.status(), Some(StatusCode::UNAUTHORIZED));
+}
+
+#[tokio::test]
+async fn client_retries_idempotent_requests_on_server_errors() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+	.and(path("/v1/hits"))
+	.respond_with(ResponseTemplate::new(503))
+	.up_to_n_times(2)
+	.expect(2)
+	.mount(&server)
+	.await;
+    Mock::given(method("GET"))
+	.and(path("/v1/hits"))
+	.respond_with(ResponseTemplate::new(200).set_body_json(Hits {
+	    n: 3,
+	    timestamps: vec![],
+	    events: Default::default(),
+	    active_visitors: 0,
+	}))
+	.expect(1)
+	.mount(&server)
+	.await;
+    let client = JhmClient::new(Url::parse(&server.uri()).unwrap())
+	.with_retry(fast_retry(2));
+
+    let hits = client.hits(&hits_params("https://example.com/")).await.unwrap();
+    assert_eq!(hits.n, 3);
+}
+
+#[tokio::test]
+async fn client_gives_up_after_the_last_retry() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+	.and(path("/v1/hits"))
+	.respond_with(ResponseTemplate::new(500).set_body_string("boom"))
+	.expect(3)
+	.mount(&server)
+	.await;
+    let client = JhmClient::new(Url::parse(&server.uri()).unwrap())
+	.with_retry(fast_retry(2));
+
+    let err = client.hits(&hits_params("https://example.com/")).await.unwrap_err();
+    assert_eq!(err.status(), Some(StatusCode::INTERNAL_SERVER_ERROR));
+    assert!(err.to_string().contains("boom"));
+}
+
+#[tokio::test]
+async fn client_does_not_repeat_requests_that_create_things() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+	.and(path("/v1/owners"))
+	.respond_with(ResponseTemplate::new(503))
+	.expect(1)
+	.mount(&server)
+	.await;
+    let client = JhmClient::new(Url::parse(&server.uri()).unwrap())
+	.with_retry(fast_retry(3));
+
+    let err = client.new_owner().await.unwrap_err();
+    assert_eq!(err.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+}
+
+#[tokio::test]
+async fn client_keeps_the_path_of_the_base_url() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+	.and(path("/jhm/v1/health_check"))
+	.respond_with(ResponseTemplate::new(200))
+	.expect(1)
+	.mount(&server)
+	.await;
+    let client = JhmClient::new(Url::parse(&format!("{}/jhm", server.uri())).unwrap());
+
+    client.health_check().await.expect("Failed to check health");
+}
+
+#[tokio::test]
+async fn client_streams_live_hits() {
+    let test_app = TestApp::spawn().await;
+    let (page_id, api_key) = test_app
+	.register_owned_page("https://example.com/")
+	.await;
+    let client = client_of(&test_app).with_api_key(api_key);
+
+    let mut hits = client.live_page(page_id).await.expect("Failed to open stream");
+    client.hit(page_id).await.unwrap();
+    let hit = tokio::time::timeout(Duration::from_secs(5), hits.next())
+	.await
+	.expect("No live hit within 5 seconds")
+	.expect("Stream ended")
+	.expect("Failed to read live hit");
+    assert_eq!(hit.page_id, page_id);
+}
+
+#[tokio::test]
+async fn blocking_client_works_outside_of_async_code() {
+    let test_app = TestApp::spawn().await;
+    let address = Url::parse(&test_app.address).unwrap();
+
+    let hits = tokio::task::spawn_blocking(move || {
+	let client = jhm::client::blocking::JhmClient::new(address);
+	let url = Url::parse("https://example.com/").unwrap();
+	let page_id = client.register(&url)?;
+	client.hit(page_id)?;
+	client.hits(&hits_params(url.as_str()))
+    })
+	.await
+	.unwrap()
+	.expect("Failed to get hits");
+    assert_eq!(hits.n, 1);
+}
@@ -0,0 +1,183 @@
+use crate::helper::TestApp;
+use url::Url;
+use uuid::Uuid;
+
+use jhm::client::JhmClient;
+use jhm::rollups::rebuild;
+use jhm::routes::{Comparison, ComparisonQuery, TimeRange};
+
+const DAY: i64 = 24 * 60 * 60;
+const PREVIOUS: TimeRange = TimeRange { since: 100 * DAY, until: 107 * DAY };
+const CURRENT: TimeRange = TimeRange { since: 107 * DAY, until: 114 * DAY };
+
+/// Insert `n` raw hits of the page at the start of the range,
+/// and count them in the rollups.
+async fn insert_hits(test_app: &TestApp, page_id: Uuid, range: TimeRange, n: usize) {
+    for _ in 0..n {
+	sqlx::query!(
+	    "INSERT INTO page_hits (page_id, timestamp) VALUES ($1, $2)",
+	    page_id,
+	    range.since,
+	)
+	    .execute(&test_app.db)
+	    .await
+	    .unwrap();
+    }
+    rebuild(&test_app.db, None).await.unwrap();
+}
+
+fn query(page_ids: Vec<Uuid>) -> ComparisonQuery {
+    ComparisonQuery {
+	page_ids,
+	current: Some(CURRENT),
+	previous: Some(PREVIOUS),
+	..Default::default()
+    }
+}
+
+/// API key of a new owner, and a client that uses it.
+async fn owner(test_app: &TestApp) -> (String, JhmClient) {
+    let api_key = test_app.post_owner().await.api_key;
+    let client = JhmClient::new(Url::parse(&test_app.address).unwrap())
+	.with_api_key(&api_key);
+    (api_key, client)
+}
+
+async fn post_compare(
+    test_app: &TestApp,
+    body: &serde_json::Value,
+    api_key: Option<&str>,
+) -> reqwest::Response {
+    let mut request = reqwest::Client::new()
+	.post(&format!("{}/compare", &test_app.address))
+	.json(body);
+    if let Some(api_key) = api_key {
+	request = request.bearer_auth(api_key);
+    }
+    request
+	.send()
+	.await
+	.expect("Failed to execute request")
+}
+
+#[tokio::test]
+async fn compare_reports_the_change_and_rank_of_each_page() {
+    let test_app = TestApp::spawn().await;
+    let (api_key, client) = owner(&test_app).await;
+    let a = test_app.register_page_with("https://example.com/a", &[], None, Some(&api_key)).await;
+    let b = test_app.register_page_with("https://example.com/b", &[], None, Some(&api_key)).await;
+    let c = test_app.register_page_with("https://example.com/c", &[], None, Some(&api_key)).await;
+    let ignored = test_app.register_page_with("https://example.com/ignored", &[], None, Some(&api_key)).await;
+    insert_hits(&test_app, a, PREVIOUS, 4).await;
+    insert_hits(&test_app, a, CURRENT, 2).await;
+    insert_hits(&test_app, b, PREVIOUS, 1).await;
+    insert_hits(&test_app, b, CURRENT, 3).await;
+    insert_hits(&test_app, c, CURRENT, 3).await;
+    insert_hits(&test_app, ignored, CURRENT, 10).await;
+    // Right after the current range.
+    insert_hits(&test_app, a, TimeRange { since: CURRENT.until, until: 0 }, 10).await;
+
+    let comparison = client.compare(&query(vec![a, b, c])).await.unwrap();
+    assert_eq!(comparison.current, CURRENT);
+    assert_eq!(comparison.previous, PREVIOUS);
+    let pages: Vec<_> = comparison.pages.iter()
+	.map(|p| (p.page_id, p.hits, p.previous_hits, p.change, p.change_percent, p.rank, p.previous_rank))
+	.collect();
+    assert_eq!(pages, vec![
+	(b, 3, 1, 2, Some(200.0), 1, 2),
+	(c, 3, 0, 3, None, 1, 3),
+	(a, 2, 4, -2, Some(-50.0), 3, 1),
+    ]);
+}
+
+#[tokio::test]
+async fn compare_takes_the_pages_of_a_tag() {
+    let test_app = TestApp::spawn().await;
+    let (api_key, client) = owner(&test_app).await;
+    let a = test_app.register_page_with("https://example.com/a", &["tutorial"], None, Some(&api_key)).await;
+    let b = test_app.register_page_with("https://example.com/b", &["tutorial", "rust"], None, Some(&api_key)).await;
+    test_app.register_page_with("https://example.com/c", &["news"], None, Some(&api_key)).await;
+    test_app.register_page_with("https://example.org/d", &["tutorial"], None, Some(&api_key)).await;
+    insert_hits(&test_app, b, CURRENT, 1).await;
+
+    let query = ComparisonQuery {
+	tag: Some("tutorial".into()),
+	site: Some("example.com".into()),
+	..query(vec![])
+    };
+    let comparison = client.compare(&query).await.unwrap();
+    let pages: Vec<(Uuid, i64)> = comparison.pages.iter().map(|p| (p.page_id, p.rank)).collect();
+    assert_eq!(pages, vec![(b, 1), (a, 2)]);
+}
+
+#[tokio::test]
+async fn compare_404s_on_unknown_pages() {
+    let test_app = TestApp::spawn().await;
+    let (api_key, _) = owner(&test_app).await;
+    let known = test_app.register_page_with("https://example.com/", &[], None, Some(&api_key)).await;
+    let unknown = Uuid::new_v4();
+
+    let body = serde_json::to_value(query(vec![known, unknown])).unwrap();
+    let response = post_compare(&test_app, &body, Some(&api_key)).await;
+    assert_eq!(404, response.status().as_u16());
+    assert!(response.text().await.unwrap().contains(&unknown.to_string()));
+}
+
+#[tokio::test]
+async fn compare_only_takes_the_pages_of_the_owner() {
+    let test_app = TestApp::spawn().await;
+    let (api_key, client) = owner(&test_app).await;
+    let (other_key, _) = owner(&test_app).await;
+    let mine = test_app.register_page_with("https://example.com/mine", &["rust"], None, Some(&api_key)).await;
+    let theirs = test_app.register_page_with("https://example.com/theirs", &["rust"], None, Some(&other_key)).await;
+    test_app.register_page_with("https://example.com/anonymous", &["rust"], None, None).await;
+
+    let body = serde_json::to_value(query(vec![mine, theirs])).unwrap();
+    let response = post_compare(&test_app, &body, Some(&api_key)).await;
+    assert_eq!(404, response.status().as_u16());
+    assert!(response.text().await.unwrap().contains(&theirs.to_string()));
+
+    let query = ComparisonQuery { tag: Some("rust".into()), ..query(vec![]) };
+    let comparison = client.compare(&query).await.unwrap();
+    let pages: Vec<Uuid> = comparison.pages.iter().map(|p| p.page_id).collect();
+    assert_eq!(pages, vec![mine]);
+
+    let response = post_compare(&test_app, &serde_json::to_value(&query).unwrap(), None).await;
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn compare_400s_on_invalid_queries() {
+    let test_app = TestApp::spawn().await;
+    let (api_key, _) = owner(&test_app).await;
+    let page_id = Uuid::new_v4().to_string();
+    let current = serde_json::json!({"since": 10, "until": 20});
+    let cases = [
+	serde_json::json!({"current": current, "previous": current}),
+	serde_json::json!({"page_ids": [page_id], "tag": "x", "current": current, "previous": current}),
+	serde_json::json!({"page_ids": [page_id], "site": "example.com", "current": current, "previous": current}),
+	serde_json::json!({"page_ids": [page_id], "current": current}),
+	serde_json::json!({"page_ids": [page_id], "current": {"since": 20, "until": 10}, "previous": current}),
+	serde_json::json!({"page_ids": ["not an ID"], "current": current, "previous": current}),
+    ];
+    for body in cases {
+	let response = post_compare(&test_app, &body, Some(&api_key)).await;
+	assert_eq!(400, response.status().as_u16(), "{body}");
+    }
+}
+
+#[tokio::test]
+async fn compare_counts_ranges_that_overlap_in_both() {
+    let test_app = TestApp::spawn().await;
+    let (api_key, client) = owner(&test_app).await;
+    let page_id = test_app.register_page_with("https://example.com/", &[], None, Some(&api_key)).await;
+    insert_hits(&test_app, page_id, CURRENT, 2).await;
+
+    let query = ComparisonQuery {
+	previous: Some(TimeRange { since: PREVIOUS.since, until: CURRENT.until }),
+	..query(vec![page_id])
+    };
+    let Comparison { pages, .. } = client.compare(&query).await.unwrap();
+    assert_eq!((pages[0].hits, pages[0].previous_hits, pages[0].change), (2, 2, 0));
+    assert_eq!(pages[0].change_percent, Some(0.0));
+}
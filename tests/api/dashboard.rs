@@ -0,0 +1,123 @@
+use crate::helper::TestApp;
+use uuid::Uuid;
+
+use jhm::routes::{Page, PageUpdate};
+
+async fn update(
+    test_app: &TestApp,
+    page_id: Uuid,
+    api_key: &str,
+    update: PageUpdate,
+) -> Page {
+    let response = test_app.patch_page(page_id, &update, Some(api_key)).await;
+    assert!(response.status().is_success());
+    response.json::<Page>().await.unwrap()
+}
+
+#[tokio::test]
+async fn dashboards_are_private_by_default() {
+    let test_app = TestApp::spawn().await;
+    let (page_id, _) = test_app
+	.register_owned_page("https://example.com/")
+	.await;
+
+    let response = test_app.get_route(&format!("dashboard/{page_id}")).await;
+    assert_eq!(404, response.status().as_u16());
+    let response = test_app.get_route("dashboard/example.com").await;
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn public_dashboard_shows_hits_and_referrers() {
+    const URL: &str = "https://example.com/";
+    let test_app = TestApp::spawn().await;
+    let (page_id, api_key) = test_app.register_owned_page(URL).await;
+    update(&test_app, page_id, &api_key, PageUpdate {
+	public: Some(true),
+	..Default::default()
+    }).await;
+
+    let response = test_app
+	.get_hit_with_referer(page_id, "https://news.example.org/item?id=1")
+	.await;
+    assert!(response.status().is_success());
+
+    let response = test_app.get_route(&format!("dashboard/{page_id}")).await;
+    assert!(response.status().is_success());
+    assert_eq!(response.headers()["Content-Type"], "text/html; charset=utf-8");
+    let body = response.text().await.unwrap();
+    assert!(body.contains(&format!("<h1>{URL}</h1>")));
+    assert!(body.contains(r#"<p class="total">1</p>"#));
+    assert_eq!(body.matches(r#"class="bar""#).count(), 30);
+    assert!(body.contains(r#"style="height:100%""#));
+    assert!(body.contains("<td>https://news.example.org/item</td>"));
+    assert!(!body.contains("<script"));
+}
+
+#[tokio::test]
+async fn site_dashboard_only_includes_public_pages() {
+    let test_app = TestApp::spawn().await;
+    let (public_page, api_key) = test_app
+	.register_owned_page("https://example.com/public")
+	.await;
+    let (private_page, _) = test_app
+	.register_owned_page("https://example.com/private")
+	.await;
+    update(&test_app, public_page, &api_key, PageUpdate {
+	public: Some(true),
+	..Default::default()
+    }).await;
+    for (page_id, hits) in [(public_page, 3), (private_page, 5)] {
+	sqlx::query!("UPDATE pages SET hits = $1 WHERE page_id = $2", hits, page_id)
+	    .execute(&test_app.db)
+	    .await
+	    .expect("Failed to set hits");
+    }
+
+    let response = test_app.get_route("dashboard/example.com").await;
+    assert!(response.status().is_success());
+    let body = response.text().await.unwrap();
+    assert!(body.contains("<h1>example.com</h1>"));
+    assert!(body.contains(r#"<p class="total">3</p>"#));
+}
+
+#[tokio::test]
+async fn share_link_opens_private_dashboard() {
+    let test_app = TestApp::spawn().await;
+    let (page_id, api_key) = test_app
+	.register_owned_page("https://example.com/")
+	.await;
+    let page = update(&test_app, page_id, &api_key, PageUpdate {
+	share_link: Some(true),
+	..Default::default()
+    }).await;
+    assert!(!page.public);
+    let share_link = page.share_link.expect("Missing share link");
+    let secret = share_link
+	.query_pairs()
+	.find(|(key, _)| key == "secret")
+	.map(|(_, secret)| secret.into_owned())
+	.expect("Missing secret");
+
+    let response = test_app
+	.get_route(&format!("dashboard/{page_id}?secret={secret}"))
+	.await;
+    assert!(response.status().is_success());
+    assert_eq!(response.headers()["Referrer-Policy"], "no-referrer");
+
+    let response = test_app
+	.get_route(&format!("dashboard/{page_id}?secret=wrong"))
+	.await;
+    assert_eq!(404, response.status().as_u16());
+
+    // Revoking the link locks the dashboard again.
+    let page = update(&test_app, page_id, &api_key, PageUpdate {
+	share_link: Some(false),
+	..Default::default()
+    }).await;
+    assert!(page.share_link.is_none());
+    let response = test_app
+	.get_route(&format!("dashboard/{page_id}?secret={secret}"))
+	.await;
+    assert_eq!(404, response.status().as_u16());
+}
@@ -0,0 +1,66 @@
+use crate::helper::TestApp;
+
+use jhm::routes::Hits;
+
+#[tokio::test]
+async fn events_are_counted_separately_from_hits() {
+    const URL: &str = "https://example.com/";
+    let test_app = TestApp::spawn().await;
+    let page_id = test_app.register_page(URL).await;
+
+    for event in ["read-to-end", "outbound-click"] {
+	let response = test_app
+	    .get_route(&format!("hit/{page_id}/event/{event}"))
+	    .await;
+	assert!(response.status().is_success());
+    }
+
+    let hits = test_app
+	.get_hits(URL)
+	.await
+	.json::<Hits>()
+	.await
+	.unwrap();
+    assert_eq!(hits.n, 0, "Events shouldn't count as page hits");
+    assert_eq!(hits.events.get("read-to-end"), Some(&1));
+    assert_eq!(hits.events.get("outbound-click"), Some(&1));
+}
+
+#[tokio::test]
+async fn events_are_counted_once_per_visit() {
+    const URL: &str = "https://example.com/";
+    let test_app = TestApp::spawn().await;
+    let page_id = test_app.register_page(URL).await;
+
+    // The page hit must not swallow the event or vice versa.
+    let response = test_app.get_route(&format!("hit/{page_id}")).await;
+    assert!(response.status().is_success());
+    for _ in 0..3 {
+	let response = test_app
+	    .get_route(&format!("hit/{page_id}/event/read-to-end"))
+	    .await;
+	assert!(response.status().is_success());
+    }
+
+    let hits = test_app
+	.get_hits(URL)
+	.await
+	.json::<Hits>()
+	.await
+	.unwrap();
+    assert_eq!(hits.n, 1);
+    assert_eq!(hits.events.get("read-to-end"), Some(&1));
+}
+
+#[tokio::test]
+async fn event_400s_on_invalid_name() {
+    let test_app = TestApp::spawn().await;
+    let page_id = test_app.insert_page().await;
+
+    for event in ["Not%20Valid", &"a".repeat(65)] {
+	let response = test_app
+	    .get_route(&format!("hit/{page_id}/event/{event}"))
+	    .await;
+	assert_eq!(400, response.status().as_u16());
+    }
+}
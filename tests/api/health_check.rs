@@ -1,9 +1,21 @@
 use crate::helper::TestApp;
+use jhm::configuration::StorageBackend;
+use jhm::routes::{ComponentStatus, Health};
 
 #[tokio::test]
 async fn health_check_works() {
     let test_app = TestApp::spawn().await;
     let response = test_app.get_route("health_check").await;
     assert!(response.status().is_success());
-    assert_eq!(Some(0), response.content_length());
+    let health: Health = response.json().await.unwrap();
+    assert_eq!(health.redis, Some(ComponentStatus::Up));
+}
+
+#[tokio::test]
+async fn health_check_leaves_out_redis_without_it() {
+    let test_app = TestApp::spawn_with(|c| c.storage.backend = StorageBackend::Memory).await;
+    let response = test_app.get_route("health_check").await;
+    assert!(response.status().is_success());
+    let health: Health = response.json().await.unwrap();
+    assert_eq!(health.redis, None);
 }
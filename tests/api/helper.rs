@@ -1,10 +1,17 @@
 use uuid::Uuid;
+use url::Url;
 use once_cell::sync::Lazy;
+use actix_web::dev::ServerHandle;
+use tokio::task::JoinHandle;
 use sqlx::{PgPool, PgConnection, Connection, Executor};
 
-use jhm::startup::{Application, get_pg_connection_pool};
-use jhm::configuration::{PostgresSettings, get_configuration};
+use jhm::startup::{Application, get_pg_connection_pool, get_redis_connection_pool};
+use jhm::configuration::{PostgresSettings, Settings, StorageBackend, get_configuration};
+use jhm::utils::RedisPool;
+use jhm::write_behind::HitBuffer;
 use jhm::telemetry::*;
+use jhm::client::JhmClient;
+use jhm::routes::{BatchRegistration, NewOwner, NewPage, NewWebhook, PageUpdate};
 
 static TRACING: Lazy<()> = Lazy::new(|| {
     let default_name = "test".to_owned();
@@ -25,16 +32,28 @@ static TRACING: Lazy<()> = Lazy::new(|| {
     }
 });
 
+/// Header that the test app takes the country of hits from.
+pub const COUNTRY_HEADER: &str = "X-Country";
 
 pub struct TestApp {
     pub address: String,
     pub port: u16,
     pub db: PgPool,
+    /// Buffer of the hits in write-behind mode.
+    pub hit_buffer: HitBuffer,
     api_client: reqwest::Client,
+    server: ServerHandle,
+    application: JoinHandle<Result<(), std::io::Error>>,
+    configuration: Settings,
 }
 
 impl TestApp {
     pub async fn spawn() -> Self {
+	Self::spawn_with(|_| {}).await
+    }
+
+    /// Spawn an app with changes to the test configuration.
+    pub async fn spawn_with(configure: impl FnOnce(&mut Settings)) -> Self {
         Lazy::force(&TRACING);
 
         let configuration = {
@@ -42,16 +61,25 @@ impl TestApp {
             c.postgres.database_name = Uuid::new_v4().to_string();
             c.application.port = 0;
 	    c.application.visit_duration = 1;
+	    c.application.country_header = Some(COUNTRY_HEADER.into());
+	    c.webhooks.poll_interval_ms = 50;
+	    c.webhooks.retry_backoff_ms = 50;
+	    // The mock receivers listen on localhost.
+	    c.webhooks.allow_private_targets = true;
+	    configure(&mut c);
             c
         };
 
-        Self::configure_postgres(&configuration.postgres).await;
+	if configuration.storage.backend == StorageBackend::Postgres {
+	    Self::configure_postgres(&configuration.postgres).await;
+	}
 
         let application = Application::build(configuration.clone())
             .await
             .expect("Failed to build application");
         let application_port = application.port();
-        let _ = tokio::spawn(application.run_until_stopped());
+	let server = application.handle();
+	let application = tokio::spawn(application.run_until_stopped());
 
         let client = reqwest::Client::builder()
             .redirect(reqwest::redirect::Policy::none())
@@ -62,10 +90,29 @@ impl TestApp {
             address: format!("http://127.0.0.1:{}", application_port),
             port: application_port,
             db: get_pg_connection_pool(&configuration.postgres).await,
+	    hit_buffer: HitBuffer::new(&configuration.postgres.database_name),
             api_client: client,
+	    server,
+	    application,
+	    configuration,
         }
     }
 
+    pub fn redis(&self) -> RedisPool {
+	get_redis_connection_pool(&self.configuration.redis)
+	    .expect("Failed to create Redis pool")
+    }
+
+    /// Stop the server gracefully, and wait until the app
+    /// has shut down.
+    pub async fn stop(self) {
+	self.server.stop(true).await;
+	self.application
+	    .await
+	    .expect("Failed to join application")
+	    .expect("Failed to run application");
+    }
+
     async fn configure_postgres(db_config: &PostgresSettings) -> PgPool {
         let mut connection = PgConnection::connect_with(&db_config.without_db())
             .await
@@ -92,6 +139,15 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    pub async fn get_route_with_key(&self, r: &str, api_key: &str) -> reqwest::Response {
+	self.api_client
+	    .get(&format!("{}/{}", &self.address, r))
+	    .bearer_auth(api_key)
+	    .send()
+	    .await
+	    .expect("Failed to execute request")
+    }
+
     pub async fn get_hits(&self, url: &str) -> reqwest::Response {
 	self.api_client
 	    .get(&format!("{}/hits", &self.address))
@@ -101,6 +157,52 @@ impl TestApp {
 	    .expect("Failed to execute request")
     }
 
+    pub async fn get_hits_with_query(
+	&self,
+	query: &[(&str, &str)],
+    ) -> reqwest::Response {
+	self.api_client
+	    .get(&format!("{}/hits", &self.address))
+	    .query(query)
+	    .send()
+	    .await
+	    .expect("Failed to execute request")
+    }
+
+    pub async fn get_hit_with_headers(
+	&self,
+	path: &str,
+	headers: &[(&str, &str)],
+    ) -> reqwest::Response {
+	let mut request = self.api_client.get(&format!("{}/{path}", &self.address));
+	for (name, value) in headers {
+	    request = request.header(*name, *value);
+	}
+	request.send().await.expect("Failed to execute request")
+    }
+
+    pub async fn get_hit_with_referer(
+	&self,
+	page_id: Uuid,
+	referer: &str,
+    ) -> reqwest::Response {
+	self.api_client
+	    .get(&format!("{}/hit/{}", &self.address, page_id))
+	    .header("Referer", referer)
+	    .send()
+	    .await
+	    .expect("Failed to execute request")
+    }
+
+    pub async fn get_campaigns(&self, query: &[(&str, &str)]) -> reqwest::Response {
+	self.api_client
+	    .get(&format!("{}/campaigns", &self.address))
+	    .query(query)
+	    .send()
+	    .await
+	    .expect("Failed to execute request")
+    }
+
     pub async fn post_register(&self, body: &str) -> reqwest::Response {
 	self.api_client
 	    .post(&format!("{}/register", &self.address))
@@ -111,6 +213,136 @@ impl TestApp {
 	    .expect("Failed to execute request")
     }
 
+    pub async fn post_register_json(&self, body: &serde_json::Value) -> reqwest::Response {
+	self.api_client
+	    .post(&format!("{}/register", &self.address))
+	    .json(body)
+	    .send()
+	    .await
+	    .expect("Failed to execute request")
+    }
+
+    pub async fn post_register_batch(&self, urls: &[&str]) -> reqwest::Response {
+	let batch = BatchRegistration {
+	    urls: urls.iter().map(|url| url.to_string()).collect(),
+	};
+	self.api_client
+	    .post(&format!("{}/register/batch", &self.address))
+	    .json(&batch)
+	    .send()
+	    .await
+	    .expect("Failed to execute request")
+    }
+
+    pub async fn post_owner(&self) -> NewOwner {
+	self.api_client
+	    .post(&format!("{}/owners", &self.address))
+	    .send()
+	    .await
+	    .expect("Failed to execute request")
+	    .json()
+	    .await
+	    .expect("Failed to create owner")
+    }
+
+    pub async fn post_register_with_key(
+	&self,
+	body: &str,
+	api_key: &str,
+    ) -> reqwest::Response {
+	self.api_client
+	    .post(&format!("{}/register", &self.address))
+	    .header("Content-Type", "application/x-www-form-urlencoded")
+	    .bearer_auth(api_key)
+	    .body(body.to_string())
+	    .send()
+	    .await
+	    .expect("Failed to execute request")
+    }
+
+    /// Register a page without an owner, or get the ID it's
+    /// registered under.
+    pub async fn register_page(&self, url: &str) -> Uuid {
+	self.register_page_with(url, &[], None, None).await
+    }
+
+    /// Register a page with tags and a group, for the owner of
+    /// the API key if one is given.
+    pub async fn register_page_with(
+	&self,
+	url: &str,
+	tags: &[&str],
+	group: Option<&str>,
+	api_key: Option<&str>,
+    ) -> Uuid {
+	let page = NewPage {
+	    tags: tags.iter().map(|tag| tag.to_string()).collect(),
+	    group: group.map(str::to_string),
+	    ..NewPage::new(Url::parse(url).expect("Invalid URL"))
+	};
+	let mut client = JhmClient::new(Url::parse(&self.address).unwrap());
+	if let Some(api_key) = api_key {
+	    client = client.with_api_key(api_key);
+	}
+	client.register_page(&page).await.expect("Failed to register page")
+    }
+
+    /// Register a page that belongs to a new owner.
+    /// Returns the page ID and the owner's API key.
+    pub async fn register_owned_page(&self, url: &str) -> (Uuid, String) {
+	let owner = self.post_owner().await;
+	let page_id = self.register_page_with(url, &[], None, Some(&owner.api_key)).await;
+	(page_id, owner.api_key)
+    }
+
+    pub async fn patch_page(
+	&self,
+	page_id: Uuid,
+	update: &PageUpdate,
+	api_key: Option<&str>,
+    ) -> reqwest::Response {
+	let mut request = self.api_client
+	    .patch(&format!("{}/pages/{}", &self.address, page_id))
+	    .json(update);
+	if let Some(api_key) = api_key {
+	    request = request.bearer_auth(api_key);
+	}
+	request
+	    .send()
+	    .await
+	    .expect("Failed to execute request")
+    }
+
+    pub async fn get_live(&self, path: &str, api_key: Option<&str>) -> reqwest::Response {
+	let mut request = self.api_client
+	    .get(&format!("{}/{}/live", &self.address, path));
+	if let Some(api_key) = api_key {
+	    request = request.bearer_auth(api_key);
+	}
+	request
+	    .send()
+	    .await
+	    .expect("Failed to execute request")
+    }
+
+    pub async fn post_webhook(
+	&self,
+	page_id: Uuid,
+	webhook: &NewWebhook,
+	api_key: Option<&str>,
+    ) -> reqwest::Response {
+	let mut request = self.api_client
+	    .post(&format!("{}/pages/{}/webhooks", &self.address, page_id))
+	    .json(webhook);
+	if let Some(api_key) = api_key {
+	    request = request.bearer_auth(api_key);
+	}
+	request
+	    .send()
+	    .await
+	    .expect("Failed to execute request")
+    }
+
     pub async fn insert_page(&self) -> uuid::Uuid {
 	let page_id = Uuid::new_v4();
 	sqlx::query!(
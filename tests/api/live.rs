@@ -0,0 +1,98 @@
+use crate::helper::TestApp;
+use std::time::Duration;
+use uuid::Uuid;
+
+use jhm::routes::LiveHit;
+
+/// Read the stream until the next hit event arrives.
+async fn next_hit(response: &mut reqwest::Response) -> LiveHit {
+    let mut buffer = String::new();
+    loop {
+	let chunk = tokio::time::timeout(Duration::from_secs(5), response.chunk())
+	    .await
+	    .expect("No live hit within 5 seconds")
+	    .expect("Failed to read stream")
+	    .expect("Stream ended");
+	buffer.push_str(std::str::from_utf8(&chunk).unwrap());
+	if let Some(data) = buffer
+	    .split("\n\n")
+	    .filter_map(|event| event.strip_prefix("event: hit\ndata: "))
+	    .next()
+	{
+	    return serde_json::from_str(data).expect("Failed to decode live hit");
+	}
+    }
+}
+
+#[tokio::test]
+async fn live_page_streams_counted_hits() {
+    let test_app = TestApp::spawn().await;
+    let (page_id, api_key) = test_app
+	.register_owned_page("https://example.com/")
+	.await;
+
+    let mut response = test_app
+	.get_live(&format!("pages/{page_id}"), Some(&api_key))
+	.await;
+    assert!(response.status().is_success());
+    assert_eq!(response.headers()["Content-Type"], "text/event-stream");
+
+    test_app
+	.get_hit_with_referer(page_id, "https://news.example.org/item?id=1")
+	.await;
+    let hit = next_hit(&mut response).await;
+    assert_eq!(hit.page_id, page_id);
+    assert_eq!(hit.referrer.as_deref(), Some("https://news.example.org/item"));
+}
+
+#[tokio::test]
+async fn live_site_streams_hits_of_owned_pages() {
+    let test_app = TestApp::spawn().await;
+    let (page_id, api_key) = test_app
+	.register_owned_page("https://example.com/a")
+	.await;
+
+    let mut response = test_app
+	.get_live("sites/example.com", Some(&api_key))
+	.await;
+    assert!(response.status().is_success());
+
+    test_app.get_route(&format!("hit/{page_id}")).await;
+    let hit = next_hit(&mut response).await;
+    assert_eq!(hit.page_id, page_id);
+}
+
+#[tokio::test]
+async fn live_streams_401_without_api_key() {
+    let test_app = TestApp::spawn().await;
+    let (page_id, _) = test_app
+	.register_owned_page("https://example.com/")
+	.await;
+
+    let response = test_app.get_live(&format!("pages/{page_id}"), None).await;
+    assert_eq!(401, response.status().as_u16());
+    let response = test_app.get_live("sites/example.com", None).await;
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn live_streams_404_for_other_owners() {
+    let test_app = TestApp::spawn().await;
+    let (page_id, _) = test_app
+	.register_owned_page("https://example.com/")
+	.await;
+    let other = test_app.post_owner().await;
+
+    let response = test_app
+	.get_live(&format!("pages/{page_id}"), Some(&other.api_key))
+	.await;
+    assert_eq!(404, response.status().as_u16());
+    let response = test_app
+	.get_live(&format!("pages/{}", Uuid::new_v4()), Some(&other.api_key))
+	.await;
+    assert_eq!(404, response.status().as_u16());
+    let response = test_app
+	.get_live("sites/example.com", Some(&other.api_key))
+	.await;
+    assert_eq!(404, response.status().as_u16());
+}
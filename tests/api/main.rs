@@ -3,3 +3,22 @@ mod health_check;
 mod hit;
 mod hits;
 mod register;
+mod campaigns;
+mod events;
+mod snippet;
+mod pages;
+mod badge;
+mod dashboard;
+mod live;
+mod active;
+mod webhooks;
+mod report;
+mod client;
+mod openapi;
+mod tags;
+mod compare;
+mod rollups;
+mod write_behind;
+mod storage;
+mod redis_down;
+mod ready;
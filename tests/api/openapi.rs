@@ -0,0 +1,273 @@
+use crate::helper::TestApp;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use url::Url;
+
+use jhm::client::JhmClient;
+use jhm::routes::{
+    ActiveParams, Campaign, CampaignsParams, HitsParams, NewPage, NewWebhook, PageUpdate,
+    ReportParams, TagsParams,
+};
+use jhm::startup::api_v1_routes;
+
+async fn get_spec(test_app: &TestApp) -> Value {
+    let response = test_app.get_route("v1/openapi.json").await;
+    assert!(response.status().is_success());
+    response.json().await.expect("Failed to decode OpenAPI document")
+}
+
+/// Schema of the JSON of a successful response.
+fn response_schema<'a>(spec: &'a Value, method: &str, path: &str) -> &'a Value {
+    let operation = &spec["paths"][path][method];
+    assert!(operation.is_object(), "{method} {path} is not documented");
+    let schema = &operation["responses"]["200"]["content"]["application/json"]["schema"];
+    assert!(schema.is_object(), "{method} {path} has no JSON response");
+    schema
+}
+
+fn resolve<'a>(schema: &'a Value, spec: &'a Value) -> &'a Value {
+    match schema["$ref"].as_str() {
+	Some(reference) => {
+	    let pointer = reference.strip_prefix('#').expect("Only local references");
+	    resolve(spec.pointer(pointer).expect("Dangling reference"), spec)
+	},
+	None => schema,
+    }
+}
+
+/// Properties and required properties of an object schema,
+/// including those of the schemas it's made of.
+fn object_parts<'a>(
+    schema: &'a Value,
+    spec: &'a Value,
+    properties: &mut serde_json::Map<String, Value>,
+    required: &mut BTreeSet<String>,
+) {
+    let schema = resolve(schema, spec);
+    for part in schema["allOf"].as_array().into_iter().flatten() {
+	object_parts(part, spec, properties, required);
+    }
+    if let Some(props) = schema["properties"].as_object() {
+	properties.extend(props.clone());
+    }
+    for name in schema["required"].as_array().into_iter().flatten() {
+	required.insert(name.as_str().unwrap().to_string());
+    }
+}
+
+/// Check that the value has the shape of the schema. Only the
+/// parts of JSON Schema that the spec uses are supported.
+fn check(value: &Value, schema: &Value, spec: &Value, at: &str) {
+    let schema = resolve(schema, spec);
+    if value.is_null() && schema["nullable"] == true {
+	return;
+    }
+    if let Some(values) = schema["enum"].as_array() {
+	assert!(values.contains(value), "{at}: {value} is not one of {values:?}");
+	return;
+    }
+    if let Some(parts) = schema["allOf"].as_array() {
+	if parts.len() == 1 && schema["properties"].is_null() {
+	    return check(value, &parts[0], spec, at);
+	}
+    }
+    match schema["type"].as_str() {
+	Some("array") => {
+	    let items = value.as_array().unwrap_or_else(|| panic!("{at}: {value} is no array"));
+	    for (i, item) in items.iter().enumerate() {
+		check(item, &schema["items"], spec, &format!("{at}[{i}]"));
+	    }
+	},
+	Some("integer") => assert!(value.is_i64() || value.is_u64(), "{at}: {value} is no integer"),
+	Some("number") => assert!(value.is_number(), "{at}: {value} is no number"),
+	Some("string") => assert!(value.is_string(), "{at}: {value} is no string"),
+	Some("boolean") => assert!(value.is_boolean(), "{at}: {value} is no boolean"),
+	_ => {
+	    let object = value.as_object().unwrap_or_else(|| panic!("{at}: {value} is no object"));
+	    if let Some(additional) = schema["additionalProperties"].as_object() {
+		let additional = Value::Object(additional.clone());
+		for (key, item) in object {
+		    check(item, &additional, spec, &format!("{at}.{key}"));
+		}
+		return;
+	    }
+	    let mut properties = serde_json::Map::new();
+	    let mut required = BTreeSet::new();
+	    object_parts(schema, spec, &mut properties, &mut required);
+	    for name in &required {
+		assert!(object.contains_key(name), "{at}: {name} is missing");
+	    }
+	    for (key, item) in object {
+		let property = properties
+		    .get(key)
+		    .unwrap_or_else(|| panic!("{at}: {key} is not documented"));
+		check(item, property, spec, &format!("{at}.{key}"));
+	    }
+	},
+    }
+}
+
+/// Get the JSON of a route of the API, and check it against the
+/// documented response.
+async fn check_response(
+    spec: &Value,
+    path: &str,
+    request: reqwest::RequestBuilder,
+) -> Value {
+    let request = request.build().unwrap();
+    let method = request.method().as_str().to_lowercase();
+    let response = reqwest::Client::new()
+	.execute(request)
+	.await
+	.expect("Failed to execute request");
+    assert!(response.status().is_success(), "{method} {path} failed: {}", response.status());
+    let value: Value = response.json().await.expect("Response is no JSON");
+    check(&value, response_schema(spec, &method, path), spec, &format!("{method} {path}"));
+    value
+}
+
+#[tokio::test]
+async fn spec_documents_exactly_the_routes_of_the_api() {
+    let test_app = TestApp::spawn().await;
+    let spec = get_spec(&test_app).await;
+
+    let documented: BTreeSet<(String, String)> = spec["paths"]
+	.as_object()
+	.unwrap()
+	.iter()
+	.flat_map(|(path, operations)| {
+	    operations.as_object().unwrap().keys().map(move |method| (method.clone(), path.clone()))
+	})
+	.collect();
+    let routed: BTreeSet<(String, String)> = api_v1_routes()
+	.into_iter()
+	.map(|(method, path, _)| (method.as_str().to_lowercase(), path.to_string()))
+	.collect();
+    assert_eq!(
+	routed.difference(&documented).collect::<Vec<_>>(),
+	Vec::<&(String, String)>::new(),
+	"Routes are missing from the spec",
+    );
+    assert_eq!(
+	documented.difference(&routed).collect::<Vec<_>>(),
+	Vec::<&(String, String)>::new(),
+	"The spec documents routes that don't exist",
+    );
+}
+
+#[tokio::test]
+async fn documented_path_parameters_are_the_ones_in_the_path() {
+    let test_app = TestApp::spawn().await;
+    let spec = get_spec(&test_app).await;
+
+    for (path, operations) in spec["paths"].as_object().unwrap() {
+	for (method, operation) in operations.as_object().unwrap() {
+	    let documented: BTreeSet<&str> = operation["parameters"]
+		.as_array()
+		.into_iter()
+		.flatten()
+		.filter(|param| param["in"] == "path")
+		.map(|param| param["name"].as_str().unwrap())
+		.collect();
+	    let in_path: BTreeSet<&str> = path
+		.split('{')
+		.skip(1)
+		.map(|part| part.split('}').next().unwrap())
+		.collect();
+	    assert_eq!(documented, in_path, "{method} {path} documents other path parameters than it has");
+	}
+    }
+}
+
+#[tokio::test]
+async fn responses_have_the_documented_shape() {
+    let test_app = TestApp::spawn().await;
+    let spec = get_spec(&test_app).await;
+    let http = reqwest::Client::new();
+    let v1 = format!("{}/v1", test_app.address);
+    const URL: &str = "https://example.com/post?id=1";
+
+    let owner = check_response(&spec, "/owners",
+			       http.post(format!("{v1}/owners"))).await;
+    let api_key = owner["api_key"].as_str().unwrap().to_string();
+    let client = JhmClient::new(Url::parse(&test_app.address).unwrap())
+	.with_api_key(&api_key);
+    let page = NewPage {
+	title: Some("Post".into()),
+	tags: vec!["blog".into()],
+	group: Some("posts".into()),
+	..NewPage::new(Url::parse(URL).unwrap())
+    };
+    let page_id = client.register_page(&page).await.unwrap();
+    client.hit(page_id).await.unwrap();
+
+    check_response(&spec, "/register",
+		   http.post(format!("{v1}/register"))
+		   .bearer_auth(&api_key)
+		   .form(&[("url", URL)])).await;
+    check_response(&spec, "/register/batch",
+		   http.post(format!("{v1}/register/batch"))
+		   .json(&serde_json::json!({"urls": [URL, "not a URL"]}))).await;
+    check_response(&spec, "/hits",
+		   http.get(format!("{v1}/hits"))
+		   .query(&HitsParams { url: Url::parse(URL).unwrap(), campaign: Campaign::default() })).await;
+    check_response(&spec, "/campaigns",
+		   http.get(format!("{v1}/campaigns"))
+		   .query(&CampaignsParams { site: Some("example.com".into()), ..Default::default() })).await;
+    check_response(&spec, "/active",
+		   http.get(format!("{v1}/active"))
+		   .query(&ActiveParams { site: Some("example.com".into()), ..Default::default() })).await;
+    check_response(&spec, "/tags",
+		   http.get(format!("{v1}/tags"))
+		   .bearer_auth(&api_key)
+		   .query(&TagsParams { site: Some("example.com".into()), group: None })).await;
+    check_response(&spec, "/tags/{tag}/hits",
+		   http.get(format!("{v1}/tags/blog/hits"))
+		   .bearer_auth(&api_key)).await;
+    check_response(&spec, "/compare",
+		   http.post(format!("{v1}/compare"))
+		   .bearer_auth(&api_key)
+		   .json(&serde_json::json!({
+		       "page_ids": [page_id],
+		       "current": {"since": 0, "until": i64::MAX},
+		       "previous": {"since": 0, "until": 1},
+		   }))).await;
+    check_response(&spec, "/pages/{page_id}/breakdown",
+		   http.get(format!("{v1}/pages/{page_id}/breakdown"))
+		   .bearer_auth(&api_key)
+		   .query(&[("dimension", "total")])).await;
+    check_response(&spec, "/report",
+		   http.get(format!("{v1}/report"))
+		   .bearer_auth(&api_key)
+		   .query(&ReportParams::default())).await;
+    check_response(&spec, "/pages/{page_id}",
+		   http.patch(format!("{v1}/pages/{page_id}"))
+		   .bearer_auth(&api_key)
+		   .json(&PageUpdate { badge: Some(true), share_link: Some(true), ..Default::default() })).await;
+    let webhook = NewWebhook {
+	url: Url::parse("https://example.com/hook").unwrap(),
+	milestones: false,
+	spike: Some(jhm::routes::Spike { hits: 10, minutes: 5 }),
+    };
+    check_response(&spec, "/pages/{page_id}/webhooks",
+		   http.post(format!("{v1}/pages/{page_id}/webhooks"))
+		   .bearer_auth(&api_key)
+		   .json(&webhook)).await;
+    check_response(&spec, "/pages/{page_id}/webhooks",
+		   http.get(format!("{v1}/pages/{page_id}/webhooks"))
+		   .bearer_auth(&api_key)).await;
+}
+
+#[tokio::test]
+async fn old_paths_are_aliases_of_the_first_version() {
+    let test_app = TestApp::spawn().await;
+    let page_id = test_app.insert_page().await;
+
+    for path in ["health_check", &format!("pages/{page_id}/snippet")] {
+	let old = test_app.get_route(path).await;
+	let v1 = test_app.get_route(&format!("v1/{path}")).await;
+	assert_eq!(old.status(), 200);
+	assert_eq!(v1.status(), 200);
+	assert_eq!(old.text().await.unwrap(), v1.text().await.unwrap());
+    }
+}
@@ -0,0 +1,133 @@
+use crate::helper::TestApp;
+use uuid::Uuid;
+
+use jhm::routes::{Page, PageUpdate};
+
+#[tokio::test]
+async fn owner_can_update_page() {
+    const URL: &str = "https://example.com/";
+    let test_app = TestApp::spawn().await;
+    let (page_id, api_key) = test_app.register_owned_page(URL).await;
+
+    let update = PageUpdate { badge: Some(true), ..Default::default() };
+    let response = test_app.patch_page(page_id, &update, Some(&api_key)).await;
+    assert!(response.status().is_success());
+    let page = response.json::<Page>().await.unwrap();
+    assert_eq!(page.page_id, page_id);
+    assert_eq!(page.url, URL);
+    assert!(page.badge);
+}
+
+#[tokio::test]
+async fn update_page_401s_without_api_key() {
+    let test_app = TestApp::spawn().await;
+    let (page_id, _) = test_app
+	.register_owned_page("https://example.com/")
+	.await;
+
+    let response = test_app
+	.patch_page(page_id, &PageUpdate::default(), None)
+	.await;
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+
+    let response = test_app
+	.patch_page(page_id, &PageUpdate::default(), Some("jhm_invalid"))
+	.await;
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn update_page_404s_for_other_owners() {
+    let test_app = TestApp::spawn().await;
+    let (page_id, _) = test_app
+	.register_owned_page("https://example.com/")
+	.await;
+    let other = test_app.post_owner().await;
+
+    let response = test_app
+	.patch_page(page_id, &PageUpdate::default(), Some(&other.api_key))
+	.await;
+    assert_eq!(404, response.status().as_u16());
+
+    let response = test_app
+	.patch_page(Uuid::new_v4(), &PageUpdate::default(), Some(&other.api_key))
+	.await;
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn pages_registered_without_api_key_have_no_owner() {
+    let test_app = TestApp::spawn().await;
+    let owner = test_app.post_owner().await;
+    let page_id = test_app
+	.post_register("url=https://example.com/")
+	.await
+	.json::<Uuid>()
+	.await
+	.unwrap();
+
+    let response = test_app
+	.patch_page(page_id, &PageUpdate::default(), Some(&owner.api_key))
+	.await;
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn owner_can_update_the_metadata_of_a_page() {
+    let test_app = TestApp::spawn().await;
+    let (page_id, api_key) = test_app
+	.register_owned_page("https://example.com/")
+	.await;
+
+    let update = PageUpdate {
+	title: Some(" Getting started ".into()),
+	tags: Some(vec!["tutorial".into(), "rust".into(), "tutorial".into()]),
+	group: Some("docs".into()),
+	..Default::default()
+    };
+    let page = test_app.patch_page(page_id, &update, Some(&api_key)).await
+	.json::<Page>()
+	.await
+	.unwrap();
+    assert_eq!(page.title.as_deref(), Some("Getting started"));
+    assert_eq!(page.tags, vec!["tutorial", "rust"]);
+    assert_eq!(page.group.as_deref(), Some("docs"));
+
+    // Missing fields are kept, empty ones are removed.
+    let update = PageUpdate { title: Some("".into()), ..Default::default() };
+    let page = test_app.patch_page(page_id, &update, Some(&api_key)).await
+	.json::<Page>()
+	.await
+	.unwrap();
+    assert_eq!(page.title, None);
+    assert_eq!(page.tags, vec!["tutorial", "rust"]);
+    assert_eq!(page.group.as_deref(), Some("docs"));
+
+    let update = PageUpdate { tags: Some(vec![]), group: Some(" ".into()), ..Default::default() };
+    let page = test_app.patch_page(page_id, &update, Some(&api_key)).await
+	.json::<Page>()
+	.await
+	.unwrap();
+    assert!(page.tags.is_empty());
+    assert_eq!(page.group, None);
+}
+
+#[tokio::test]
+async fn update_page_400s_on_invalid_metadata() {
+    let test_app = TestApp::spawn().await;
+    let (page_id, api_key) = test_app
+	.register_owned_page("https://example.com/")
+	.await;
+
+    let updates = [
+	PageUpdate { title: Some("x".repeat(201)), ..Default::default() },
+	PageUpdate { tags: Some(vec!["x".repeat(51)]), ..Default::default() },
+	PageUpdate { tags: Some((0..21).map(|i| i.to_string()).collect()), ..Default::default() },
+	PageUpdate { group: Some("x".repeat(101)), ..Default::default() },
+    ];
+    for update in updates {
+	let response = test_app.patch_page(page_id, &update, Some(&api_key)).await;
+	assert_eq!(400, response.status().as_u16(), "{update:?}");
+    }
+}
@@ -0,0 +1,53 @@
+use crate::helper::TestApp;
+use sqlx::{Connection, Executor, PgConnection};
+use jhm::configuration::{get_configuration, StorageBackend};
+use jhm::routes::{ComponentStatus, Readiness};
+
+#[tokio::test]
+async fn ready_works() {
+    let test_app = TestApp::spawn().await;
+    let response = test_app.get_route("ready").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let readiness: Readiness = response.json().await.unwrap();
+    assert_eq!(readiness, Readiness {
+	postgres: Some(ComponentStatus::Up),
+	redis: Some(ComponentStatus::Up),
+	degraded: false,
+    });
+}
+
+#[tokio::test]
+async fn ready_503s_without_postgres() {
+    let test_app = TestApp::spawn().await;
+    let database_name: String = sqlx::query_scalar("SELECT current_database()")
+	.fetch_one(&test_app.db)
+	.await
+	.unwrap();
+    test_app.db.close().await;
+    let configuration = get_configuration().unwrap();
+    let mut connection = PgConnection::connect_with(&configuration.postgres.without_db())
+	.await
+	.unwrap();
+    connection
+	.execute(format!(r#"DROP DATABASE "{database_name}" WITH (FORCE);"#).as_str())
+	.await
+	.unwrap();
+
+    let response = test_app.get_route("ready").await;
+    assert_eq!(response.status().as_u16(), 503);
+    let readiness: Readiness = response.json().await.unwrap();
+    assert_eq!(readiness, Readiness {
+	postgres: Some(ComponentStatus::Down),
+	redis: Some(ComponentStatus::Up),
+	degraded: false,
+    });
+}
+
+#[tokio::test]
+async fn ready_only_checks_what_is_used() {
+    let test_app = TestApp::spawn_with(|c| c.storage.backend = StorageBackend::Memory).await;
+    let response = test_app.get_route("ready").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let readiness: Readiness = response.json().await.unwrap();
+    assert_eq!(readiness, Readiness { postgres: None, redis: None, degraded: false });
+}
@@ -0,0 +1,137 @@
+use std::time::{Duration, Instant};
+use crate::helper::TestApp;
+
+use jhm::configuration::RedisFallback;
+use jhm::routes::{ComponentStatus, Health, Hits, Readiness};
+
+/// Port that nothing listens on.
+fn unused_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+	.unwrap()
+	.local_addr()
+	.unwrap()
+	.port()
+}
+
+/// Spawn an app whose Redis doesn't answer.
+async fn spawn_without_redis(fallback: RedisFallback) -> TestApp {
+    TestApp::spawn_with(|c| {
+	c.redis.port = unused_port();
+	c.redis.fallback = fallback;
+	c.redis.retry_interval_ms = 60_000;
+    }).await
+}
+
+async fn hit_twice(test_app: &TestApp, url: &str) -> Hits {
+    let page_id = test_app.register_page(url).await;
+    for _ in 0..2 {
+	let response = test_app.get_route(&format!("hit/{page_id}")).await;
+	assert!(response.status().is_success());
+    }
+    let response = test_app.get_hits(url).await;
+    assert!(response.status().is_success());
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn health_check_reports_redis_down() {
+    let test_app = spawn_without_redis(RedisFallback::Memory).await;
+    let response = test_app.get_route("health_check").await;
+    assert!(response.status().is_success());
+    let health: Health = response.json().await.unwrap();
+    assert_eq!(health.redis, Some(ComponentStatus::Down));
+}
+
+#[tokio::test]
+async fn visitors_are_deduplicated_in_memory_while_redis_is_down() {
+    let test_app = spawn_without_redis(RedisFallback::Memory).await;
+    let hits = hit_twice(&test_app, "https://example.com/").await;
+    assert_eq!(hits.n, 1);
+    assert_eq!(hits.active_visitors, 1);
+}
+
+#[tokio::test]
+async fn every_hit_counts_while_redis_is_down_with_count_fallback() {
+    let test_app = spawn_without_redis(RedisFallback::Count).await;
+    let hits = hit_twice(&test_app, "https://example.com/").await;
+    assert_eq!(hits.n, 2);
+}
+
+#[tokio::test]
+async fn hits_are_dropped_while_redis_is_down_with_drop_fallback() {
+    let test_app = spawn_without_redis(RedisFallback::Drop).await;
+    let hits = hit_twice(&test_app, "https://example.com/").await;
+    assert_eq!(hits.n, 0);
+}
+
+#[tokio::test]
+async fn hits_skip_redis_while_it_is_down() {
+    let test_app = spawn_without_redis(RedisFallback::Memory).await;
+    let page_id = test_app.register_page("https://example.com/").await;
+
+    // Each call to Redis would wait for the 2s timeout of
+    // the pool.
+    let start = Instant::now();
+    for _ in 0..5 {
+	let response = test_app.get_route(&format!("hit/{page_id}")).await;
+	assert!(response.status().is_success());
+    }
+    assert!(start.elapsed() < Duration::from_secs(1), "{:?}", start.elapsed());
+}
+
+#[tokio::test]
+async fn buffered_hits_are_written_right_away_while_redis_is_down() {
+    let test_app = TestApp::spawn_with(|c| {
+	c.redis.port = unused_port();
+	c.write_behind.enabled = true;
+    }).await;
+    let hits = hit_twice(&test_app, "https://example.com/").await;
+    assert_eq!(hits.n, 1);
+}
+
+#[tokio::test]
+async fn ready_503s_while_redis_is_down_with_drop_fallback() {
+    let test_app = spawn_without_redis(RedisFallback::Drop).await;
+    let response = test_app.get_route("ready").await;
+    assert_eq!(response.status().as_u16(), 503);
+    let readiness: Readiness = response.json().await.unwrap();
+    assert_eq!(readiness, Readiness {
+	postgres: Some(ComponentStatus::Up),
+	redis: Some(ComponentStatus::Down),
+	degraded: false,
+    });
+}
+
+#[tokio::test]
+async fn ready_is_degraded_while_hits_are_counted_without_redis() {
+    for fallback in [RedisFallback::Count, RedisFallback::Memory] {
+	let test_app = spawn_without_redis(fallback).await;
+	let response = test_app.get_route("ready").await;
+	assert_eq!(response.status().as_u16(), 200, "{fallback:?}");
+	let body: serde_json::Value = response.json().await.unwrap();
+	assert_eq!(
+	    body,
+	    serde_json::json!({"postgres": "up", "redis": "down", "degraded": true}),
+	    "{fallback:?}",
+	);
+    }
+}
+
+#[tokio::test]
+async fn active_503s_while_redis_is_down() {
+    let test_app = spawn_without_redis(RedisFallback::Memory).await;
+    test_app.register_page("https://example.com/").await;
+    let response = test_app.get_route("active?site=example.com").await;
+    assert_eq!(response.status().as_u16(), 503);
+}
+
+#[tokio::test]
+async fn live_streams_503_while_redis_is_down() {
+    let test_app = spawn_without_redis(RedisFallback::Memory).await;
+    let (page_id, api_key) = test_app.register_owned_page("https://example.com/").await;
+    let start = Instant::now();
+    let response = test_app.get_live(&format!("pages/{page_id}"), Some(&api_key)).await;
+    assert_eq!(response.status().as_u16(), 503);
+    // The stream doesn't wait for Redis to time out.
+    assert!(start.elapsed() < Duration::from_secs(1), "{:?}", start.elapsed());
+}
@@ -1,6 +1,8 @@
 use crate::helper::TestApp;
 use uuid::Uuid;
 
+use jhm::routes::{BatchResult, MAX_BATCH_SIZE};
+
 #[tokio::test]
 async fn register_400s_on_missing_data() {
     let test_app = TestApp::spawn().await;
@@ -96,3 +98,166 @@ async fn register_creates_each_url_only_once() {
     // Different pages should have different IDs.
     assert_ne!(page1_id1, page2_id1);
 }
+
+#[tokio::test]
+async fn register_batch_returns_a_result_per_url() {
+    const URL1: &str = "https://example.com/a";
+    const URL2: &str = "https://example.com/b?page=2";
+    let test_app = TestApp::spawn().await;
+    let existing = test_app.post_register(&format!("url={URL1}"))
+	.await
+	.json::<Uuid>()
+	.await
+	.unwrap();
+
+    let response = test_app
+	.post_register_batch(&[URL1, "not a URL", URL2, URL2])
+	.await;
+    assert!(response.status().is_success());
+    let results = response.json::<Vec<BatchResult>>().await.unwrap();
+
+    let urls: Vec<&str> = results.iter().map(|r| r.url.as_str()).collect();
+    assert_eq!(urls, [URL1, "not a URL", URL2, URL2]);
+    assert_eq!(results[0].page_id, Some(existing));
+    assert!(results[1].page_id.is_none());
+    assert!(results[1].error.is_some());
+    assert!(results[2].page_id.is_some());
+    assert_eq!(results[2].page_id, results[3].page_id);
+
+    // The new page is the same one that a single registration finds.
+    let page_id = test_app.post_register("url=https%3A%2F%2Fexample.com%2Fb%3Fpage%3D2")
+	.await
+	.json::<Uuid>()
+	.await
+	.unwrap();
+    assert_eq!(results[2].page_id, Some(page_id));
+}
+
+#[tokio::test]
+async fn register_batch_400s_on_too_many_urls() {
+    let test_app = TestApp::spawn().await;
+    let urls: Vec<String> = (0..=MAX_BATCH_SIZE)
+	.map(|i| format!("https://example.com/{i}"))
+	.collect();
+    let urls: Vec<&str> = urls.iter().map(String::as_str).collect();
+
+    let response = test_app.post_register_batch(&urls).await;
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn register_accepts_json_with_metadata() {
+    const URL: &str = "https://example.com/blog/hello";
+    let test_app = TestApp::spawn().await;
+    let response = test_app.post_register_json(&serde_json::json!({
+	"url": URL,
+	"title": "  Hello, world  ",
+	"tags": ["blog", " rust ", "blog", ""],
+	"site": "blog.example.com",
+    })).await;
+    assert_eq!(200, response.status().as_u16());
+    let page_id = response.json::<Uuid>().await.unwrap();
+
+    let page = sqlx::query!(
+	r#"
+SELECT url, title, tags, site
+FROM pages
+WHERE page_id = $1"#,
+	page_id,
+    )
+	.fetch_one(&test_app.db)
+	.await
+	.unwrap();
+    assert_eq!(page.url, URL);
+    assert_eq!(page.title.as_deref(), Some("Hello, world"));
+    assert_eq!(page.tags, vec!["blog", "rust"]);
+    assert_eq!(page.site, "blog.example.com");
+}
+
+#[tokio::test]
+async fn register_json_defaults_to_the_host_as_site() {
+    let test_app = TestApp::spawn().await;
+    let response = test_app
+	.post_register_json(&serde_json::json!({"url": "https://example.com/"}))
+	.await;
+    assert_eq!(200, response.status().as_u16());
+    let page_id = response.json::<Uuid>().await.unwrap();
+
+    let page = sqlx::query!("SELECT site, title, tags FROM pages WHERE page_id = $1", page_id)
+	.fetch_one(&test_app.db)
+	.await
+	.unwrap();
+    assert_eq!(page.site, "example.com");
+    assert_eq!(page.title, None);
+    assert!(page.tags.is_empty());
+}
+
+#[tokio::test]
+async fn register_json_400s_on_invalid_data() {
+    let test_app = TestApp::spawn().await;
+    let long_tag = "x".repeat(51);
+    let cases = [
+	serde_json::json!({"url": "This is not a valid URL."}),
+	serde_json::json!({"title": "No URL"}),
+	serde_json::json!({"url": "https://example.com/", "tags": [long_tag]}),
+	serde_json::json!({"url": "https://example.com/", "title": "x".repeat(201)}),
+    ];
+    for body in cases {
+	let response = test_app.post_register_json(&body).await;
+	assert_eq!(400, response.status().as_u16(), "{body}");
+    }
+}
+
+#[tokio::test]
+async fn register_415s_on_other_content_types() {
+    let test_app = TestApp::spawn().await;
+    let response = reqwest::Client::new()
+	.post(&format!("{}/register", &test_app.address))
+	.header("Content-Type", "text/plain")
+	.body("https://example.com/")
+	.send()
+	.await
+	.unwrap();
+    assert_eq!(415, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn register_keeps_the_query_string_of_urls() {
+    const URL: &str = "https://example.com/search?q=a+b&page=2&sort=new#results";
+    let test_app = TestApp::spawn().await;
+
+    let form = reqwest::Client::new()
+	.post(&format!("{}/register", &test_app.address))
+	.form(&[("url", URL)])
+	.send()
+	.await
+	.unwrap();
+    assert_eq!(200, form.status().as_u16());
+    let form_page_id = form.json::<Uuid>().await.unwrap();
+
+    let json = test_app.post_register_json(&serde_json::json!({"url": URL})).await;
+    assert_eq!(200, json.status().as_u16());
+    assert_eq!(json.json::<Uuid>().await.unwrap(), form_page_id);
+
+    let url = sqlx::query!("SELECT url FROM pages WHERE page_id = $1", form_page_id)
+	.fetch_one(&test_app.db)
+	.await
+	.unwrap()
+	.url;
+    assert_eq!(url, URL);
+
+    let response = test_app.get_hits(URL).await;
+    assert!(response.status().is_success());
+}
+
+#[tokio::test]
+async fn register_tells_apart_urls_that_differ_in_their_query_string() {
+    let test_app = TestApp::spawn().await;
+    let mut page_ids = vec![];
+    for url in ["https://example.com/?id=1", "https://example.com/?id=1&lang=en"] {
+	let response = test_app.post_register_json(&serde_json::json!({"url": url})).await;
+	assert_eq!(200, response.status().as_u16());
+	page_ids.push(response.json::<Uuid>().await.unwrap());
+    }
+    assert_ne!(page_ids[0], page_ids[1]);
+}
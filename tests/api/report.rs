@@ -0,0 +1,88 @@
+use crate::helper::TestApp;
+use uuid::Uuid;
+
+use jhm::dashboard::SECS_PER_DAY;
+use jhm::rollups::rebuild;
+use jhm::routes::Report;
+use jhm::utils::unix_time_secs;
+
+async fn get_report(test_app: &TestApp, query: &str, api_key: Option<&str>) -> reqwest::Response {
+    let mut request = reqwest::Client::new()
+	.get(&format!("{}/report{query}", test_app.address));
+    if let Some(api_key) = api_key {
+	request = request.bearer_auth(api_key);
+    }
+    request.send().await.expect("Failed to execute request")
+}
+
+/// Insert a raw hit, and count it in the rollups.
+async fn insert_hit(test_app: &TestApp, page_id: Uuid, days_ago: i64, referrer: Option<&str>) {
+    let timestamp = unix_time_secs() as i64 - days_ago * SECS_PER_DAY - 60;
+    sqlx::query!(
+	"INSERT INTO page_hits (page_id, timestamp, referrer) VALUES ($1, $2, $3)",
+	page_id,
+	timestamp,
+	referrer,
+    )
+	.execute(&test_app.db)
+	.await
+	.unwrap();
+    rebuild(&test_app.db, None).await.unwrap();
+}
+
+#[tokio::test]
+async fn report_compares_pages_of_owner_with_previous_period() {
+    let test_app = TestApp::spawn().await;
+    let owner = test_app.post_owner().await;
+    let mut page_ids = vec![];
+    for url in ["https://example.com/a", "https://example.com/b"] {
+	let page_id: Uuid = test_app
+	    .post_register_with_key(&format!("url={url}"), &owner.api_key)
+	    .await
+	    .json()
+	    .await
+	    .unwrap();
+	page_ids.push(page_id);
+    }
+    let (other_page_id, _) = test_app
+	.register_owned_page("https://example.com/other")
+	.await;
+
+    insert_hit(&test_app, page_ids[1], 1, Some("https://news.example.org/")).await;
+    insert_hit(&test_app, page_ids[1], 2, Some("https://news.example.org/")).await;
+    insert_hit(&test_app, page_ids[1], 10, None).await;
+    insert_hit(&test_app, page_ids[0], 10, None).await;
+    insert_hit(&test_app, page_ids[0], 30, None).await;
+    insert_hit(&test_app, other_page_id, 1, Some("https://other.example.org/")).await;
+
+    let response = get_report(&test_app, "?period=week", Some(&owner.api_key)).await;
+    assert!(response.status().is_success());
+    let report = response.json::<Report>().await.unwrap();
+    assert_eq!(report.until - report.since, 7 * SECS_PER_DAY);
+
+    let pages: Vec<_> = report.pages
+	.iter()
+	.map(|page| (page.page_id, page.hits, page.previous_hits))
+	.collect();
+    assert_eq!(pages, vec![(page_ids[1], 2, 1), (page_ids[0], 0, 1)]);
+    assert_eq!(report.top_referrers.len(), 1);
+    assert_eq!(report.top_referrers[0].referrer, "https://news.example.org/");
+    assert_eq!(report.top_referrers[0].n, 2);
+}
+
+#[tokio::test]
+async fn report_401s_without_api_key() {
+    let test_app = TestApp::spawn().await;
+
+    let response = get_report(&test_app, "", None).await;
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn report_400s_on_unknown_period() {
+    let test_app = TestApp::spawn().await;
+    let owner = test_app.post_owner().await;
+
+    let response = get_report(&test_app, "?period=year", Some(&owner.api_key)).await;
+    assert_eq!(400, response.status().as_u16());
+}
@@ -0,0 +1,234 @@
+use crate::helper::{TestApp, COUNTRY_HEADER};
+use url::Url;
+use uuid::Uuid;
+
+use jhm::client::JhmClient;
+use jhm::dashboard::SECS_PER_DAY;
+use jhm::rollups::{rebuild, Dimension};
+use jhm::routes::{BreakdownParams, DimensionHits};
+use jhm::utils::unix_time_secs;
+
+const IPHONE: &str = "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X) Mobile/15E148";
+
+/// Client of a new owner, since only owners can break down
+/// the hits of their pages.
+async fn client_of(test_app: &TestApp) -> JhmClient {
+    let owner = test_app.post_owner().await;
+    JhmClient::new(Url::parse(&test_app.address).unwrap())
+	.with_api_key(&owner.api_key)
+}
+
+fn by(dimension: Dimension) -> BreakdownParams {
+    BreakdownParams { dimension, since: None, until: None, limit: None }
+}
+
+fn values(hits: &[DimensionHits]) -> Vec<(&str, i64)> {
+    hits.iter().map(|hits| (hits.value.as_str(), hits.n)).collect()
+}
+
+/// All rollups of the page, hourly ones first.
+async fn rollups_of(test_app: &TestApp, page_id: Uuid) -> Vec<(String, String, i64, i64)> {
+    sqlx::query!(
+	r#"
+SELECT 'hourly' AS "table!", dimension AS "dimension!", value AS "value!",
+       hour AS "bucket!", hits AS "hits!"
+FROM hits_hourly
+WHERE page_id = $1
+UNION ALL
+SELECT 'daily', dimension, value, day, hits
+FROM hits_daily
+WHERE page_id = $1
+ORDER BY 1 DESC, 2, 3, 4"#,
+	page_id,
+    )
+	.fetch_all(&test_app.db)
+	.await
+	.unwrap()
+	.into_iter()
+	.map(|r| (format!("{} {}", r.table, r.dimension), r.value, r.bucket, r.hits))
+	.collect()
+}
+
+#[tokio::test]
+async fn hits_are_rolled_up_by_dimension() {
+    let test_app = TestApp::spawn().await;
+    let client = client_of(&test_app).await;
+    let page_id = client.register(&Url::parse("https://example.com/").unwrap()).await.unwrap();
+
+    let response = test_app.get_hit_with_headers(&format!("hit/{page_id}"), &[
+	("Referer", "https://news.example.org/post?id=1"),
+	("User-Agent", IPHONE),
+	(COUNTRY_HEADER, "de"),
+    ]).await;
+    assert!(response.status().is_success());
+
+    let rollups = rollups_of(&test_app, page_id).await;
+    let hour = rollups[0].2;
+    assert_eq!(hour % 3600, 0);
+    let day = hour - hour % SECS_PER_DAY;
+    let expected = |table: &str, bucket: i64| vec![
+	(format!("{table} country"), "DE".to_string(), bucket, 1),
+	(format!("{table} device"), "mobile".to_string(), bucket, 1),
+	(format!("{table} referrer"), "https://news.example.org/post".to_string(), bucket, 1),
+	(format!("{table} total"), "".to_string(), bucket, 1),
+    ];
+    assert_eq!(rollups, [expected("hourly", hour), expected("daily", day)].concat());
+
+    let countries = client.breakdown(page_id, &by(Dimension::Country)).await.unwrap();
+    assert_eq!(values(&countries), vec![("DE", 1)]);
+}
+
+#[tokio::test]
+async fn events_are_only_rolled_up_as_events() {
+    let test_app = TestApp::spawn().await;
+    let client = client_of(&test_app).await;
+    let page_id = client.register(&Url::parse("https://example.com/").unwrap()).await.unwrap();
+
+    client.hit_event(page_id, "read-to-end").await.unwrap();
+
+    let events = client.breakdown(page_id, &by(Dimension::Event)).await.unwrap();
+    assert_eq!(values(&events), vec![("read-to-end", 1)]);
+    let total = client.breakdown(page_id, &by(Dimension::Total)).await.unwrap();
+    assert!(total.is_empty());
+}
+
+#[tokio::test]
+async fn devices_are_told_apart_by_user_agent() {
+    let test_app = TestApp::spawn().await;
+    let client = client_of(&test_app).await;
+    let user_agents = [
+	IPHONE,
+	"Mozilla/5.0 (Linux; Android 14; Pixel 8) Mobile Safari/537.36",
+	"Mozilla/5.0 (Linux; Android 13; SM-X710) Safari/537.36",
+	"Mozilla/5.0 (iPad; CPU OS 17_0 like Mac OS X)",
+	"Mozilla/5.0 (X11; Linux x86_64; rv:121.0) Gecko/20100101 Firefox/121.0",
+	"Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)",
+    ];
+    // A page per hit, so that each one counts as a new visit.
+    for (i, user_agent) in user_agents.iter().enumerate() {
+	let url = Url::parse(&format!("https://example.com/{i}")).unwrap();
+	let page_id = client.register(&url).await.unwrap();
+	test_app.get_hit_with_headers(&format!("hit/{page_id}"), &[("User-Agent", user_agent)]).await;
+    }
+
+    let devices: Vec<(String, i64)> = sqlx::query!(
+	r#"
+SELECT value, SUM(hits)::bigint AS "n!"
+FROM hits_daily
+WHERE dimension = 'device'
+GROUP BY value
+ORDER BY "n!" DESC, value"#,
+    )
+	.fetch_all(&test_app.db)
+	.await
+	.unwrap()
+	.into_iter()
+	.map(|r| (r.value, r.n))
+	.collect();
+    let devices: Vec<(&str, i64)> = devices.iter().map(|(value, n)| (value.as_str(), *n)).collect();
+    assert_eq!(devices, vec![("mobile", 2), ("tablet", 2), ("bot", 1), ("desktop", 1)]);
+}
+
+#[tokio::test]
+async fn rebuild_recounts_the_rollups_from_the_raw_hits() {
+    let test_app = TestApp::spawn().await;
+    let client = client_of(&test_app).await;
+    let page_id = client.register(&Url::parse("https://example.com/").unwrap()).await.unwrap();
+    test_app.get_hit_with_headers(&format!("hit/{page_id}"), &[
+	("Referer", "https://news.example.org/?utm_source=news&utm_campaign=\"launch\""),
+	("User-Agent", IPHONE),
+	(COUNTRY_HEADER, "FR"),
+    ]).await;
+    client.hit_event(page_id, "dwell-30s").await.unwrap();
+    let counted = rollups_of(&test_app, page_id).await;
+    assert!(counted.iter().any(|(dimension, ..)| dimension == "hourly campaign"));
+
+    sqlx::query!("UPDATE hits_hourly SET hits = 100").execute(&test_app.db).await.unwrap();
+    sqlx::query!("DELETE FROM hits_daily").execute(&test_app.db).await.unwrap();
+    rebuild(&test_app.db, None).await.unwrap();
+    assert_eq!(rollups_of(&test_app, page_id).await, counted);
+}
+
+#[tokio::test]
+async fn rebuild_counts_hits_that_only_have_timestamps() {
+    let test_app = TestApp::spawn().await;
+    let client = client_of(&test_app).await;
+    let page_id = client.register(&Url::parse("https://example.com/").unwrap()).await.unwrap();
+    let now = unix_time_secs() as i64;
+    // Hits counted before `page_hits` only have their timestamp,
+    // while newer ones have both.
+    sqlx::query!(
+	"UPDATE pages SET timestamps = $2 WHERE page_id = $1",
+	page_id,
+	&[now - 10 * SECS_PER_DAY, now - 10 * SECS_PER_DAY, now][..],
+    )
+	.execute(&test_app.db)
+	.await
+	.unwrap();
+    sqlx::query!("INSERT INTO page_hits (page_id, timestamp) VALUES ($1, $2)", page_id, now)
+	.execute(&test_app.db)
+	.await
+	.unwrap();
+
+    rebuild(&test_app.db, None).await.unwrap();
+    let total = client.breakdown(page_id, &by(Dimension::Total)).await.unwrap();
+    assert_eq!(values(&total), vec![("", 3)]);
+}
+
+#[tokio::test]
+async fn rebuild_since_a_day_keeps_older_rollups() {
+    let test_app = TestApp::spawn().await;
+    let client = client_of(&test_app).await;
+    let page_id = client.register(&Url::parse("https://example.com/").unwrap()).await.unwrap();
+    let now = unix_time_secs() as i64;
+    for days_ago in [0, 10] {
+	sqlx::query!(
+	    "INSERT INTO page_hits (page_id, timestamp) VALUES ($1, $2)",
+	    page_id,
+	    now - days_ago * SECS_PER_DAY,
+	)
+	    .execute(&test_app.db)
+	    .await
+	    .unwrap();
+    }
+
+    rebuild(&test_app.db, Some(now - SECS_PER_DAY)).await.unwrap();
+    let total = client.breakdown(page_id, &by(Dimension::Total)).await.unwrap();
+    assert_eq!(values(&total), vec![("", 1)]);
+
+    rebuild(&test_app.db, None).await.unwrap();
+    let total = client.breakdown(page_id, &by(Dimension::Total)).await.unwrap();
+    assert_eq!(values(&total), vec![("", 2)]);
+    let recent = BreakdownParams { since: Some(now - SECS_PER_DAY), ..by(Dimension::Total) };
+    let total = client.breakdown(page_id, &recent).await.unwrap();
+    assert_eq!(values(&total), vec![("", 1)]);
+}
+
+#[tokio::test]
+async fn breakdown_400s_on_invalid_params() {
+    let test_app = TestApp::spawn().await;
+    let (page_id, api_key) = test_app.register_owned_page("https://example.com/").await;
+    for query in ["dimension=planet", "", "dimension=country&limit=0", "dimension=country&limit=1001"] {
+	let response = test_app
+	    .get_route_with_key(&format!("pages/{page_id}/breakdown?{query}"), &api_key)
+	    .await;
+	assert_eq!(400, response.status().as_u16(), "{query}");
+    }
+}
+
+#[tokio::test]
+async fn breakdowns_of_other_owners_pages_are_hidden() {
+    let test_app = TestApp::spawn().await;
+    let (page_id, _) = test_app.register_owned_page("https://example.com/").await;
+    let other = test_app.post_owner().await;
+    let path = format!("pages/{page_id}/breakdown?dimension=total");
+
+    let response = test_app.get_route(&path).await;
+    assert_eq!(401, response.status().as_u16());
+    let response = test_app.get_route_with_key(&path, &other.api_key).await;
+    assert_eq!(404, response.status().as_u16());
+    let response = test_app
+	.get_route_with_key(&format!("pages/{}/breakdown?dimension=total", Uuid::new_v4()), &other.api_key)
+	.await;
+    assert_eq!(404, response.status().as_u16());
+}
@@ -0,0 +1,75 @@
+use crate::helper::TestApp;
+
+#[tokio::test]
+async fn snippet_defaults_to_css() {
+    let test_app = TestApp::spawn().await;
+    let page_id = test_app.insert_page().await;
+
+    let response = test_app
+	.get_route(&format!("pages/{page_id}/snippet"))
+	.await;
+    assert!(response.status().is_success());
+    assert_eq!(
+	response.headers()["Content-Type"],
+	"text/css; charset=utf-8",
+    );
+    let body = response.text().await.unwrap();
+    assert!(body.starts_with("body:hover {"));
+    assert!(body.contains(&format!("/hit/{page_id}\"")));
+}
+
+#[tokio::test]
+async fn snippet_supports_all_formats() {
+    let test_app = TestApp::spawn().await;
+    let page_id = test_app.insert_page().await;
+
+    for (format, start) in [
+	("css", "body:hover {"),
+	("style", "<style>"),
+	("img", "<img "),
+	("prefetch", "<link rel=\"prefetch\""),
+    ] {
+	let response = test_app
+	    .get_route(&format!("pages/{page_id}/snippet?format={format}"))
+	    .await;
+	assert!(response.status().is_success());
+	let body = response.text().await.unwrap();
+	assert!(body.starts_with(start), "Unexpected {format} snippet: {body}");
+	assert!(body.contains(&format!("/hit/{page_id}")));
+    }
+}
+
+#[tokio::test]
+async fn snippet_400s_on_unknown_format() {
+    let test_app = TestApp::spawn().await;
+    let page_id = test_app.insert_page().await;
+
+    let response = test_app
+	.get_route(&format!("pages/{page_id}/snippet?format=gif"))
+	.await;
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn snippet_404s_on_unknown_page() {
+    let test_app = TestApp::spawn().await;
+
+    let response = test_app
+	.get_route(&format!("pages/{}/snippet", uuid::Uuid::new_v4()))
+	.await;
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn snippet_points_to_the_service_without_a_base_url() {
+    let test_app = TestApp::spawn_with(|c| c.application.base_url = None).await;
+    let page_id = test_app.insert_page().await;
+
+    let body = test_app
+	.get_route(&format!("pages/{page_id}/snippet"))
+	.await
+	.text()
+	.await
+	.unwrap();
+    assert!(body.contains(&format!("{}/hit/{page_id}", test_app.address)), "{body}");
+}
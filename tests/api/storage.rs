@@ -0,0 +1,217 @@
+use crate::helper::TestApp;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+use jhm::configuration::{StorageBackend, VisitorBackend};
+use jhm::routes::{BatchResult, Hits, Page, PageUpdate};
+
+/// Backends that don't need Postgres and Redis.
+const BACKENDS: [StorageBackend; 2] = [StorageBackend::Memory, StorageBackend::Sqlite];
+
+/// The SQLite database is in the directory, which is removed
+/// with all its files when it's dropped.
+async fn spawn(backend: StorageBackend, dir: &TempDir) -> TestApp {
+    TestApp::spawn_with(|c| {
+	c.storage.backend = backend;
+	c.storage.sqlite_path = dir.path().join("jhm.sqlite").to_string_lossy().into();
+    }).await
+}
+
+fn temp_dir() -> TempDir {
+    TempDir::new().expect("Failed to create temporary directory")
+}
+
+async fn hits(test_app: &TestApp, query: &[(&str, &str)]) -> Hits {
+    let response = test_app.get_hits_with_query(query).await;
+    assert!(response.status().is_success(), "{}", response.status());
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn hits_are_counted_with_every_backend() {
+    for backend in BACKENDS {
+	let dir = temp_dir();
+	let test_app = spawn(backend, &dir).await;
+	let url = "https://example.com/post";
+	let page_id = test_app.register_page(url).await;
+	assert_eq!(test_app.register_page(url).await, page_id, "{backend:?}");
+
+	test_app.get_route(&format!("hit/{page_id}")).await;
+	// The same visitor only counts once.
+	test_app.get_route(&format!("hit/{page_id}")).await;
+	test_app.get_route(&format!("hit/{page_id}/event/signup")).await;
+	// Hits of pages that don't exist are ignored.
+	test_app.get_route(&format!("hit/{}", Uuid::new_v4())).await;
+
+	let hits = hits(&test_app, &[("url", url)]).await;
+	assert_eq!(hits.n, 1, "{backend:?}");
+	assert_eq!(hits.timestamps.len(), 1, "{backend:?}");
+	assert_eq!(hits.events.get("signup"), Some(&1), "{backend:?}");
+	assert_eq!(hits.active_visitors, 1, "{backend:?}");
+    }
+}
+
+#[tokio::test]
+async fn campaigns_and_batches_work_with_every_backend() {
+    for backend in BACKENDS {
+	let dir = temp_dir();
+	let test_app = spawn(backend, &dir).await;
+	let url = "https://example.com/";
+	let results: Vec<BatchResult> = test_app.post_register_batch(&[url, "not a url"])
+	    .await
+	    .json()
+	    .await
+	    .unwrap();
+	let page_id = results[0].page_id.unwrap();
+	assert!(results[1].error.is_some(), "{backend:?}");
+
+	test_app.get_hit_with_referer(page_id, "https://news.example.org/?utm_source=news").await;
+
+	let news = hits(&test_app, &[("url", url), ("utm_source", "news")]).await;
+	assert_eq!(news.n, 1, "{backend:?}");
+	let other = hits(&test_app, &[("url", url), ("utm_source", "other")]).await;
+	assert_eq!(other.n, 0, "{backend:?}");
+    }
+}
+
+#[tokio::test]
+async fn owners_work_with_every_backend() {
+    for backend in BACKENDS {
+	let dir = temp_dir();
+	let test_app = spawn(backend, &dir).await;
+	let owner = test_app.post_owner().await;
+
+	let response = test_app
+	    .post_register_with_key("url=https%3A%2F%2Fexample.com%2F", &owner.api_key)
+	    .await;
+	assert_eq!(response.status().as_u16(), 200, "{backend:?}");
+	let response = test_app
+	    .post_register_with_key("url=https%3A%2F%2Fexample.com%2F", "jhm_unknown")
+	    .await;
+	assert_eq!(response.status().as_u16(), 401, "{backend:?}");
+    }
+}
+
+#[tokio::test]
+async fn pages_can_be_managed_with_every_backend() {
+    for backend in BACKENDS {
+	let dir = temp_dir();
+	let test_app = spawn(backend, &dir).await;
+	let (page_id, api_key) = test_app.register_owned_page("https://example.com/").await;
+	test_app.get_route(&format!("hit/{page_id}")).await;
+
+	let response = test_app.get_route(&format!("badge/{page_id}.svg")).await;
+	assert_eq!(response.status().as_u16(), 404, "{backend:?}");
+
+	let update = PageUpdate {
+	    badge: Some(true),
+	    share_link: Some(true),
+	    title: Some("Home".to_string()),
+	    tags: Some(vec!["rust".to_string()]),
+	    ..Default::default()
+	};
+	let response = test_app.patch_page(page_id, &update, Some(&api_key)).await;
+	assert_eq!(response.status().as_u16(), 200, "{backend:?}");
+	let page: Page = response.json().await.unwrap();
+	assert_eq!(page.title.as_deref(), Some("Home"), "{backend:?}");
+	assert_eq!(page.tags, ["rust"], "{backend:?}");
+	assert_eq!(page.hits, 1, "{backend:?}");
+	assert!(page.badge && page.share_link.is_some(), "{backend:?}");
+
+	// Only the owner can change the page.
+	let other = test_app.post_owner().await;
+	let response = test_app.patch_page(page_id, &update, Some(&other.api_key)).await;
+	assert_eq!(response.status().as_u16(), 404, "{backend:?}");
+
+	let update = PageUpdate { title: Some(String::new()), ..Default::default() };
+	let page: Page = test_app.patch_page(page_id, &update, Some(&api_key))
+	    .await
+	    .json()
+	    .await
+	    .unwrap();
+	assert_eq!(page.title, None, "{backend:?}");
+	assert!(page.share_link.is_some(), "{backend:?}");
+
+	let response = test_app.get_route(&format!("badge/{page_id}.svg")).await;
+	assert_eq!(response.status().as_u16(), 200, "{backend:?}");
+	let response = test_app.get_route(&format!("pages/{page_id}/snippet")).await;
+	assert_eq!(response.status().as_u16(), 200, "{backend:?}");
+	let response = test_app.get_route(&format!("pages/{}/snippet", Uuid::new_v4())).await;
+	assert_eq!(response.status().as_u16(), 404, "{backend:?}");
+    }
+}
+
+#[tokio::test]
+async fn routes_that_need_postgres_are_not_implemented_with_other_backends() {
+    for backend in BACKENDS {
+	let dir = temp_dir();
+	let test_app = spawn(backend, &dir).await;
+	for path in ["report", "v1/tags", "active?site=example.com"] {
+	    let response = test_app.get_route(path).await;
+	    assert_eq!(response.status().as_u16(), 501, "{backend:?} {path}");
+	}
+	let response = test_app.get_route("v1/health_check").await;
+	assert!(response.status().is_success(), "{backend:?}");
+    }
+}
+
+#[tokio::test]
+async fn sqlite_keeps_hits_across_restarts() {
+    let dir = temp_dir();
+    let url = "https://example.com/";
+    let test_app = spawn(StorageBackend::Sqlite, &dir).await;
+    let page_id = test_app.register_page(url).await;
+    test_app.get_route(&format!("hit/{page_id}")).await;
+    test_app.stop().await;
+
+    let test_app = spawn(StorageBackend::Sqlite, &dir).await;
+    assert_eq!(test_app.register_page(url).await, page_id);
+    assert_eq!(hits(&test_app, &[("url", url)]).await.n, 1);
+}
+
+#[tokio::test]
+async fn visitors_can_be_kept_in_memory_with_postgres() {
+    let test_app = TestApp::spawn_with(|c| c.storage.visitors = Some(VisitorBackend::Memory)).await;
+    let url = "https://example.com/";
+    let page_id = test_app.register_page(url).await;
+
+    test_app.get_route(&format!("hit/{page_id}")).await;
+    test_app.get_route(&format!("hit/{page_id}")).await;
+
+    let hits = hits(&test_app, &[("url", url)]).await;
+    assert_eq!(hits.n, 1);
+    assert_eq!(hits.active_visitors, 1);
+}
+
+#[tokio::test]
+async fn visits_in_memory_end_after_the_visit_duration() {
+    let dir = temp_dir();
+    let test_app = spawn(StorageBackend::Memory, &dir).await;
+    let url = "https://example.com/";
+    let page_id = test_app.register_page(url).await;
+
+    test_app.get_route(&format!("hit/{page_id}")).await;
+    tokio::time::sleep(std::time::Duration::from_millis(2100)).await;
+    test_app.get_route(&format!("hit/{page_id}")).await;
+
+    assert_eq!(hits(&test_app, &[("url", url)]).await.n, 2);
+}
+
+#[tokio::test]
+async fn oldest_visits_in_memory_are_forgotten_when_full() {
+    let test_app = TestApp::spawn_with(|c| {
+	c.storage.backend = StorageBackend::Memory;
+	c.storage.visitor_capacity = 1;
+    }).await;
+    let first = "https://example.com/first";
+    let first_id = test_app.register_page(first).await;
+    let second_id = test_app.register_page("https://example.com/second").await;
+
+    test_app.get_route(&format!("hit/{first_id}")).await;
+    test_app.get_route(&format!("hit/{second_id}")).await;
+    // The visit of the first page was forgotten, so this is
+    // a new one.
+    test_app.get_route(&format!("hit/{first_id}")).await;
+
+    assert_eq!(hits(&test_app, &[("url", first)]).await.n, 2);
+}
@@ -0,0 +1,130 @@
+use crate::helper::TestApp;
+use url::Url;
+use uuid::Uuid;
+
+use jhm::client::JhmClient;
+use jhm::routes::TagsParams;
+
+/// Set the hits of a page, since hits of the same visitor
+/// aren't counted more than once.
+async fn set_hits(test_app: &TestApp, page_id: Uuid, hits: i32) {
+    sqlx::query!("UPDATE pages SET hits = $2 WHERE page_id = $1", page_id, hits)
+	.execute(&test_app.db)
+	.await
+	.unwrap();
+}
+
+#[tokio::test]
+async fn hits_are_summed_up_per_tag() {
+    let test_app = TestApp::spawn().await;
+    let owner = test_app.post_owner().await;
+    let client = JhmClient::new(Url::parse(&test_app.address).unwrap())
+	.with_api_key(&owner.api_key);
+    let intro = test_app.register_page_with("https://example.com/intro", &["tutorial", "rust"], None, Some(&owner.api_key)).await;
+    let traits = test_app.register_page_with("https://example.com/traits", &["tutorial"], None, Some(&owner.api_key)).await;
+    let news = test_app.register_page_with("https://example.com/news", &["news"], None, Some(&owner.api_key)).await;
+    let other = test_app.register_page_with("https://example.org/intro", &["tutorial"], None, Some(&owner.api_key)).await;
+    set_hits(&test_app, intro, 1).await;
+    set_hits(&test_app, traits, 2).await;
+    set_hits(&test_app, news, 4).await;
+    set_hits(&test_app, other, 8).await;
+
+    let site = TagsParams { site: Some("example.com".into()), group: None };
+    let tags = client.tags(&site).await.unwrap();
+    let tags: Vec<(&str, i64, i64)> = tags.iter()
+	.map(|tag| (tag.tag.as_str(), tag.pages, tag.n))
+	.collect();
+    assert_eq!(tags, vec![("news", 1, 4), ("tutorial", 2, 3), ("rust", 1, 1)]);
+
+    let all = client.tags(&TagsParams::default()).await.unwrap();
+    let tutorial = all.iter().find(|tag| tag.tag == "tutorial").unwrap();
+    assert_eq!((tutorial.pages, tutorial.n), (3, 11));
+}
+
+#[tokio::test]
+async fn hits_of_a_tag_are_reported_per_page() {
+    let test_app = TestApp::spawn().await;
+    let owner = test_app.post_owner().await;
+    let client = JhmClient::new(Url::parse(&test_app.address).unwrap())
+	.with_api_key(&owner.api_key);
+    let intro = test_app.register_page_with("https://example.com/docs/intro", &["tutorial"], Some("docs"), Some(&owner.api_key)).await;
+    let post = test_app.register_page_with("https://example.com/blog/post", &["tutorial"], Some("blog"), Some(&owner.api_key)).await;
+    test_app.register_page_with("https://example.com/blog/news", &["news"], Some("blog"), Some(&owner.api_key)).await;
+    set_hits(&test_app, intro, 1).await;
+    set_hits(&test_app, post, 2).await;
+
+    let site = TagsParams { site: Some("example.com".into()), group: None };
+    let hits = client.tag_hits("tutorial", &site).await.unwrap();
+    assert_eq!(hits.tag, "tutorial");
+    assert_eq!(hits.n, 3);
+    let pages: Vec<(Uuid, i64)> = hits.pages.iter().map(|page| (page.page_id, page.n)).collect();
+    assert_eq!(pages, vec![(post, 2), (intro, 1)]);
+    assert_eq!(hits.pages[0].group.as_deref(), Some("blog"));
+
+    let docs = TagsParams { group: Some("docs".into()), ..site };
+    let hits = client.tag_hits("tutorial", &docs).await.unwrap();
+    assert_eq!(hits.n, 1);
+    assert_eq!(hits.pages.len(), 1);
+    assert_eq!(hits.pages[0].page_id, intro);
+}
+
+#[tokio::test]
+async fn tags_with_special_characters_work() {
+    let test_app = TestApp::spawn().await;
+    let owner = test_app.post_owner().await;
+    let client = JhmClient::new(Url::parse(&test_app.address).unwrap())
+	.with_api_key(&owner.api_key);
+    let page_id = test_app.register_page_with("https://example.com/", &["c++/rust & go?"], None, Some(&owner.api_key)).await;
+    set_hits(&test_app, page_id, 1).await;
+
+    let hits = client.tag_hits("c++/rust & go?", &TagsParams::default()).await.unwrap();
+    assert_eq!(hits.n, 1);
+    assert_eq!(hits.pages[0].page_id, page_id);
+}
+
+#[tokio::test]
+async fn unknown_tags_have_no_hits() {
+    let test_app = TestApp::spawn().await;
+    let owner = test_app.post_owner().await;
+    let client = JhmClient::new(Url::parse(&test_app.address).unwrap())
+	.with_api_key(&owner.api_key);
+
+    let hits = client.tag_hits("nothing", &TagsParams::default()).await.unwrap();
+    assert_eq!(hits.n, 0);
+    assert!(hits.pages.is_empty());
+    assert!(client.tags(&TagsParams::default()).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn tags_need_an_api_key() {
+    let test_app = TestApp::spawn().await;
+    test_app.register_page_with("https://example.com/", &["rust"], None, None).await;
+
+    for path in ["tags", "tags/rust/hits"] {
+	let response = test_app.get_route(path).await;
+	assert_eq!(response.status().as_u16(), 401, "{path}");
+    }
+}
+
+#[tokio::test]
+async fn tags_only_count_the_pages_of_the_owner() {
+    let test_app = TestApp::spawn().await;
+    let owner = test_app.post_owner().await;
+    let other = test_app.post_owner().await;
+    let client = JhmClient::new(Url::parse(&test_app.address).unwrap())
+	.with_api_key(&owner.api_key);
+    let mine = test_app.register_page_with("https://example.com/mine", &["rust"], None, Some(&owner.api_key)).await;
+    let theirs = test_app.register_page_with("https://example.com/theirs", &["rust"], None, Some(&other.api_key)).await;
+    let anonymous = test_app.register_page_with("https://example.com/anonymous", &["rust"], None, None).await;
+    set_hits(&test_app, mine, 1).await;
+    set_hits(&test_app, theirs, 2).await;
+    set_hits(&test_app, anonymous, 4).await;
+
+    let tags = client.tags(&TagsParams::default()).await.unwrap();
+    assert_eq!(tags.len(), 1);
+    assert_eq!((tags[0].pages, tags[0].n), (1, 1));
+    let hits = client.tag_hits("rust", &TagsParams::default()).await.unwrap();
+    assert_eq!(hits.n, 1);
+    let pages: Vec<Uuid> = hits.pages.iter().map(|page| page.page_id).collect();
+    assert_eq!(pages, vec![mine]);
+}
@@ -0,0 +1,319 @@
+use crate::helper::TestApp;
+use std::time::Duration;
+use uuid::Uuid;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use wiremock::matchers::{method, path};
+
+use jhm::routes::{NewWebhook, Spike, Webhook};
+use jhm::webhooks::{sign, Trigger, WebhookPayload, SIGNATURE_HEADER};
+
+/// Wait until the mock server has received `n` requests.
+async fn received_requests(server: &MockServer, n: usize) -> Vec<wiremock::Request> {
+    for _ in 0..100 {
+	let requests = server.received_requests().await.unwrap();
+	if requests.len() >= n {
+	    return requests;
+	}
+	tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("Expected {n} webhook deliveries");
+}
+
+/// Check the signature of the delivery and decode its payload.
+fn verify(request: &wiremock::Request, secret: &str) -> WebhookPayload {
+    let body = std::str::from_utf8(&request.body).unwrap();
+    let signature = request.headers
+	.iter()
+	.find(|(name, _)| name.as_str().eq_ignore_ascii_case(SIGNATURE_HEADER))
+	.map(|(_, values)| values.last().as_str().to_owned())
+	.expect("Missing signature");
+    assert_eq!(signature, format!("sha256={}", sign(secret, body)));
+    serde_json::from_str(body).unwrap()
+}
+
+async fn create_webhook(
+    test_app: &TestApp,
+    page_id: Uuid,
+    api_key: &str,
+    webhook: NewWebhook,
+) -> Webhook {
+    let response = test_app.post_webhook(page_id, &webhook, Some(api_key)).await;
+    assert!(response.status().is_success());
+    response.json().await.unwrap()
+}
+
+fn hook_url(server: &MockServer) -> url::Url {
+    format!("{}/hook", server.uri()).parse().unwrap()
+}
+
+#[tokio::test]
+async fn milestones_are_delivered_signed() {
+    let test_app = TestApp::spawn().await;
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+	.and(path("/hook"))
+	.respond_with(ResponseTemplate::new(200))
+	.mount(&server)
+	.await;
+    let (page_id, api_key) = test_app
+	.register_owned_page("https://example.com/")
+	.await;
+    let webhook = create_webhook(&test_app, page_id, &api_key, NewWebhook {
+	url: hook_url(&server),
+	milestones: true,
+	spike: None,
+    }).await;
+
+    sqlx::query!("UPDATE pages SET hits = 999 WHERE page_id = $1", page_id)
+	.execute(&test_app.db)
+	.await
+	.unwrap();
+    test_app.get_route(&format!("hit/{page_id}")).await;
+
+    let requests = received_requests(&server, 1).await;
+    let payload = verify(&requests[0], &webhook.secret);
+    assert_eq!(payload.webhook_id, webhook.webhook_id);
+    assert_eq!(payload.page_id, page_id);
+    assert_eq!(payload.trigger, Trigger::Milestone { hits: 1000 });
+
+    // Milestones are only delivered once.
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn spikes_are_delivered() {
+    let test_app = TestApp::spawn().await;
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+	.respond_with(ResponseTemplate::new(200))
+	.mount(&server)
+	.await;
+    let (page_id, api_key) = test_app
+	.register_owned_page("https://example.com/")
+	.await;
+    let webhook = create_webhook(&test_app, page_id, &api_key, NewWebhook {
+	url: hook_url(&server),
+	milestones: false,
+	spike: Some(Spike { hits: 1, minutes: 5 }),
+    }).await;
+
+    test_app.get_route(&format!("hit/{page_id}")).await;
+
+    let requests = received_requests(&server, 1).await;
+    let payload = verify(&requests[0], &webhook.secret);
+    assert_eq!(payload.trigger, Trigger::Spike { hits: 1, minutes: 5 });
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried() {
+    let test_app = TestApp::spawn().await;
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+	.respond_with(ResponseTemplate::new(500))
+	.up_to_n_times(2)
+	.mount(&server)
+	.await;
+    Mock::given(method("POST"))
+	.respond_with(ResponseTemplate::new(200))
+	.mount(&server)
+	.await;
+    let (page_id, api_key) = test_app
+	.register_owned_page("https://example.com/")
+	.await;
+    let webhook = create_webhook(&test_app, page_id, &api_key, NewWebhook {
+	url: hook_url(&server),
+	milestones: false,
+	spike: Some(Spike { hits: 1, minutes: 5 }),
+    }).await;
+
+    test_app.get_route(&format!("hit/{page_id}")).await;
+
+    let requests = received_requests(&server, 3).await;
+    let payloads: Vec<_> = requests
+	.iter()
+	.map(|request| verify(request, &webhook.secret))
+	.collect();
+    assert!(payloads.iter().all(|p| p.timestamp == payloads[0].timestamp));
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(server.received_requests().await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn deliveries_are_not_locked_while_they_are_sent() {
+    let test_app = TestApp::spawn().await;
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+	.respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+	.mount(&server)
+	.await;
+    let (page_id, api_key) = test_app
+	.register_owned_page("https://example.com/")
+	.await;
+    create_webhook(&test_app, page_id, &api_key, NewWebhook {
+	url: hook_url(&server),
+	milestones: false,
+	spike: Some(Spike { hits: 1, minutes: 5 }),
+    }).await;
+
+    test_app.get_route(&format!("hit/{page_id}")).await;
+    received_requests(&server, 1).await;
+
+    // The receiver is still answering, but the delivery has
+    // been claimed and its row is free.
+    let claimed = sqlx::query!(
+	"SELECT next_attempt_at > now() AS \"claimed!\" FROM webhook_deliveries FOR UPDATE NOWAIT"
+    )
+	.fetch_one(&test_app.db)
+	.await
+	.expect("Delivery is locked");
+    assert!(claimed.claimed);
+}
+
+#[tokio::test]
+async fn webhooks_can_be_listed_and_deleted() {
+    let test_app = TestApp::spawn().await;
+    let (page_id, api_key) = test_app
+	.register_owned_page("https://example.com/")
+	.await;
+    let webhook = create_webhook(&test_app, page_id, &api_key, NewWebhook {
+	url: "https://hooks.example.org/jhm".parse().unwrap(),
+	milestones: true,
+	spike: None,
+    }).await;
+
+    let client = reqwest::Client::new();
+    let list_url = format!("{}/pages/{page_id}/webhooks", test_app.address);
+    let webhooks: Vec<Webhook> = client.get(&list_url)
+	.bearer_auth(&api_key)
+	.send()
+	.await
+	.unwrap()
+	.json()
+	.await
+	.unwrap();
+    assert_eq!(webhooks.len(), 1);
+    assert_eq!(webhooks[0].webhook_id, webhook.webhook_id);
+
+    let delete_url = format!("{list_url}/{}", webhook.webhook_id);
+    let response = client.delete(&delete_url)
+	.bearer_auth(&api_key)
+	.send()
+	.await
+	.unwrap();
+    assert_eq!(204, response.status().as_u16());
+    let response = client.delete(&delete_url)
+	.bearer_auth(&api_key)
+	.send()
+	.await
+	.unwrap();
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn create_webhook_rejects_invalid_requests() {
+    let test_app = TestApp::spawn().await;
+    let (page_id, api_key) = test_app
+	.register_owned_page("https://example.com/")
+	.await;
+    let other = test_app.post_owner().await;
+    let webhook = |milestones, spike| NewWebhook {
+	url: "https://hooks.example.org/jhm".parse().unwrap(),
+	milestones,
+	spike,
+    };
+
+    let response = test_app.post_webhook(page_id, &webhook(true, None), None).await;
+    assert_eq!(401, response.status().as_u16());
+    let response = test_app
+	.post_webhook(page_id, &webhook(true, None), Some(&other.api_key))
+	.await;
+    assert_eq!(404, response.status().as_u16());
+
+    let invalid = [
+	webhook(false, None),
+	webhook(false, Some(Spike { hits: 0, minutes: 5 })),
+	webhook(false, Some(Spike { hits: 10, minutes: 0 })),
+	NewWebhook { url: "ftp://example.org/".parse().unwrap(), ..webhook(true, None) },
+    ];
+    for webhook in invalid {
+	let response = test_app.post_webhook(page_id, &webhook, Some(&api_key)).await;
+	assert_eq!(400, response.status().as_u16(), "{webhook:?}");
+    }
+}
+
+#[tokio::test]
+async fn webhooks_to_private_addresses_are_rejected() {
+    let test_app = TestApp::spawn_with(|c| c.webhooks.allow_private_targets = false).await;
+    let (page_id, api_key) = test_app
+	.register_owned_page("https://example.com/")
+	.await;
+
+    for url in [
+	"http://127.0.0.1:8080/hook",
+	"http://localhost/hook",
+	"http://10.0.0.1/hook",
+	"http://169.254.169.254/latest/meta-data/",
+	"http://[::1]/hook",
+	"http://[::ffff:192.168.0.1]/hook",
+    ] {
+	let webhook = NewWebhook {
+	    url: url.parse().unwrap(),
+	    milestones: true,
+	    spike: None,
+	};
+	let response = test_app.post_webhook(page_id, &webhook, Some(&api_key)).await;
+	assert_eq!(400, response.status().as_u16(), "{url}");
+    }
+}
+
+#[tokio::test]
+async fn deliveries_to_private_addresses_are_dropped() {
+    let test_app = TestApp::spawn_with(|c| {
+	c.webhooks.allow_private_targets = false;
+	c.webhooks.max_attempts = 1;
+    }).await;
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+	.respond_with(ResponseTemplate::new(200))
+	.mount(&server)
+	.await;
+    let (page_id, _) = test_app
+	.register_owned_page("https://example.com/")
+	.await;
+    // E.g. a webhook created before the check.
+    let url = hook_url(&server);
+    sqlx::query!(
+	r#"
+INSERT INTO webhooks (webhook_id, page_id, url, secret, milestones, last_milestone, spike_hits, spike_minutes)
+VALUES ($1, $2, $3, 'secret', false, 0, 1, 5)"#,
+	Uuid::new_v4(),
+	page_id,
+	url.as_str(),
+    )
+	.execute(&test_app.db)
+	.await
+	.unwrap();
+
+    test_app.get_route(&format!("hit/{page_id}")).await;
+    for _ in 0..40 {
+	let queued = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM webhook_deliveries"#)
+	    .fetch_one(&test_app.db)
+	    .await
+	    .unwrap()
+	    .n;
+	let triggered = sqlx::query!(r#"SELECT last_spike_at FROM webhooks"#)
+	    .fetch_one(&test_app.db)
+	    .await
+	    .unwrap()
+	    .last_spike_at
+	    .is_some();
+	if triggered && queued == 0 {
+	    break;
+	}
+	tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    assert!(server.received_requests().await.unwrap().is_empty());
+}
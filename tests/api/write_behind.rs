@@ -0,0 +1,221 @@
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Instant;
+use crate::helper::TestApp;
+use sqlx::Executor;
+use uuid::Uuid;
+
+use jhm::routes::HitDimensions;
+use jhm::write_behind::{BufferedHit, CHUNK_SIZE};
+
+
+async fn spawn_write_behind() -> TestApp {
+    TestApp::spawn_with(|c| {
+	c.write_behind.enabled = true;
+	// Tests flush by hand.
+	c.write_behind.flush_interval_ms = 60 * 60 * 1000;
+    }).await
+}
+
+/// Hits of the page, and the number of raw hits and events.
+async fn counts_of(test_app: &TestApp, page_id: Uuid) -> (i32, i64) {
+    let rec = sqlx::query!(
+	r#"
+SELECT p.hits, (SELECT COUNT(*) FROM page_hits h WHERE h.page_id = p.page_id) AS "raw!"
+FROM pages p
+WHERE p.page_id = $1"#,
+	page_id,
+    )
+	.fetch_one(&test_app.db)
+	.await
+	.unwrap();
+    (rec.hits, rec.raw)
+}
+
+async fn flush(test_app: &TestApp) -> usize {
+    test_app.hit_buffer
+	.flush(&test_app.db, &test_app.redis())
+	.await
+	.expect("Failed to flush hits")
+}
+
+#[tokio::test]
+async fn buffered_hits_are_written_by_a_flush() {
+    let test_app = spawn_write_behind().await;
+    let page_id = test_app.register_page("https://example.com/").await;
+
+    let response = test_app.get_hit_with_referer(page_id, "https://news.example.org/").await;
+    assert!(response.status().is_success());
+    let response = test_app.get_route(&format!("hit/{page_id}/event/signup")).await;
+    assert!(response.status().is_success());
+    assert_eq!(counts_of(&test_app, page_id).await, (0, 0));
+
+    assert_eq!(flush(&test_app).await, 2);
+    assert_eq!(counts_of(&test_app, page_id).await, (1, 2));
+    let rollups = sqlx::query!(
+	r#"
+SELECT dimension, value, hits
+FROM hits_daily
+WHERE page_id = $1
+ORDER BY dimension"#,
+	page_id,
+    )
+	.fetch_all(&test_app.db)
+	.await
+	.unwrap()
+	.into_iter()
+	.map(|r| (r.dimension, r.value, r.hits))
+	.collect::<Vec<_>>();
+    assert_eq!(rollups, vec![
+	("event".to_string(), "signup".to_string(), 1),
+	("referrer".to_string(), "https://news.example.org/".to_string(), 1),
+	("total".to_string(), "".to_string(), 1),
+    ]);
+
+    // Written hits are gone from the buffer.
+    assert_eq!(flush(&test_app).await, 0);
+    assert_eq!(counts_of(&test_app, page_id).await, (1, 2));
+}
+
+#[tokio::test]
+async fn buffered_hits_of_deleted_pages_are_dropped() {
+    let test_app = spawn_write_behind().await;
+    let page_id = test_app.register_page("https://example.com/").await;
+
+    test_app.get_route(&format!("hit/{page_id}")).await;
+    sqlx::query!("DELETE FROM pages WHERE page_id = $1", page_id)
+	.execute(&test_app.db)
+	.await
+	.unwrap();
+
+    assert_eq!(flush(&test_app).await, 0);
+}
+
+#[tokio::test]
+async fn buffered_hits_are_flushed_on_shutdown() {
+    let test_app = spawn_write_behind().await;
+    let page_id = test_app.register_page("https://example.com/").await;
+
+    test_app.get_route(&format!("hit/{page_id}")).await;
+    let db = test_app.db.clone();
+    test_app.stop().await;
+
+    let hits = sqlx::query_scalar!("SELECT hits FROM pages WHERE page_id = $1", page_id)
+	.fetch_one(&db)
+	.await
+	.unwrap();
+    assert_eq!(hits, 1);
+}
+
+#[tokio::test]
+async fn a_failed_flush_is_retried() {
+    let test_app = spawn_write_behind().await;
+    let page_id = test_app.register_page("https://example.com/").await;
+    let other_page_id = test_app.register_page("https://example.com/other").await;
+
+    test_app.get_route(&format!("hit/{page_id}")).await;
+    test_app.db.execute("ALTER TABLE page_hits RENAME TO page_hits_gone").await.unwrap();
+    assert!(test_app.hit_buffer.flush(&test_app.db, &test_app.redis()).await.is_err());
+    test_app.db.execute("ALTER TABLE page_hits_gone RENAME TO page_hits").await.unwrap();
+    assert_eq!(counts_of(&test_app, page_id).await, (0, 0));
+
+    // The failed batch is written along with the hits that
+    // came in since, and only once.
+    test_app.get_route(&format!("hit/{other_page_id}")).await;
+    assert_eq!(flush(&test_app).await, 2);
+    assert_eq!(counts_of(&test_app, page_id).await, (1, 1));
+    assert_eq!(counts_of(&test_app, other_page_id).await, (1, 1));
+    assert_eq!(flush(&test_app).await, 0);
+}
+
+/// Count the updates of rows in `pages`.
+async fn count_page_updates(test_app: &TestApp) {
+    test_app.db.execute(r#"
+CREATE SEQUENCE page_updates;
+CREATE FUNCTION count_page_update() RETURNS trigger AS $$
+BEGIN
+    PERFORM nextval('page_updates');
+    RETURN NEW;
+END $$ LANGUAGE plpgsql;
+CREATE TRIGGER count_page_updates
+BEFORE UPDATE ON pages
+FOR EACH ROW EXECUTE FUNCTION count_page_update();"#)
+	.await
+	.unwrap();
+}
+
+async fn page_updates(db: &sqlx::PgPool) -> i64 {
+    // The sequence isn't part of the schema that queries are
+    // checked against.
+    sqlx::query_scalar("SELECT last_value FROM page_updates")
+	.fetch_one(db)
+	.await
+	.unwrap()
+}
+
+/// Hit the page from different addresses, all at once. Each
+/// one counts as a new visitor.
+async fn hit_from_visitors(test_app: &TestApp, page_id: Uuid, visitors: u8) {
+    let hits = (1..=visitors).map(|i| {
+	let client = reqwest::Client::builder()
+	    .local_address(IpAddr::V4(Ipv4Addr::new(127, 0, 0, i)))
+	    .build()
+	    .unwrap();
+	let url = format!("http://127.0.0.1:{}/hit/{page_id}", test_app.port);
+	async move {
+	    let response = client.get(url).send().await.expect("Failed to execute request");
+	    assert!(response.status().is_success());
+	}
+    });
+    futures_util::future::join_all(hits).await;
+}
+
+#[tokio::test]
+#[ignore = "load test, needs the whole of 127.0.0.0/8 on loopback"]
+async fn write_behind_takes_load_off_busy_pages() {
+    const VISITORS: u8 = 250;
+    let mut updates = vec![];
+    let mut timings = vec![];
+    for write_behind in [false, true] {
+	let test_app = TestApp::spawn_with(|c| {
+	    c.write_behind.enabled = write_behind;
+	    c.write_behind.flush_interval_ms = 60 * 60 * 1000;
+	}).await;
+	let page_id = test_app.register_page("https://example.com/").await;
+	count_page_updates(&test_app).await;
+
+	let start = Instant::now();
+	hit_from_visitors(&test_app, page_id, VISITORS).await;
+	let elapsed = start.elapsed();
+	let db = test_app.db.clone();
+	test_app.stop().await;
+
+	let hits = sqlx::query_scalar!("SELECT hits FROM pages WHERE page_id = $1", page_id)
+	    .fetch_one(&db)
+	    .await
+	    .unwrap();
+	assert_eq!(hits, i32::from(VISITORS));
+	updates.push(page_updates(&db).await);
+	timings.push(elapsed);
+    }
+
+    // One update per hit, against one per flush.
+    assert_eq!(updates, vec![i64::from(VISITORS), 1], "{VISITORS} hits took {timings:?}");
+}
+
+#[tokio::test]
+async fn long_backlogs_are_flushed_in_chunks() {
+    let test_app = spawn_write_behind().await;
+    let page_id = test_app.register_page("https://example.com/").await;
+    let n = 2 * CHUNK_SIZE + 1;
+    let hit = BufferedHit::now(page_id, None, &HitDimensions::default());
+    for _ in 0..n {
+	test_app.hit_buffer.push(&test_app.redis(), &hit).unwrap();
+    }
+    count_page_updates(&test_app).await;
+
+    assert_eq!(flush(&test_app).await, n);
+    assert_eq!(counts_of(&test_app, page_id).await, (n as i32, n as i64));
+    // One update of the page per chunk.
+    assert_eq!(page_updates(&test_app.db).await, 3);
+    assert_eq!(flush(&test_app).await, 0);
+}